@@ -0,0 +1,139 @@
+use crate::api::Downloader;
+use crate::download::DownloadResult;
+use crate::io::InputEntry;
+use crate::output::events::report_progress;
+use crate::output::{Event, EventLog, RunSummary};
+use futures_util::Stream;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Downloads running in the background, as a stream of their events:
+/// requests, redirects, retries, `Progress` every progress interval,
+/// `Completed` or `Failed` for each URL, and a last `Summary`.
+///
+/// The downloads go on whether or not the stream is read; the results are
+/// available from [`results`](Batch::results) once they are over.
+pub struct Batch {
+    events: mpsc::UnboundedReceiver<Event>,
+    task: JoinHandle<Vec<DownloadResult>>,
+    /// Set once the summary has been handed out
+    finished: bool,
+}
+
+impl Batch {
+    pub(crate) fn start(downloader: &Downloader, entries: Vec<InputEntry>) -> Self {
+        let (events, receiver) = EventLog::channel();
+        let options = downloader.run_options(events);
+        let manager = downloader.manager(&options.jobs);
+        let output_dir = downloader.target_dir().map(Path::to_path_buf);
+        let interval = downloader.progress_interval();
+
+        let task = tokio::spawn(async move {
+            let started = Instant::now();
+            let jobs = options.jobs.clone();
+            let progress = report_progress(jobs.clone(), interval);
+            let results = manager
+                .download_urls_silent(entries, output_dir.as_deref())
+                .await;
+            if let Some(progress) = progress {
+                progress.abort();
+            }
+            jobs.events()
+                .emit(Event::Summary(RunSummary::new(&jobs, started.elapsed())));
+            results
+        });
+
+        Self {
+            events: receiver,
+            task,
+            finished: false,
+        }
+    }
+
+    /// Waits for every download and returns the results in input order
+    pub async fn results(self) -> Vec<DownloadResult> {
+        match self.task.await {
+            Ok(results) => results,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+impl Stream for Batch {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let event = std::task::ready!(self.events.poll_recv(cx));
+        if matches!(event, None | Some(Event::Summary(_))) {
+            self.finished = true;
+        }
+        Poll::Ready(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_batch_streams_events_and_ends_with_summary() {
+        // "/ok" answers with three bytes, anything else with a 404
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                let response = if buf.starts_with(b"GET /ok ") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("wget-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let downloader = Downloader::builder()
+            .with_output_dir(&dir)
+            .with_tries(1)
+            .build()
+            .unwrap();
+        let mut batch = downloader.download_all([
+            format!("http://{}/ok", addr),
+            format!("http://{}/missing", addr),
+        ]);
+
+        let mut events = Vec::new();
+        while let Some(event) = batch.next().await {
+            events.push(event);
+        }
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, Event::Completed { bytes: 3, .. }))
+        );
+        assert!(events.iter().any(|e| matches!(e, Event::Failed { .. })));
+        let Some(Event::Summary(summary)) = events.last() else {
+            panic!("batch did not end with a summary");
+        };
+        assert_eq!((summary.files, summary.failed), (1, 1));
+
+        let results = batch.results().await;
+        assert!(results[0].success && !results[1].success);
+        assert_eq!(std::fs::read(dir.join("ok")).unwrap(), b"abc");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
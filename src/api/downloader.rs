@@ -0,0 +1,329 @@
+use crate::api::{Batch, Mirror};
+use crate::checksum::{Checksum, ChecksumPolicy};
+use crate::control::Jobs;
+use crate::download::{ConcurrentDownloadManager, DownloadOptions, DownloadResult, Quota};
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::http::{Compression, CompressionConfig, HttpClient, ProxyConfig, Timeouts, TlsConfig};
+use crate::io::InputEntry;
+use crate::output::EventLog;
+use crate::rate::{RateLimits, RateSchedule};
+use crate::utils::url::extract_filename;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Downloads files into one directory without printing anything. Build one
+/// with [`Downloader::builder`]; clones share the quota, rate limits and
+/// HTTP connections.
+#[derive(Clone)]
+pub struct Downloader {
+    options: DownloadOptions,
+    output_dir: PathBuf,
+    progress_interval: Duration,
+    /// Built once with the HTTP client; each run gets a copy with its own
+    /// registry
+    manager: ConcurrentDownloadManager,
+    /// Registry every run reports to instead of one of its own: that of
+    /// the command line, which signals and `wget ctl` act on
+    jobs: Option<Jobs>,
+    /// Print status lines and progress bars, as the command line does.
+    /// Never set for library users.
+    messages: bool,
+}
+
+impl fmt::Debug for Downloader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Downloader")
+            .field("options", &self.options)
+            .field("output_dir", &self.output_dir)
+            .field("progress_interval", &self.progress_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Downloader {
+    pub fn builder() -> DownloaderBuilder {
+        DownloaderBuilder::new()
+    }
+
+    /// The downloader behind the `wget` command line: its runs report to
+    /// `options.jobs`, and print unless `quiet`. Files go to `output_dir`
+    /// (`-P`), or the current directory under their bare names.
+    pub(crate) fn for_command_line(
+        options: DownloadOptions,
+        output_dir: Option<&Path>,
+        quiet: bool,
+    ) -> Result<Self, DownloadError> {
+        Ok(Self {
+            manager: ConcurrentDownloadManager::new(&options)?,
+            jobs: Some(options.jobs.clone()),
+            options,
+            output_dir: output_dir.map(Path::to_path_buf).unwrap_or_default(),
+            progress_interval: Duration::from_secs(1),
+            messages: !quiet,
+        })
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Downloads `url` into the output directory, named after its last
+    /// path segment
+    pub async fn download(&self, url: &str) -> DownloadResult {
+        self.download_entry(InputEntry {
+            url: url.to_string(),
+            checksum: None,
+        })
+        .await
+    }
+
+    /// Like [`download`](Self::download), then checks the file against
+    /// `checksum` and handles a mismatch by the checksum policy
+    pub async fn download_verified(&self, url: &str, checksum: Checksum) -> DownloadResult {
+        self.download_entry(InputEntry {
+            url: url.to_string(),
+            checksum: Some(checksum),
+        })
+        .await
+    }
+
+    /// Starts downloading `urls`, `max_concurrent` at a time. The returned
+    /// [`Batch`] streams their events and ends with the run summary.
+    pub fn download_all<I, S>(&self, urls: I) -> Batch
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let entries = urls
+            .into_iter()
+            .map(|url| InputEntry {
+                url: url.into(),
+                checksum: None,
+            })
+            .collect();
+        Batch::start(self, entries)
+    }
+
+    /// A crawl of the site at `url`, saved under the output directory
+    pub fn mirror(&self, url: &str) -> Mirror {
+        Mirror::new(self.clone(), url)
+    }
+
+    async fn download_entry(&self, entry: InputEntry) -> DownloadResult {
+        let url = entry.url.clone();
+        self.download_entries(vec![entry])
+            .await
+            .pop()
+            .unwrap_or_else(|| DownloadResult {
+                file_path: self.output_dir.join(extract_filename(&url)),
+                url,
+                bytes_downloaded: 0,
+                success: false,
+                error: Some(DownloadError {
+                    kind: ErrorKind::Interrupted,
+                    message: "Download did not run".to_string(),
+                }),
+            })
+    }
+
+    /// Downloads `entries`, `max_concurrent` at a time, and returns the
+    /// results in input order
+    pub(crate) async fn download_entries(&self, entries: Vec<InputEntry>) -> Vec<DownloadResult> {
+        let manager = self.manager(&self.run_options(EventLog::default()).jobs);
+        if self.messages {
+            manager.download_urls(entries, self.target_dir()).await
+        } else {
+            manager.download_urls_silent(entries, self.target_dir()).await
+        }
+    }
+
+    /// The download manager for a run registered in `jobs`
+    pub(crate) fn manager(&self, jobs: &Jobs) -> ConcurrentDownloadManager {
+        self.manager.with_jobs(jobs.clone())
+    }
+
+    /// The HTTP client shared by every download of the downloader
+    pub(crate) fn client(&self) -> &HttpClient {
+        self.manager.client()
+    }
+
+    /// Options for one run, reporting its downloads to `events`. Each run
+    /// gets its own registry, so a summary covers that run only; on the
+    /// command line they all share its registry.
+    pub(crate) fn run_options(&self, events: EventLog) -> DownloadOptions {
+        DownloadOptions {
+            jobs: self.jobs.clone().unwrap_or_else(|| Jobs::new(events)),
+            ..self.options.clone()
+        }
+    }
+
+    /// The output directory, or `None` for bare file names
+    pub(crate) fn target_dir(&self) -> Option<&Path> {
+        Some(self.output_dir.as_path()).filter(|dir| !dir.as_os_str().is_empty())
+    }
+
+    pub(crate) fn messages(&self) -> bool {
+        self.messages
+    }
+
+    pub(crate) fn progress_interval(&self) -> Duration {
+        self.progress_interval
+    }
+}
+
+/// Typed settings for a [`Downloader`]. Everything starts at wget's
+/// defaults: 3 tries, a 900 second read timeout, no rate limit, 4 downloads
+/// at a time into the current directory.
+#[derive(Debug)]
+pub struct DownloaderBuilder {
+    options: DownloadOptions,
+    output_dir: PathBuf,
+    progress_interval: Duration,
+    rate_limit: Option<RateSchedule>,
+    rate_limit_per_host: Option<RateSchedule>,
+    rate_limit_per_download: Option<RateSchedule>,
+}
+
+impl Default for DownloaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DownloaderBuilder {
+    pub fn new() -> Self {
+        Self {
+            options: DownloadOptions::default(),
+            output_dir: PathBuf::from("."),
+            progress_interval: Duration::from_secs(1),
+            rate_limit: None,
+            rate_limit_per_host: None,
+            rate_limit_per_download: None,
+        }
+    }
+
+    pub fn with_output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.options.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Attempts per request, including the first
+    pub fn with_tries(mut self, tries: u32) -> Self {
+        self.options.tries = tries;
+        self
+    }
+
+    /// Wait before the first retry; later retries wait longer
+    pub fn with_wait_retry(mut self, wait: Duration) -> Self {
+        self.options.wait_retry = wait.as_secs();
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+
+    /// Combined rate of every download, e.g. `RateSchedule::constant(200 * 1024)`
+    pub fn with_rate_limit(mut self, rate: RateSchedule) -> Self {
+        self.rate_limit = Some(rate);
+        self
+    }
+
+    pub fn with_rate_limit_per_host(mut self, rate: RateSchedule) -> Self {
+        self.rate_limit_per_host = Some(rate);
+        self
+    }
+
+    pub fn with_rate_limit_per_download(mut self, rate: RateSchedule) -> Self {
+        self.rate_limit_per_download = Some(rate);
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.options.proxy = proxy;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.options.tls = tls;
+        self
+    }
+
+    pub fn with_compression(mut self, mode: Compression, keep_encoded: bool) -> Self {
+        self.options.compression = CompressionConfig { mode, keep_encoded };
+        self
+    }
+
+    /// Continue partial files instead of starting over
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.options.resume = resume;
+        self
+    }
+
+    /// Parallel range requests per file
+    pub fn with_segments(mut self, segments: usize) -> Self {
+        self.options.segments = segments.max(1);
+        self
+    }
+
+    pub fn with_checksum_policy(mut self, policy: ChecksumPolicy) -> Self {
+        self.options.checksum_policy = policy;
+        self
+    }
+
+    /// Files downloaded at the same time by a [`Batch`]
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.options.max_concurrent = max_concurrent.max(1);
+        self
+    }
+
+    pub fn with_max_per_host(mut self, max_per_host: usize) -> Self {
+        self.options.max_per_host = Some(max_per_host.max(1));
+        self
+    }
+
+    /// Stop starting downloads once this many bytes are downloaded, across
+    /// every run of the downloader
+    pub fn with_quota(mut self, bytes: u64) -> Self {
+        self.options.quota = Quota::new(Some(bytes));
+        self
+    }
+
+    /// Skip files larger than this many bytes
+    pub fn with_max_filesize(mut self, bytes: u64) -> Self {
+        self.options.max_filesize = Some(bytes);
+        self
+    }
+
+    /// How often a [`Batch`] reports the progress of running downloads
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Checks the proxy and TLS settings by building the HTTP client every
+    /// download of the downloader shares
+    pub fn build(mut self) -> Result<Downloader, DownloadError> {
+        self.options.rate_limits = RateLimits::from_schedules(
+            self.rate_limit,
+            self.rate_limit_per_host,
+            self.rate_limit_per_download,
+        );
+        let manager = ConcurrentDownloadManager::new(&self.options)?;
+        Ok(Downloader {
+            options: self.options,
+            output_dir: self.output_dir,
+            progress_interval: self.progress_interval,
+            manager,
+            jobs: None,
+            messages: false,
+        })
+    }
+}
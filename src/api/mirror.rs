@@ -0,0 +1,85 @@
+use crate::api::Downloader;
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::mirror::MirrorCrawler;
+use crate::output::{EventLog, RunSummary};
+use std::time::Instant;
+
+/// A crawl of one site, following links within it and saving every page
+/// under the downloader's output directory (`host/path`). Get one from
+/// [`Downloader::mirror`].
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    downloader: Downloader,
+    url: String,
+    reject_suffixes: Option<String>,
+    exclude_dirs: Option<String>,
+}
+
+impl Mirror {
+    pub(crate) fn new(downloader: Downloader, url: &str) -> Self {
+        Self {
+            downloader,
+            url: url.to_string(),
+            reject_suffixes: None,
+            exclude_dirs: None,
+        }
+    }
+
+    /// Skip files ending in any of `suffixes`, e.g. `["jpg", "gif"]`
+    pub fn with_reject<I, S>(mut self, suffixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.reject_suffixes = Some(join(suffixes));
+        self
+    }
+
+    /// Skip paths under any of `dirs`, e.g. `["/tmp", "/cache"]`
+    pub fn with_exclude<I, S>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exclude_dirs = Some(join(dirs));
+        self
+    }
+
+    /// Crawls the site. Pages that fail do not stop the crawl; they are
+    /// listed in the summary's failures.
+    pub async fn run(self) -> Result<RunSummary, DownloadError> {
+        let started = Instant::now();
+        let options = self.downloader.run_options(EventLog::default());
+        let client = self.downloader.client().clone().with_jobs(options.jobs.clone());
+        let mut crawler =
+            MirrorCrawler::new(&self.url, self.downloader.target_dir(), client, &options)
+                .map_err(|e| DownloadError {
+                    kind: ErrorKind::Input,
+                    message: format!("Cannot mirror {}: {}", self.url, e),
+                })?;
+        let crawled = if self.downloader.messages() {
+            crawler.mirror(&self.reject_suffixes, &self.exclude_dirs).await
+        } else {
+            crawler
+                .mirror_silent(&self.reject_suffixes, &self.exclude_dirs)
+                .await
+        };
+        crawled.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
+            message: e.to_string(),
+        })?;
+        Ok(RunSummary::new(&options.jobs, started.elapsed()))
+    }
+}
+
+fn join<I, S>(items: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    items
+        .into_iter()
+        .map(|item| item.as_ref().to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
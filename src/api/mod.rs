@@ -0,0 +1,7 @@
+pub mod batch;
+pub mod downloader;
+pub mod mirror;
+
+pub use batch::Batch;
+pub use downloader::{Downloader, DownloaderBuilder};
+pub use mirror::Mirror;
@@ -0,0 +1,145 @@
+use crate::cli::Cli;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Set for the detached copy of wget to the log it writes to. Its presence
+/// tells the re-executed process that it already runs in the background.
+const LOG_ENV: &str = "WGET_BACKGROUND_LOG";
+const DEFAULT_LOG: &str = "wget-log";
+
+/// Log of the detached process; `None` in the process started from the shell
+pub fn child_log() -> Option<PathBuf> {
+    std::env::var_os(LOG_ENV).map(PathBuf::from)
+}
+
+/// Where `-B` writes: `-o` or `-a` if given, otherwise the first free name
+/// of `wget-log`, `wget-log.1`, `wget-log.2`, ...
+pub fn log_path(args: &Cli) -> PathBuf {
+    args.output_file
+        .clone()
+        .or_else(|| args.append_output.clone())
+        .unwrap_or_else(default_log_path)
+}
+
+/// The first free name of `wget-log`, `wget-log.1`, ...
+pub fn default_log_path() -> PathBuf {
+    first_free(Path::new(DEFAULT_LOG))
+}
+
+fn first_free(base: &Path) -> PathBuf {
+    (0..)
+        .map(|n| match n {
+            0 => base.to_path_buf(),
+            n => PathBuf::from(format!("{}.{}", base.display(), n)),
+        })
+        .find(|path| !path.exists())
+        .expect("some log name is free")
+}
+
+/// `--pid-file`, by default next to the log so that several background
+/// runs in one directory do not share it
+pub fn pid_path(args: &Cli, log: &Path) -> PathBuf {
+    args.pid_file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.pid", log.display())))
+}
+
+/// `--control-socket`, by default next to the log like the PID file
+pub fn socket_path(args: &Cli, log: &Path) -> PathBuf {
+    args.control_socket
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.sock", log.display())))
+}
+
+/// Starts this command again as a detached process in its own session, with
+/// stdin from /dev/null and stdout and stderr going to the log. Returns the
+/// child's process id and the log path.
+pub fn spawn(args: &Cli) -> io::Result<(u32, PathBuf)> {
+    let log = log_path(args);
+    let file = open_log(&log, args.append_output.is_some())?;
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(LOG_ENV, &log)
+        .stdin(Stdio::null())
+        .stdout(file.try_clone()?)
+        .stderr(file);
+    // A new session has no controlling terminal, so closing the terminal
+    // or pressing Ctrl-C in it no longer reaches the download
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn()?;
+    Ok((child.id(), log))
+}
+
+/// Sends the messages of a foreground run to `-o`/`-a` instead of the
+/// terminal. With `-O -` stdout carries the data and stays untouched.
+pub fn redirect_output(path: &Path, append: bool, keep_stdout: bool) -> io::Result<()> {
+    let file = open_log(path, append)?;
+    let targets: &[i32] = if keep_stdout {
+        &[libc::STDERR_FILENO]
+    } else {
+        &[libc::STDOUT_FILENO, libc::STDERR_FILENO]
+    };
+    for &fd in targets {
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Opens a log for appending, emptying it first unless `append` (`-a`)
+fn open_log(path: &Path, append: bool) -> io::Result<File> {
+    if !append {
+        File::create(path)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Process id file of a background run, removed when the run ends
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        std::fs::write(&path, format!("{}\n", std::process::id()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_existing_logs_are_numbered() {
+        let dir = std::env::temp_dir().join(format!("wget-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("wget-log");
+        assert_eq!(first_free(&base), base);
+
+        std::fs::write(&base, "").unwrap();
+        std::fs::write(dir.join("wget-log.1"), "").unwrap();
+        assert_eq!(first_free(&base), dir.join("wget-log.2"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1 @@
-pub mod logger;
-pub mod process;
-
-pub use logger::BackgroundLogger;
-pub use process::BackgroundProcessor;
\ No newline at end of file
+pub mod daemon;
@@ -0,0 +1,8 @@
+use wget::cli;
+
+/// `wget-ctl ...` is an alias of `wget ctl ...` that can never be taken for
+/// a download of a URL named `ctl`.
+#[tokio::main]
+async fn main() {
+    std::process::exit(cli::ctl_main().await);
+}
@@ -0,0 +1,272 @@
+use crate::http::client::{DownloadError, ErrorKind};
+use openssl::hash::{Hasher, MessageDigest};
+use std::fmt;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// Hash functions accepted by `--checksum` and checksum files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Some(Self::Md5),
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            "blake3" | "b3" => Some(Self::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// Digest length in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 | Self::Blake3 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+/// An expected digest, written `algorithm:hex` on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Parses `sha256:<hex>` and the like
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (name, hex) = value
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("Checksum {:?} must look like sha256:<hex>", value))?;
+        let algorithm = ChecksumAlgorithm::parse(name).ok_or_else(|| {
+            format!(
+                "Unknown checksum algorithm {:?} (use md5, sha1, sha256, sha512 or blake3)",
+                name
+            )
+        })?;
+        Self::from_hex(algorithm, hex)
+    }
+
+    pub fn from_hex(algorithm: ChecksumAlgorithm, hex: &str) -> Result<Self, String> {
+        let hex = hex.trim();
+        let digest = decode_hex(hex)
+            .filter(|digest| digest.len() == algorithm.digest_len())
+            .ok_or_else(|| {
+                format!(
+                    "{:?} is not a valid {} digest ({} hex digits expected)",
+                    hex,
+                    algorithm.name(),
+                    algorithm.digest_len() * 2
+                )
+            })?;
+        Ok(Self { algorithm, digest })
+    }
+
+    /// Compares the digest of a finished download, named `name` in the
+    /// error, with this one. Nothing on disk is touched.
+    pub fn compare(&self, actual: &[u8], name: &str) -> Result<(), DownloadError> {
+        if actual == self.digest.as_slice() {
+            return Ok(());
+        }
+        Err(DownloadError {
+            kind: ErrorKind::Checksum,
+            message: format!(
+                "Checksum mismatch for {}: expected {}, got {}:{}",
+                name,
+                self,
+                self.algorithm.name(),
+                encode_hex(actual)
+            ),
+        })
+    }
+
+    /// `compare` for a download saved to `file_path`. On a mismatch the
+    /// file is kept or deleted according to `policy`.
+    pub async fn verify(
+        &self,
+        actual: &[u8],
+        file_path: &Path,
+        policy: ChecksumPolicy,
+    ) -> Result<(), DownloadError> {
+        let Err(mut e) = self.compare(actual, &file_path.display().to_string()) else {
+            return Ok(());
+        };
+        let fate = match policy {
+            ChecksumPolicy::Keep => "kept",
+            ChecksumPolicy::Delete => {
+                let _ = tokio::fs::remove_file(file_path).await;
+                "deleted"
+            }
+        };
+        e.message.push_str(&format!(" (file {})", fate));
+        Err(e)
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), encode_hex(&self.digest))
+    }
+}
+
+/// What happens to a downloaded file whose checksum does not match
+/// (`--checksum-mismatch`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChecksumPolicy {
+    /// Leave the file in place for inspection
+    Keep,
+    /// Remove the file
+    #[default]
+    Delete,
+}
+
+/// Incremental hasher fed from the download loop
+pub struct StreamHasher {
+    algorithm: ChecksumAlgorithm,
+    state: HasherState,
+}
+
+enum HasherState {
+    OpenSsl(Hasher),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl StreamHasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let digest = match algorithm {
+            ChecksumAlgorithm::Md5 => MessageDigest::md5(),
+            ChecksumAlgorithm::Sha1 => MessageDigest::sha1(),
+            ChecksumAlgorithm::Sha256 => MessageDigest::sha256(),
+            ChecksumAlgorithm::Sha512 => MessageDigest::sha512(),
+            ChecksumAlgorithm::Blake3 => {
+                return Self {
+                    algorithm,
+                    state: HasherState::Blake3(Box::default()),
+                };
+            }
+        };
+        Self {
+            algorithm,
+            state: HasherState::OpenSsl(
+                Hasher::new(digest).expect("digest is supported by OpenSSL"),
+            ),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            HasherState::OpenSsl(hasher) => {
+                hasher.update(data).expect("hashing in memory cannot fail")
+            }
+            HasherState::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Feeds the bytes already on disk, e.g. the part of a resumed download
+    /// fetched by an earlier run
+    pub async fn update_from_file(&mut self, path: &Path) -> Result<(), DownloadError> {
+        let read_error = |e: std::io::Error| DownloadError {
+            kind: ErrorKind::Io,
+            message: format!("Failed to read {} for hashing: {}", path.display(), e),
+        };
+        let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).await.map_err(read_error)?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.update(&buf[..n]);
+        }
+    }
+
+    /// The digest of everything fed in
+    pub fn finish(self) -> Checksum {
+        let digest = match self.state {
+            HasherState::OpenSsl(mut hasher) => hasher
+                .finish()
+                .expect("hashing in memory cannot fail")
+                .to_vec(),
+            HasherState::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        Checksum {
+            algorithm: self.algorithm,
+            digest,
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let cases = [
+            (
+                "md5:900150983cd24fb0d6963f7d28e17f72",
+                ChecksumAlgorithm::Md5,
+            ),
+            (
+                "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                ChecksumAlgorithm::Sha256,
+            ),
+            (
+                "blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+                ChecksumAlgorithm::Blake3,
+            ),
+        ];
+        for (expected, algorithm) in cases {
+            let expected = Checksum::parse(expected).unwrap();
+            assert_eq!(expected.algorithm, algorithm);
+
+            let mut hasher = StreamHasher::new(algorithm);
+            hasher.update(b"a");
+            hasher.update(b"bc");
+            assert_eq!(hasher.finish(), expected);
+        }
+
+        assert!(Checksum::parse("sha256:abcd").is_err());
+        assert!(Checksum::parse("crc32:00000000").is_err());
+        assert!(Checksum::parse("900150983cd24fb0d6963f7d28e17f72").is_err());
+    }
+}
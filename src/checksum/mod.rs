@@ -0,0 +1,5 @@
+pub mod digest;
+pub mod sums;
+
+pub use digest::{Checksum, ChecksumAlgorithm, ChecksumPolicy, StreamHasher};
+pub use sums::ChecksumFile;
@@ -0,0 +1,135 @@
+use crate::checksum::digest::{Checksum, ChecksumAlgorithm};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Expected digests by file name, read from a `--checksum-file` such as
+/// `SHA256SUMS`.
+///
+/// Both the GNU coreutils layout (`<hex>  name`, `<hex> *name`) and the BSD
+/// tagged layout (`SHA256 (name) = <hex>`) are accepted. For untagged lines
+/// the algorithm comes from the file name (`SHA1SUMS`, `B3SUMS`, ...) and
+/// otherwise from the digest length.
+#[derive(Debug, Default)]
+pub struct ChecksumFile {
+    entries: HashMap<String, Checksum>,
+}
+
+impl ChecksumFile {
+    pub async fn load(path: &Path) -> Result<Self, String> {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read checksum file {:?}: {}", path, e))?;
+        let hint = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .and_then(|name| algorithm_from_file_name(&name));
+        Self::parse(&text, hint).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, hint: Option<ChecksumAlgorithm>) -> Result<Self, String> {
+        let mut entries = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, checksum) =
+                parse_line(line, hint).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            entries.insert(name, checksum);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Looks up the expected digest for a downloaded file's base name
+    pub fn lookup(&self, file_name: &str) -> Option<&Checksum> {
+        self.entries.get(file_name).or_else(|| {
+            // Entries may carry a directory, e.g. `./dist/app.tar.gz`
+            self.entries
+                .iter()
+                .find(|(name, _)| Path::new(name).file_name().is_some_and(|n| n == file_name))
+                .map(|(_, checksum)| checksum)
+        })
+    }
+}
+
+fn parse_line(line: &str, hint: Option<ChecksumAlgorithm>) -> Result<(String, Checksum), String> {
+    // BSD style: `SHA256 (name) = hex`
+    if let Some((tag, rest)) = line.split_once(" (")
+        && let Some((name, hex)) = rest.rsplit_once(") = ")
+    {
+        let algorithm = ChecksumAlgorithm::parse(tag)
+            .ok_or_else(|| format!("unknown checksum algorithm {:?}", tag))?;
+        return Ok((name.to_string(), Checksum::from_hex(algorithm, hex)?));
+    }
+
+    // GNU style: `hex  name` or `hex *name` (binary mode)
+    let (hex, name) = line
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("expected \"<hex>  <file name>\", got {:?}", line))?;
+    let name = name.trim_start();
+    let name = name.strip_prefix('*').unwrap_or(name);
+    let algorithm = match hint {
+        Some(algorithm) => algorithm,
+        None => match hex.len() / 2 {
+            16 => ChecksumAlgorithm::Md5,
+            20 => ChecksumAlgorithm::Sha1,
+            32 => ChecksumAlgorithm::Sha256,
+            64 => ChecksumAlgorithm::Sha512,
+            _ => return Err(format!("cannot tell the algorithm of {:?}", hex)),
+        },
+    };
+    Ok((name.to_string(), Checksum::from_hex(algorithm, hex)?))
+}
+
+fn algorithm_from_file_name(name: &str) -> Option<ChecksumAlgorithm> {
+    [
+        ("md5", ChecksumAlgorithm::Md5),
+        ("sha1", ChecksumAlgorithm::Sha1),
+        ("sha256", ChecksumAlgorithm::Sha256),
+        ("sha512", ChecksumAlgorithm::Sha512),
+        ("b3", ChecksumAlgorithm::Blake3),
+        ("blake3", ChecksumAlgorithm::Blake3),
+    ]
+    .into_iter()
+    .find(|(prefix, _)| name.starts_with(prefix))
+    .map(|(_, algorithm)| algorithm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gnu_and_bsd_lines() {
+        let text = "\
+# release checksums
+ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  app.tar.gz
+900150983cd24fb0d6963f7d28e17f72 *./dist/app.zip
+SHA1 (notes.txt) = a9993e364706816aba3e25717850c26c9cd0d89d
+";
+        let sums = ChecksumFile::parse(text, None).unwrap();
+        assert_eq!(
+            sums.lookup("app.tar.gz").unwrap().algorithm,
+            ChecksumAlgorithm::Sha256
+        );
+        assert_eq!(
+            sums.lookup("app.zip").unwrap().algorithm,
+            ChecksumAlgorithm::Md5
+        );
+        assert_eq!(
+            sums.lookup("notes.txt").unwrap().algorithm,
+            ChecksumAlgorithm::Sha1
+        );
+        assert!(sums.lookup("missing.bin").is_none());
+
+        let b3 = ChecksumFile::parse(
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85  abc.txt",
+            algorithm_from_file_name("b3sums"),
+        )
+        .unwrap();
+        assert_eq!(
+            b3.lookup("abc.txt").unwrap().algorithm,
+            ChecksumAlgorithm::Blake3
+        );
+    }
+}
@@ -1,18 +1,25 @@
-use clap::Parser;
-use std::path::PathBuf;
+use crate::checksum::{Checksum, ChecksumFile, ChecksumPolicy};
+use crate::http::tls::{CertificateType, parse_pinned_pubkeys, parse_secure_protocol};
+use crate::http::{Compression, CompressionConfig, HeaderOutput, ProxyConfig, Timeouts, TlsConfig};
+use crate::output::{LogFormat, ProgressDisplay, ProgressMode};
+use crate::rate::RateSchedule;
+use crate::utils::size::parse_size;
+use clap::{Arg, ArgAction, Command, CommandFactory, FromArgMatches, Parser};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug, Clone)]
-#[command(name = "wget", about = "A simple wget clone", version = "0.1.0")]
+#[command(name = "wget", about = "A simple wget clone", version = "0.1.0", args_override_self = true)]
 pub struct Cli {
     /// URL(s) to download
     #[arg(help = "URL(s) to download")]
     pub urls: Vec<String>,
 
-    /// Save file as a specific name (-O)
+    /// Save file as a specific name (-O); `-` writes to stdout
     #[arg(
         short = 'O',
         long,
-        help = "Write documents to file",
+        help = "Write documents to file ('-' for stdout); several URLs are concatenated",
         conflicts_with = "directory_prefix"
     )]
     pub output: Option<PathBuf>,
@@ -26,26 +33,92 @@ pub struct Cli {
     )]
     pub directory_prefix: Option<PathBuf>,
 
+    /// Startup file read instead of /etc/wgetrc and ~/.wgetrc (--config)
+    #[arg(long, value_name = "FILE", help = "Read settings from FILE instead of /etc/wgetrc and ~/.wgetrc")]
+    pub config: Option<PathBuf>,
+
+    /// Skip the startup files (--no-config)
+    #[arg(long, conflicts_with = "config", help = "Don't read any wgetrc file")]
+    pub no_config: bool,
+
+    /// wgetrc commands applied after the startup files (-e)
+    #[arg(short = 'e', long, value_name = "COMMAND", help = "Apply a wgetrc setting, e.g. -e 'tries = 5' (can be repeated)")]
+    pub execute: Vec<String>,
+
     /// Download in background (-B)
     #[arg(
         short = 'B',
         long,
-        help = "Download in background (output to wget-log)"
+        help = "Go to background after startup (output to wget-log, wget-log.1, ...)"
     )]
     pub background: bool,
 
+    /// Log messages to a file, replacing it (-o)
+    #[arg(short = 'o', long, value_name = "LOGFILE", help = "Log messages to LOGFILE")]
+    pub output_file: Option<PathBuf>,
+
+    /// Log messages to a file, keeping what it holds (-a)
+    #[arg(
+        short = 'a',
+        long,
+        value_name = "LOGFILE",
+        conflicts_with = "output_file",
+        help = "Append messages to LOGFILE"
+    )]
+    pub append_output: Option<PathBuf>,
+
+    /// Where -B records the process id (--pid-file)
+    #[arg(long, value_name = "FILE", requires = "background", help = "Write the background process id to FILE (default: <logfile>.pid)")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Message format (--log-format)
+    #[arg(long, value_enum, default_value = "text", help = "Write messages as text or as one JSON event per line (json)")]
+    pub log_format: LogFormat,
+
+    /// Summary of every download, written when the run ends (--report)
+    #[arg(long, value_name = "FILE", help = "Write the outcome of every download to FILE (CSV if it ends in .csv, otherwise JSON)")]
+    pub report: Option<PathBuf>,
+
+    /// Socket `wget ctl` uses to reach the -B session (--control-socket)
+    #[arg(long, value_name = "PATH", requires = "background", help = "Listen for wget ctl on PATH (default: <logfile>.sock)")]
+    pub control_socket: Option<PathBuf>,
+
     /// Limit download rate (--rate-limit=200k/2M)
-    #[arg(long, help = "Limit download rate (e.g., 200k, 2M)")]
+    #[arg(long, help = "Limit download rate (e.g., 200k, 1.5M, 2MB or 08:00-18:00=500k,*=0)")]
     pub rate_limit: Option<String>,
 
+    /// Limit the combined rate of downloads from one host (--limit-rate-per-host)
+    #[arg(long, value_name = "RATE", help = "Limit the combined download rate per host (e.g., 500k)")]
+    pub limit_rate_per_host: Option<String>,
+
+    /// Limit the rate of each download on its own (--limit-rate-per-download)
+    #[arg(long, value_name = "RATE", help = "Limit the rate of each single download (e.g., 100k)")]
+    pub limit_rate_per_download: Option<String>,
+
     /// Input file with list of URLs to download (-i)
     #[arg(short = 'i', long, help = "File containing list of URLs to download")]
     pub input_file: Option<PathBuf>,
 
+    /// Metalink v4 document listing files and their mirrors (--input-metalink)
+    #[arg(long, help = "Download the files described by a Metalink v4 file")]
+    pub input_metalink: Option<PathBuf>,
+
+    /// Plain list of mirror URLs for a single file (--mirror-list)
+    #[arg(long, help = "File listing mirror URLs of one file, most preferred first")]
+    pub mirror_list: Option<PathBuf>,
+
+    /// Country code of preferred mirrors (--preferred-location)
+    #[arg(long, help = "Prefer Metalink mirrors in this location (e.g., de)")]
+    pub preferred_location: Option<String>,
+
     /// Enable mirror mode (--mirror)
     #[arg(long, help = "Enable website mirroring")]
     pub mirror: bool,
 
+    /// Check links instead of downloading them (--spider)
+    #[arg(long, help = "Check that files exist without saving them")]
+    pub spider: bool,
+
     /// Reject file suffixes during mirror (-R)
     #[arg(
         short = 'R',
@@ -66,6 +139,51 @@ pub struct Cli {
     #[arg(long, help = "Convert links in mirrored files for offline viewing")]
     pub convert_links: bool,
 
+    /// Stop starting downloads after this many bytes (-Q, --quota)
+    #[arg(short = 'Q', long, value_name = "SIZE", help = "Stop starting new downloads once SIZE bytes are downloaded (e.g., 500M, 2G)")]
+    pub quota: Option<String>,
+
+    /// Skip files bigger than this (--max-filesize)
+    #[arg(long, value_name = "SIZE", help = "Skip files larger than SIZE (e.g., 100M)")]
+    pub max_filesize: Option<String>,
+
+    /// Journal for restarting an interrupted run (--session)
+    #[arg(long, value_name = "FILE", help = "Record progress in FILE; running again with it skips finished downloads and continues the rest")]
+    pub session: Option<PathBuf>,
+
+    /// Files downloaded at the same time (-j, --max-concurrent)
+    #[arg(
+        short = 'j',
+        long,
+        default_value = "4",
+        help = "Download up to N files at the same time"
+    )]
+    pub max_concurrent: usize,
+
+    /// Simultaneous downloads from one host (--max-per-host)
+    #[arg(long, value_name = "N", help = "Download at most N files from the same host at once")]
+    pub max_per_host: Option<usize>,
+
+    /// Parallel connections per file (--segments)
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Download each file over N parallel range requests"
+    )]
+    pub segments: usize,
+
+    /// Expected digest of the downloaded file (--checksum)
+    #[arg(long, value_name = "ALGO:HEX", help = "Verify the file against md5, sha1, sha256, sha512 or blake3 digest")]
+    pub checksum: Option<String>,
+
+    /// File of expected digests by file name (--checksum-file)
+    #[arg(long, help = "Look up expected digests by file name in a SHA256SUMS-style file")]
+    pub checksum_file: Option<PathBuf>,
+
+    /// What to do with a file that fails verification (--checksum-mismatch)
+    #[arg(long, value_enum, default_value = "delete", help = "Keep or delete files whose checksum does not match")]
+    pub checksum_mismatch: ChecksumPolicy,
+
     /// Continue partial downloads (-c)
     #[arg(short = 'c', long, help = "Continue partial downloads")]
     pub continue_download: bool,
@@ -90,45 +208,224 @@ pub struct Cli {
     #[arg(short = 'd', long, help = "Debug output")]
     pub debug: bool,
 
+    /// Print server response (-S)
+    #[arg(short = 'S', long, help = "Print the request line and response headers of every request, redirects included")]
+    pub server_response: bool,
+
+    /// Save headers (--save-headers)
+    #[arg(long, help = "Write the response headers at the start of each saved file")]
+    pub save_headers: bool,
+
+    /// Progress display (--progress)
+    #[arg(long, value_name = "TYPE", help = "Progress display: bar, dot[:default|binary|mega|giga] or none (default: bar, dot when stderr is not a terminal)")]
+    pub progress: Option<ProgressMode>,
+
+    /// Show progress even in quiet mode (--show-progress)
+    #[arg(long, help = "Show the progress display even with -q")]
+    pub show_progress: bool,
+
     /// Timeout in seconds (--timeout)
-    #[arg(long, default_value = "30", help = "Timeout in seconds")]
-    pub timeout: u64,
+    #[arg(long, help = "Set the DNS, connect and read timeouts (seconds)")]
+    pub timeout: Option<f64>,
+
+    /// DNS lookup timeout (--dns-timeout)
+    #[arg(long, help = "DNS lookup timeout in seconds (0 = none)")]
+    pub dns_timeout: Option<f64>,
+
+    /// Connect timeout (--connect-timeout)
+    #[arg(long, help = "Connect timeout in seconds (0 = none)")]
+    pub connect_timeout: Option<f64>,
+
+    /// Read (idle) timeout (--read-timeout)
+    #[arg(long, help = "Idle timeout between received data in seconds (default 900, 0 = none)")]
+    pub read_timeout: Option<f64>,
+
+    /// Overall deadline per request (--deadline)
+    #[arg(long, help = "Abort a single download after this many seconds in total")]
+    pub deadline: Option<f64>,
 
     /// User agent string (--user-agent)
     #[arg(long, help = "User agent string")]
     pub user_agent: Option<String>,
+
+    /// Proxy username (--proxy-user)
+    #[arg(long, help = "Username for proxy authentication")]
+    pub proxy_user: Option<String>,
+
+    /// Proxy password (--proxy-password)
+    #[arg(long, help = "Password for proxy authentication")]
+    pub proxy_password: Option<String>,
+
+    /// Ignore proxy environment variables (--no-proxy)
+    #[arg(long, help = "Don't use proxies, even if *_proxy variables are set")]
+    pub no_proxy: bool,
+
+    /// CA bundle used to verify servers (--ca-certificate)
+    #[arg(long, help = "File with the bundle of certificate authorities")]
+    pub ca_certificate: Option<PathBuf>,
+
+    /// Directory of CA certificates (--ca-directory)
+    #[arg(long, help = "Directory containing certificate authority files")]
+    pub ca_directory: Option<PathBuf>,
+
+    /// Client certificate for mutual TLS (--certificate)
+    #[arg(long, help = "Client certificate file (PEM or PKCS#12)")]
+    pub certificate: Option<PathBuf>,
+
+    /// Client certificate format (--certificate-type)
+    #[arg(long, value_enum, help = "Client certificate type: PEM or P12 (default: from extension)")]
+    pub certificate_type: Option<CertificateType>,
+
+    /// Password for a PKCS#12 client certificate (--certificate-password)
+    #[arg(long, help = "Password for a PKCS#12 client certificate")]
+    pub certificate_password: Option<String>,
+
+    /// Private key for the client certificate (--private-key)
+    #[arg(long, help = "Private key file for the client certificate")]
+    pub private_key: Option<PathBuf>,
+
+    /// Skip server certificate verification (--no-check-certificate)
+    #[arg(long, help = "Don't validate the server's certificate")]
+    pub no_check_certificate: bool,
+
+    /// Pinned public keys (--pinnedpubkey)
+    #[arg(long, help = "Public key file or sha256//<base64> hashes separated by ';'")]
+    pub pinnedpubkey: Option<String>,
+
+    /// Minimum TLS version (--secure-protocol)
+    #[arg(long, default_value = "auto", help = "Minimum TLS version: auto, TLSv1, TLSv1_1, TLSv1_2 or TLSv1_3")]
+    pub secure_protocol: String,
+
+    /// Content codings to request and decode (--compression)
+    #[arg(long, value_enum, default_value = "none", help = "Request compressed bodies: auto, gzip, br, zstd or none")]
+    pub compression: Compression,
+
+    /// Save compressed bodies as received (--keep-encoded)
+    #[arg(long, help = "Save the body exactly as sent, without decoding Content-Encoding")]
+    pub keep_encoded: bool,
 }
 
 impl Cli {
+    /// `Cli::command()` plus a hidden `--no-X` for every on/off option `--X`
+    /// and `--X` for every `--no-X`, so that the command line can turn off
+    /// what a wgetrc file turned on. Of the two, the last one given wins.
+    pub fn command_with_negations() -> Command {
+        let command = Cli::command();
+        let negations: Vec<(String, String)> = command
+            .get_arguments()
+            .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+            .filter_map(|arg| {
+                let long = arg.get_long()?;
+                let negation = match long.strip_prefix("no-") {
+                    Some(positive) => positive.to_string(),
+                    None => format!("no-{}", long),
+                };
+                Some((arg.get_id().to_string(), negation))
+            })
+            // `--config` takes a file, so `--no-config` has no counterpart
+            .filter(|(_, negation)| {
+                command
+                    .get_arguments()
+                    .all(|arg| arg.get_long() != Some(negation.as_str()))
+            })
+            .collect();
+        negations
+            .into_iter()
+            .fold(command, |command, (id, negation)| {
+                command
+                    .mut_arg(&id, |arg| arg.overrides_with(negation.clone()))
+                    .arg(
+                        Arg::new(negation.clone())
+                            .long(negation)
+                            .action(ArgAction::SetTrue)
+                            .overrides_with(id)
+                            .hide(true),
+                    )
+            })
+    }
+
+    /// Parses `argv` with `command_with_negations`, exiting on errors like
+    /// `Cli::parse_from`
+    pub fn parse_with_negations<I, T>(argv: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command_with_negations().get_matches_from(argv);
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// The checks of `validate` that a value of `--long` passes or fails on
+    /// its own, for wgetrc settings
+    pub fn check_value(long: &str, value: &str) -> Result<(), String> {
+        match long {
+            "rate-limit" | "limit-rate-per-host" | "limit-rate-per-download" => {
+                RateSchedule::parse(value).map_err(|e| format!("Rate limit: {}", e))?;
+            }
+            "quota" | "max-filesize" => {
+                parse_size(value).map_err(|e| format!("Size: {}", e))?;
+            }
+            "checksum" => {
+                Checksum::parse(value)?;
+            }
+            "secure-protocol" => {
+                parse_secure_protocol(value)?;
+            }
+            "timeout" | "dns-timeout" | "connect-timeout" | "read-timeout" | "deadline" => {
+                let secs = value
+                    .parse::<f64>()
+                    .map_err(|_| "Timeouts must be non-negative numbers of seconds".to_string())?;
+                Timeouts::check_secs(secs)?;
+            }
+            "max-concurrent" | "max-per-host" | "segments" if value == "0" => {
+                return Err("Must be at least 1".into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     pub fn validate(&self) -> Result<(), String> {
         // Must have either direct URLs or an input file
-        if self.urls.is_empty() && self.input_file.is_none() {
+        if self.urls.is_empty()
+            && self.input_file.is_none()
+            && self.input_metalink.is_none()
+            && self.mirror_list.is_none()
+        {
             return Err("Provide at least one URL or use -i with a file containing URLs.".into());
         }
 
-        // Input file must exist
-        if let Some(file) = &self.input_file {
+        // Input files must exist
+        for file in [&self.input_file, &self.input_metalink, &self.mirror_list]
+            .into_iter()
+            .flatten()
+        {
             if !file.exists() {
                 return Err(format!("Input file {:?} does not exist", file));
             }
         }
+        if (self.input_metalink.is_some() || self.mirror_list.is_some())
+            && (self.mirror || self.spider)
+        {
+            return Err("--input-metalink and --mirror-list cannot be combined with --mirror or --spider".into());
+        }
 
         // Directory must exist if -P is used
-        if let Some(dir) = &self.directory_prefix {
-            if !dir.exists() || !dir.is_dir() {
-                return Err(format!(
-                    "Directory {:?} does not exist or is not a folder",
-                    dir
-                ));
-            }
+        if let Some(dir) = &self.directory_prefix
+            && (!dir.exists() || !dir.is_dir())
+        {
+            return Err(format!(
+                "Directory {:?} does not exist or is not a folder",
+                dir
+            ));
         }
 
-        // Validate rate-limit format (e.g., 200k or 2M)
-        if let Some(rate) = &self.rate_limit {
-            let valid = rate.ends_with('k') || rate.ends_with('M') || rate.parse::<u64>().is_ok();
-            if !valid {
-                return Err("Rate limit must be a number or end with 'k'/'M' (e.g., 400k, 2M)".into());
-            }
+        // Validate rates and schedules (e.g., 200k, 1.5M or 08:00-18:00=500k,*=0)
+        for rate in [&self.rate_limit, &self.limit_rate_per_host, &self.limit_rate_per_download]
+            .into_iter()
+            .flatten()
+        {
+            RateSchedule::parse(rate).map_err(|e| format!("Rate limit: {}", e))?;
         }
 
         // Validate output level conflicts
@@ -150,7 +447,157 @@ impl Cli {
                 );
             }
         }
+        for size in [&self.quota, &self.max_filesize].into_iter().flatten() {
+            parse_size(size).map_err(|e| format!("Size: {}", e))?;
+        }
+        if self.max_concurrent == 0 || self.max_per_host == Some(0) {
+            return Err("--max-concurrent and --max-per-host must be at least 1".into());
+        }
+        if self.segments == 0 {
+            return Err("--segments must be at least 1".into());
+        }
+        if let Some(checksum) = &self.checksum {
+            Checksum::parse(checksum)?;
+            let targets = self.urls.len() + usize::from(self.mirror_list.is_some());
+            if targets != 1 || self.mirror {
+                return Err("--checksum applies to exactly one URL or mirror list; use per-line checksums or --checksum-file for more".into());
+            }
+        }
+        if let Some(file) = &self.checksum_file
+            && !file.is_file()
+        {
+            return Err(format!("Checksum file {:?} does not exist", file));
+        }
+        if self.writes_single_output() {
+            if self.mirror || self.spider || self.input_metalink.is_some() {
+                return Err("-O with several documents cannot be combined with --mirror, --spider or --input-metalink".into());
+            }
+            if self.continue_download {
+                return Err("-c cannot continue output written to stdout or shared by several URLs".into());
+            }
+        }
+        if self.session.is_some() && (self.writes_single_output() || self.spider) {
+            return Err("--session cannot be combined with --spider, -O - or several URLs into one -O file".into());
+        }
+        if self.output_to_stdout() && (self.background || self.mirror_list.is_some()) {
+            return Err("-O - cannot be combined with -B or --mirror-list".into());
+        }
+        if self.spider && (self.continue_download || self.convert_links) {
+            return Err("--spider cannot be combined with -c or --convert-links".into());
+        }
+
+        for path in [&self.ca_certificate, &self.certificate, &self.private_key]
+            .into_iter()
+            .flatten()
+        {
+            if !path.is_file() {
+                return Err(format!("TLS file {:?} does not exist", path));
+            }
+        }
+        if let Some(dir) = &self.ca_directory
+            && !dir.is_dir()
+        {
+            return Err(format!("CA directory {:?} does not exist or is not a folder", dir));
+        }
+        if self.private_key.is_some() && self.certificate.is_none() {
+            return Err("--private-key requires --certificate".into());
+        }
+        parse_secure_protocol(&self.secure_protocol)?;
+
+        let timeouts = [
+            self.timeout,
+            self.dns_timeout,
+            self.connect_timeout,
+            self.read_timeout,
+            self.deadline,
+        ];
+        for secs in timeouts.into_iter().flatten() {
+            Timeouts::check_secs(secs)?;
+        }
+
+        if self.no_proxy && (self.proxy_user.is_some() || self.proxy_password.is_some()) {
+            return Err("--proxy-user and --proxy-password cannot be combined with --no-proxy".into());
+        }
 
         Ok(())
     }
+
+    /// Whether `-O -` sends the documents to stdout
+    pub fn output_to_stdout(&self) -> bool {
+        self.output.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Whether every document goes to the one `-O` target, in order: stdout,
+    /// or a file shared by several URLs (including those of `-i`)
+    pub fn writes_single_output(&self) -> bool {
+        self.output.is_some()
+            && (self.output_to_stdout() || self.urls.len() > 1 || self.input_file.is_some())
+    }
+
+    /// Builds the proxy configuration from the environment and proxy flags.
+    pub fn proxy_config(&self) -> Result<ProxyConfig, String> {
+        if self.no_proxy {
+            return Ok(ProxyConfig::default());
+        }
+        Ok(ProxyConfig::from_env()?
+            .with_credentials(self.proxy_user.as_deref(), self.proxy_password.as_deref()))
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts::from_secs(
+            self.timeout,
+            self.dns_timeout,
+            self.connect_timeout,
+            self.read_timeout,
+            self.deadline,
+        )
+    }
+
+    /// Headers to print or save; `-q` silences `-S`
+    pub fn header_output(&self) -> HeaderOutput {
+        HeaderOutput {
+            server_response: self.server_response && !self.quiet,
+            save_headers: self.save_headers,
+            debug: self.debug,
+        }
+    }
+
+    pub fn progress_display(&self) -> ProgressDisplay {
+        ProgressDisplay::new(self.progress, self.show_progress)
+    }
+
+    pub fn compression_config(&self) -> CompressionConfig {
+        CompressionConfig {
+            mode: self.compression,
+            keep_encoded: self.keep_encoded,
+        }
+    }
+
+    /// Digest a command line download must match: `--checksum`, otherwise
+    /// the `--checksum-file` entry for the saved file's name
+    pub fn expected_checksum(&self, sums: Option<&ChecksumFile>, file_path: &Path) -> Option<Checksum> {
+        if let Some(checksum) = &self.checksum {
+            return Checksum::parse(checksum).ok();
+        }
+        let name = file_path.file_name()?.to_str()?;
+        sums?.lookup(name).cloned()
+    }
+
+    /// Builds the TLS configuration, reading any pinned public key files.
+    pub fn tls_config(&self) -> Result<TlsConfig, String> {
+        Ok(TlsConfig {
+            ca_certificate: self.ca_certificate.clone(),
+            ca_directory: self.ca_directory.clone(),
+            certificate: self.certificate.clone(),
+            certificate_type: self.certificate_type,
+            certificate_password: self.certificate_password.clone(),
+            private_key: self.private_key.clone(),
+            check_certificate: !self.no_check_certificate,
+            pinned_pubkeys: match &self.pinnedpubkey {
+                Some(value) => parse_pinned_pubkeys(value)?,
+                None => Vec::new(),
+            },
+            min_version: parse_secure_protocol(&self.secure_protocol)?,
+        })
+    }
 }
@@ -0,0 +1,92 @@
+use crate::control::Request;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// `wget ctl`: talks to a background session (`-B`) over its control socket
+#[derive(Parser, Debug)]
+#[command(
+    name = "wget ctl",
+    about = "Query and steer a running background download"
+)]
+pub struct CtlCli {
+    /// Control socket of the session (--socket)
+    #[arg(
+        long,
+        default_value = "wget-log.sock",
+        help = "Control socket of the session (<logfile>.sock)"
+    )]
+    pub socket: PathBuf,
+
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CtlCommand {
+    /// Print every download with its state and progress as JSON
+    Status,
+    /// Hold all transfers and start no new ones
+    Pause,
+    /// Continue after `pause`
+    Resume,
+    /// Cancel a queued or running download
+    Cancel { id: u64 },
+    /// Queue another URL
+    Add { url: String },
+    /// Change the combined rate limit, e.g. 1M (0 for unlimited)
+    SetRate { rate: String },
+}
+
+impl CtlCli {
+    /// Parses `wget ctl ...` when `argv` (program name first) is one: `ctl`
+    /// followed by one of its commands, `--socket` or `--help`. Anything
+    /// else is a download command line, so `wget ctl` alone still fetches
+    /// a URL named `ctl`.
+    pub fn from_wget_args(argv: &[OsString]) -> Option<Self> {
+        if argv.get(1).is_none_or(|arg| arg != "ctl") {
+            return None;
+        }
+        let next = argv.get(2)?.to_str()?;
+        let command = Self::command();
+        let ctl = command
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == next)
+            || next.starts_with("--socket")
+            || matches!(next, "help" | "-h" | "--help");
+        if !ctl {
+            return None;
+        }
+        let matches = command.bin_name("wget ctl").get_matches_from(&argv[1..]);
+        Some(Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
+    }
+
+    pub fn request(&self) -> Request {
+        match self.command.clone() {
+            CtlCommand::Status => Request::Status,
+            CtlCommand::Pause => Request::Pause,
+            CtlCommand::Resume => Request::Resume,
+            CtlCommand::Cancel { id } => Request::Cancel { id },
+            CtlCommand::Add { url } => Request::Add { url },
+            CtlCommand::SetRate { rate } => Request::SetRate { rate },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ctl_is_only_taken_with_a_command() {
+        let argv = |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+
+        let ctl = CtlCli::from_wget_args(&argv(&["wget", "ctl", "--socket=s.sock", "cancel", "3"]));
+        assert!(matches!(ctl.unwrap().request(), Request::Cancel { id: 3 }));
+        assert!(CtlCli::from_wget_args(&argv(&["wget", "ctl", "status"])).is_some());
+        // A URL (or two) named `ctl` is still downloaded
+        assert!(CtlCli::from_wget_args(&argv(&["wget", "ctl"])).is_none());
+        assert!(CtlCli::from_wget_args(&argv(&["wget", "ctl", "ctl"])).is_none());
+        assert!(CtlCli::from_wget_args(&argv(&["wget", "-q", "ctl", "status"])).is_none());
+    }
+}
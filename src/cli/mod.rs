@@ -1,2 +1,34 @@
-pub mod args;
-pub use args::Cli;
+mod args;
+mod ctl;
+mod run;
+pub(crate) use args::Cli;
+pub(crate) use ctl::CtlCli;
+
+use clap::Parser;
+use std::ffi::OsString;
+
+/// The `wget` binary: `wget ctl ...` talks to a running background session,
+/// anything else is a command line run with the startup files applied.
+/// Exits the process with wget's status when something failed.
+pub async fn main() {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    if let Some(ctl) = CtlCli::from_wget_args(&argv) {
+        std::process::exit(run::run_ctl(&ctl).await);
+    }
+
+    // Settings from wgetrc files and -e sit beneath the command line
+    let args = match crate::config::load(Cli::parse_with_negations(argv)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Config error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    run::run(args).await;
+}
+
+/// The `wget-ctl` alias binary: `wget ctl` without the `ctl`. Returns the
+/// exit status.
+pub async fn ctl_main() -> i32 {
+    run::run_ctl(&CtlCli::parse()).await
+}
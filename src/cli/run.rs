@@ -0,0 +1,707 @@
+use crate::background::daemon::{self, PidFile};
+use crate::cli::Cli;
+use crate::control::ControlSocket;
+use crate::api::Downloader;
+use crate::download::DownloadOptions;
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::mirror::MirrorCrawler;
+use crate::output::{OutputLevel, OutputLogger, RunSummary};
+use crate::{checksum, cli, control, io, metalink, output, session, signals, spider, utils};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Runs the command line `args`, as parsed with the startup files applied.
+/// Exits the process with wget's status when something failed.
+pub async fn run(mut args: Cli) {
+    // Checked as given, before JSON output turns on quiet for its own sake
+    if let Err(e) = args.validate() {
+        eprintln!("Argument error: {}", e);
+        std::process::exit(1);
+    }
+    // JSON events take the place of the messages; what the logger still
+    // says (unless -q) becomes `log` events
+    let quiet_requested = args.quiet;
+    if args.log_format == output::LogFormat::Json {
+        args.quiet = true;
+    }
+
+    // -B: start a detached copy of this command and leave it running
+    let background_log = daemon::child_log();
+    if args.background && background_log.is_none() {
+        match daemon::spawn(&args) {
+            Ok((pid, log)) => {
+                println!("Continuing in background, pid {}.", pid);
+                println!("Output will be written to '{}'.", log.display());
+                return;
+            }
+            Err(e) => {
+                eprintln!("Cannot go to background: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if background_log.is_none()
+        && let Some(log) = args.output_file.as_ref().or(args.append_output.as_ref())
+        && let Err(e) =
+            daemon::redirect_output(log, args.append_output.is_some(), args.output_to_stdout())
+    {
+        eprintln!("Cannot write to {}: {}", log.display(), e);
+        std::process::exit(1);
+    }
+
+    let proxy = match args.proxy_config() {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            eprintln!("Proxy error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let tls = match args.tls_config() {
+        Ok(tls) => tls,
+        Err(e) => {
+            eprintln!("TLS error: {}", e);
+            std::process::exit(5);
+        }
+    };
+
+    let mut options = DownloadOptions::from_cli(&args, proxy, tls);
+    if let Some(path) = &args.session {
+        match session::Session::open(path) {
+            Ok(session) => options.session = Some(session),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Output already going to a log has nowhere to move on SIGHUP
+    let hangup = if background_log.is_some()
+        || args.output_file.is_some()
+        || args.append_output.is_some()
+    {
+        signals::Hangup::Ignore
+    } else {
+        signals::Hangup::Redirect {
+            keep_stdout: args.output_to_stdout(),
+        }
+    };
+    if let Err(e) = signals::listen(options.jobs.clone(), hangup) {
+        eprintln!("Cannot install signal handlers: {}", e);
+    }
+    output::events::report_progress(options.jobs.clone(), Duration::from_secs(1));
+
+    let sums = match &args.checksum_file {
+        Some(path) => match checksum::ChecksumFile::load(path).await {
+            Ok(sums) => Some(sums),
+            Err(e) => {
+                eprintln!("Checksum error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let output_level = if quiet_requested {
+        OutputLevel::Quiet
+    } else if args.verbose {
+        OutputLevel::Verbose
+    } else if args.debug {
+        OutputLevel::Debug
+    } else {
+        OutputLevel::Normal
+    };
+    let logger = OutputLogger::new(output_level)
+        .with_stderr(args.output_to_stdout())
+        .with_events(options.jobs.events().clone());
+
+    let start_time = Utc::now();
+    logger.info(&format!("start at {}", start_time.format("%Y-%m-%d %H:%M:%S")));
+
+    // -B: this is the detached copy. The PID file and the control socket
+    // are removed when dropped, so the modes return their status instead
+    // of exiting
+    let background = background_log.map(|log| start_background(&args, &log, &options));
+    let status = tokio::select! {
+        status = dispatch(&args, &options, sums.as_ref(), &logger, start_time) => status,
+        // A second SIGINT or SIGTERM: quit without waiting for the transfers
+        _ = options.jobs.aborted() => 128 + options.jobs.interrupt_signal().unwrap_or(libc::SIGINT),
+    };
+    drop(background);
+    if status != 0 {
+        std::process::exit(status);
+    }
+}
+
+/// Runs the mode `args` asks for and returns wget's exit status
+async fn dispatch(
+    args: &Cli,
+    options: &DownloadOptions,
+    sums: Option<&checksum::ChecksumFile>,
+    logger: &OutputLogger,
+    start_time: chrono::DateTime<Utc>,
+) -> i32 {
+    // Every mode runs on the one downloader, and so on one HTTP client
+    let downloader = match Downloader::for_command_line(
+        options.clone(),
+        args.directory_prefix.as_deref(),
+        args.quiet,
+    ) {
+        Ok(downloader) => downloader,
+        Err(e) => {
+            eprintln!("{}", e);
+            return exit_status(&[e]);
+        }
+    };
+    let mut errors = Vec::new();
+
+    // Handle spider mode: check links, save nothing
+    if args.spider {
+        let report = match process_spider_mode(args, options, &downloader).await {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Spider failed: {}", e);
+                return 1;
+            }
+        };
+        if !args.quiet {
+            print!("{}", report.render());
+        }
+        if let Some(status) = interrupted(args, options) {
+            return status;
+        }
+        // Like wget, broken links are reported as a server error
+        return if report.broken().is_empty() { 0 } else { 8 };
+    }
+
+    // Handle mirror mode
+    if args.mirror {
+        if let Err(e) = process_mirror_mode(args, &downloader).await {
+            eprintln!("Mirror failed: {}", e.message);
+            write_report(args, &options.jobs);
+            logger.summary(&run_summary(start_time, &options.jobs));
+            return 1;
+        }
+        write_report(args, &options.jobs);
+        if let Some(status) = interrupted(args, options) {
+            return status;
+        }
+        logger.summary(&run_summary(start_time, &options.jobs));
+        let end_time = Utc::now();
+        logger.info(&format!("finished at {}", end_time.format("%Y-%m-%d %H:%M:%S")));
+        return 0;
+    }
+
+    if args.writes_single_output() {
+        // -O - or one -O file for several URLs: everything in order, one stream
+        errors.extend(process_urls_to_output(args, options, &downloader, sums).await);
+    } else {
+        // Process command line URLs sequentially (like real wget)
+        if !args.urls.is_empty() && !args.quiet {
+            println!(
+                "Processing {} command line URLs sequentially...",
+                args.urls.len()
+            );
+        }
+        if !args.urls.is_empty() {
+            errors.extend(
+                process_urls_sequentially(args, options, &downloader, sums, &args.urls).await,
+            );
+        }
+
+        // Process input file URLs concurrently (for efficiency)
+        if let Some(input_file) = &args.input_file
+            && !options.jobs.is_interrupted()
+        {
+            match io::read_entries_from_file(input_file).await {
+                Ok(entries) => {
+                    if !entries.is_empty() {
+                        if !args.quiet {
+                            println!(
+                                "Read {} URLs from file: {}",
+                                entries.len(),
+                                input_file.display()
+                            );
+                            println!("Processing file URLs concurrently...");
+                        }
+                        let entries = attach_checksums(entries, sums);
+                        errors.extend(
+                            process_urls_concurrently(options, &downloader, entries).await,
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading URLs from file: {}", e);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    // Metalink documents and mirror lists: one file from several mirrors
+    if (args.input_metalink.is_some() || args.mirror_list.is_some())
+        && !options.jobs.is_interrupted()
+    {
+        errors.extend(process_metalink_mode(args, options, &downloader).await);
+    }
+
+    // URLs added with `wget ctl add` (-B only) after the last batch started
+    while options.jobs.has_added() && !options.jobs.is_interrupted() {
+        errors.extend(process_urls_concurrently(options, &downloader, Vec::new()).await);
+    }
+
+    // Check if we have any URLs to process
+    if args.urls.is_empty()
+        && args.input_file.is_none()
+        && args.input_metalink.is_none()
+        && args.mirror_list.is_none()
+    {
+        eprintln!("No URLs to download");
+        return 1;
+    }
+
+    write_report(args, &options.jobs);
+    if let Some(status) = interrupted(args, options) {
+        return status;
+    }
+
+    logger.summary(&run_summary(start_time, &options.jobs));
+    let end_time = Utc::now();
+    logger.info(&format!("finished at {}", end_time.format("%Y-%m-%d %H:%M:%S")));
+
+    // Exit with error code if any downloads failed (like real wget);
+    // skipped files are not failures
+    errors.retain(|e| e.kind != ErrorKind::Skipped);
+    if errors.is_empty() { 0 } else { exit_status(&errors) }
+}
+
+/// After SIGINT or SIGTERM: prints the partial summary and gives the exit
+/// status, `128 + signal`
+fn interrupted(args: &Cli, options: &DownloadOptions) -> Option<i32> {
+    let signal = options.jobs.interrupt_signal()?;
+    if !args.quiet {
+        let summary = signals::handler::partial_summary(signal, &options.jobs.status());
+        // `-O -` keeps stdout for the body
+        if args.output_to_stdout() {
+            eprint!("{}", summary);
+        } else {
+            print!("{}", summary);
+        }
+    }
+    Some(128 + signal)
+}
+
+/// The PID file and `wget ctl` socket of the detached copy started by -B,
+/// next to its log
+fn start_background(
+    args: &Cli,
+    log: &Path,
+    options: &DownloadOptions,
+) -> (Option<PidFile>, Option<ControlSocket>) {
+    let pid_file = match PidFile::create(daemon::pid_path(args, log)) {
+        Ok(pid_file) => Some(pid_file),
+        Err(e) => {
+            eprintln!("Cannot write PID file: {}", e);
+            None
+        }
+    };
+    let socket_path = daemon::socket_path(args, log);
+    let control_socket = match ControlSocket::bind(&socket_path, options.clone()) {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            eprintln!("Cannot listen on {}: {}", socket_path.display(), e);
+            None
+        }
+    };
+    (pid_file, control_socket)
+}
+
+/// Sends one `wget ctl` command and prints the session's JSON reply.
+/// Returns the exit status: 0 when the session accepted the command.
+pub async fn run_ctl(ctl: &cli::CtlCli) -> i32 {
+    match control::client::send(&ctl.socket, &ctl.request()).await {
+        Ok(reply) => {
+            println!("{}", reply);
+            let accepted = serde_json::from_str::<serde_json::Value>(&reply)
+                .is_ok_and(|reply| reply["ok"] == true);
+            if accepted { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("Cannot reach {}: {}", ctl.socket.display(), e);
+            1
+        }
+    }
+}
+
+/// wget-style exit status for failed downloads: certificate problems give 5
+/// and checksum mismatches 9, and the lower code wins when both occur.
+/// Downloads cancelled with `wget ctl cancel` were asked for and not
+/// fetched, so like other failures they give 1.
+fn exit_status(errors: &[DownloadError]) -> i32 {
+    errors
+        .iter()
+        .filter_map(|e| match e.kind {
+            ErrorKind::Tls => Some(5),
+            ErrorKind::Checksum => Some(9),
+            _ => None,
+        })
+        .min()
+        .unwrap_or(1)
+}
+
+/// What the run did since `start_time`, from the downloads in `jobs`
+fn run_summary(start_time: chrono::DateTime<Utc>, jobs: &control::Jobs) -> RunSummary {
+    let wall_time = (Utc::now() - start_time).to_std().unwrap_or_default();
+    RunSummary::new(jobs, wall_time)
+}
+
+/// Writes the `--report` summary of every download of the run
+fn write_report(args: &cli::Cli, jobs: &control::Jobs) {
+    if let Some(path) = &args.report
+        && let Err(e) = output::report::write(path, &jobs.status())
+    {
+        eprintln!("Cannot write report {}: {}", path.display(), e);
+    }
+}
+
+/// Fills in `--checksum-file` digests for input file lines without their own
+fn attach_checksums(
+    entries: Vec<io::InputEntry>,
+    sums: Option<&checksum::ChecksumFile>,
+) -> Vec<io::InputEntry> {
+    let Some(sums) = sums else {
+        return entries;
+    };
+    entries
+        .into_iter()
+        .map(|mut entry| {
+            if entry.checksum.is_none() {
+                let name = utils::url::extract_filename(&entry.url);
+                entry.checksum = sums.lookup(&name).cloned();
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Process URLs sequentially (for command line URLs)
+async fn process_urls_sequentially(
+    args: &cli::Cli,
+    options: &DownloadOptions,
+    downloader: &Downloader,
+    sums: Option<&checksum::ChecksumFile>,
+    urls: &[String],
+) -> Vec<DownloadError> {
+    let client = downloader.client();
+    let mut errors = Vec::new();
+    if let Some(session) = &options.session {
+        session.add_pending(urls.iter().map(String::as_str));
+    }
+    let jobs: Vec<_> = urls.iter().map(|url| options.jobs.add(url)).collect();
+
+    for (url, job) in urls.iter().zip(jobs) {
+        if options.jobs.is_interrupted() {
+            break;
+        }
+        if let Err(e) = client.quota().check(url) {
+            if !args.quiet {
+                println!("Skipped [{}]: {}", url, e.message);
+            }
+            errors.push(e);
+            continue;
+        }
+        let file_path = determine_output_path(args, url);
+        job.set_path(&file_path);
+
+        let expected = args.expected_checksum(sums, &file_path);
+
+        if let Some(session) = &options.session
+            && session.is_done(url, &file_path)
+        {
+            options.jobs.add_skipped_file(&file_path);
+            if !args.quiet {
+                println!("Already downloaded in this session: {}", url);
+            }
+            continue;
+        }
+        let outcome = job
+            .run(async {
+                job.start().await;
+                let download = |resume| {
+                    client.download_to_file_checked(url, &file_path, resume, args.quiet, expected.as_ref())
+                };
+                match &options.session {
+                    Some(session) => {
+                        session
+                            .track(url, &file_path, options.resume, download)
+                            .await
+                    }
+                    None => download(options.resume).await,
+                }
+                .map(|fetched| fetched.bytes)
+            })
+            .await;
+        match outcome {
+            Ok(_) => {
+                if !args.quiet {
+                    println!("Downloaded [{}]", url);
+                }
+            }
+            Err(e) if e.kind == ErrorKind::Skipped => {
+                if !args.quiet {
+                    println!("Skipped [{}]: {}", url, e.message);
+                }
+                errors.push(e);
+            }
+            Err(e) if e.kind == ErrorKind::Interrupted => {
+                if !args.quiet {
+                    println!("Interrupted [{}]", url);
+                }
+                errors.push(e);
+            }
+            Err(e) if e.kind == ErrorKind::Cancelled => {
+                if !args.quiet {
+                    println!("Cancelled [{}]", url);
+                }
+                errors.push(e);
+            }
+            Err(e) => {
+                eprintln!("Download failed for [{}]: {}", url, e);
+                errors.push(e);
+            }
+        }
+    }
+
+    errors
+}
+
+/// Writes every command line and `-i` URL, in order, to the single `-O`
+/// target: stdout for `-O -`, otherwise one file holding all the bodies
+async fn process_urls_to_output(
+    args: &cli::Cli,
+    options: &DownloadOptions,
+    downloader: &Downloader,
+    sums: Option<&checksum::ChecksumFile>,
+) -> Vec<DownloadError> {
+    let client = downloader.client();
+
+    let mut entries: Vec<io::InputEntry> = args
+        .urls
+        .iter()
+        .map(|url| io::InputEntry {
+            url: url.clone(),
+            checksum: None,
+        })
+        .collect();
+    if let Some(input_file) = &args.input_file {
+        match io::read_entries_from_file(input_file).await {
+            Ok(file_entries) => entries.extend(file_entries),
+            Err(e) => {
+                eprintln!("Error reading URLs from file: {}", e);
+                return vec![e];
+            }
+        }
+    }
+    let entries = attach_checksums(entries, sums);
+
+    let mut out: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = if args.output_to_stdout() {
+        Box::new(tokio::io::stdout())
+    } else {
+        let path = args.output.as_deref().unwrap_or(Path::new("-"));
+        match tokio::fs::File::create(path).await {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                let e = DownloadError {
+                    kind: ErrorKind::Io,
+                    message: format!("Failed to create {}: {}", path.display(), e),
+                };
+                eprintln!("{}", e);
+                return vec![e];
+            }
+        }
+    };
+
+    let mut errors = Vec::new();
+    let jobs: Vec<_> = entries.iter().map(|entry| options.jobs.add(&entry.url)).collect();
+    let path = args.output.as_deref().unwrap_or(Path::new("-"));
+    for (entry, job) in entries.into_iter().zip(jobs) {
+        job.set_path(path);
+        let expected = match &args.checksum {
+            Some(checksum) => checksum::Checksum::parse(checksum).ok(),
+            None => entry.checksum,
+        };
+        let outcome = job
+            .run(async {
+                job.start().await;
+                client
+                    .download_to_writer(&entry.url, &mut out, args.quiet, expected.as_ref())
+                    .await
+            })
+            .await;
+        match outcome {
+            Ok(_) => {}
+            // The reader has all it wants (e.g. `| head`): stop quietly
+            Err(e) if e.kind == ErrorKind::OutputClosed => break,
+            Err(e) if e.kind == ErrorKind::Interrupted => {
+                errors.push(e);
+                break;
+            }
+            Err(e) if e.kind == ErrorKind::Skipped => {
+                eprintln!("Skipped [{}]: {}", entry.url, e.message);
+                errors.push(e);
+            }
+            Err(e) if e.kind == ErrorKind::Cancelled => {
+                eprintln!("Cancelled [{}]", entry.url);
+                errors.push(e);
+            }
+            Err(e) => {
+                eprintln!("Download failed for [{}]: {}", entry.url, e);
+                errors.push(e);
+            }
+        }
+    }
+
+    errors
+}
+
+/// Process URLs concurrently (for input file URLs)
+async fn process_urls_concurrently(
+    options: &DownloadOptions,
+    downloader: &Downloader,
+    urls: Vec<io::InputEntry>,
+) -> Vec<DownloadError> {
+    // With no URLs of its own the manager still runs those `wget ctl add` queued
+    if urls.is_empty() && !options.jobs.has_added() {
+        return Vec::new();
+    }
+
+    // The end-of-run summary covers these downloads too
+    let results = downloader.download_entries(urls).await;
+    results.into_iter().filter_map(|r| r.error).collect()
+}
+
+/// Process mirror mode
+async fn process_mirror_mode(args: &cli::Cli, downloader: &Downloader) -> Result<(), DownloadError> {
+    let url = &args.urls[0]; // Validation ensures exactly one URL for mirror mode
+    if !args.quiet {
+        println!("Starting mirror of: {}", url);
+    }
+
+    let list = |list: &Option<String>| -> Vec<String> {
+        list.iter().flat_map(|list| list.split(',')).map(str::to_string).collect()
+    };
+    let mut mirror = downloader.mirror(url);
+    if args.reject_suffixes.is_some() {
+        mirror = mirror.with_reject(list(&args.reject_suffixes));
+    }
+    if args.exclude_dirs.is_some() {
+        mirror = mirror.with_exclude(list(&args.exclude_dirs));
+    }
+    mirror.run().await?;
+
+    if !args.quiet {
+        println!("Mirror completed successfully");
+    }
+    Ok(())
+}
+
+/// Process spider mode: the crawl for --mirror, otherwise every command line
+/// and input file URL is probed
+async fn process_spider_mode(
+    args: &cli::Cli,
+    options: &DownloadOptions,
+    downloader: &Downloader,
+) -> Result<spider::SpiderReport, Box<dyn std::error::Error>> {
+    if args.mirror {
+        let url = &args.urls[0];
+        println!("Spidering: {}", url);
+        let mut crawler = MirrorCrawler::new_spider(url, downloader.client().clone(), options)?;
+        crawler.mirror(&args.reject_suffixes, &args.exclude_dirs).await?;
+        return Ok(crawler.spider_report().clone());
+    }
+
+    let mut urls = args.urls.clone();
+    if let Some(input_file) = &args.input_file {
+        urls.extend(io::read_urls_from_file(input_file).await?);
+    }
+    if urls.is_empty() {
+        return Err("No URLs to check".into());
+    }
+
+    Ok(spider::check_urls(downloader.client(), &urls, args.max_concurrent, args.quiet).await)
+}
+
+/// Process --input-metalink and --mirror-list downloads
+async fn process_metalink_mode(
+    args: &cli::Cli,
+    options: &DownloadOptions,
+    downloader: &Downloader,
+) -> Vec<DownloadError> {
+    let input_error = |message: String| DownloadError {
+        kind: ErrorKind::Input,
+        message,
+    };
+    let mut files = Vec::new();
+
+    if let Some(path) = &args.input_metalink {
+        let parsed = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))
+            .and_then(|xml| metalink::parse_metalink(&xml));
+        match parsed {
+            Ok(parsed) => files.extend(parsed),
+            Err(e) => {
+                eprintln!("Metalink error: {}", e);
+                return vec![input_error(e)];
+            }
+        }
+    }
+
+    if let Some(path) = &args.mirror_list {
+        let urls = match io::read_urls_from_file(path).await {
+            Ok(urls) => urls,
+            Err(e) => {
+                eprintln!("Error reading mirror list: {}", e);
+                return vec![e];
+            }
+        };
+        let name = args
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(utils::url::extract_filename(&urls[0])));
+        let mut file = metalink::MetalinkFile::from_mirror_list(name, urls);
+        if let Some(checksum) = &args.checksum {
+            file.hashes.extend(checksum::Checksum::parse(checksum).ok());
+        }
+        files.push(file);
+    }
+
+    metalink::download_files(
+        downloader.client(),
+        &files,
+        args.directory_prefix.as_deref(),
+        args.preferred_location.as_deref(),
+        options.resume,
+        args.quiet,
+    )
+    .await
+}
+
+fn determine_output_path(args: &cli::Cli, url: &str) -> PathBuf {
+    if let Some(output) = &args.output {
+        // -O flag: use specified filename
+        return output.clone();
+    }
+
+    // Extract filename from URL
+    let filename = utils::url::extract_filename(url);
+
+    if let Some(dir) = &args.directory_prefix {
+        // -P flag: save to specified directory
+        dir.join(filename)
+    } else {
+        // Default: save to current directory
+        PathBuf::from(filename)
+    }
+}
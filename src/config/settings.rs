@@ -0,0 +1,380 @@
+use crate::cli::Cli;
+use clap::parser::ValueSource;
+use clap::error::ErrorKind;
+use clap::{Arg, ArgMatches, Command, CommandFactory, Id};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Startup file read by every user
+pub const SYSTEM_WGETRC: &str = "/etc/wgetrc";
+
+/// wget's names for settings whose option is spelled differently here
+const ALIASES: [(&str, &str); 8] = [
+    ("limitrate", "rate-limit"),
+    ("continue", "continue-download"),
+    ("dirprefix", "directory-prefix"),
+    ("input", "input-file"),
+    ("outputdocument", "output"),
+    ("logfile", "output-file"),
+    ("reject", "reject-suffixes"),
+    ("excludedirectories", "exclude-dirs"),
+];
+
+/// Settings that turn a `--no-...` flag off when they are on
+const INVERTED: [(&str, &str); 2] = [
+    ("checkcertificate", "no-check-certificate"),
+    ("useproxy", "no-proxy"),
+];
+
+/// Options of which only one can be given, although clap does not know it
+const OUTPUT_LEVELS: [&str; 3] = ["quiet", "verbose", "debug"];
+
+/// Options that only make sense on the command line
+const COMMAND_LINE_ONLY: [&str; 5] = ["config", "no-config", "execute", "help", "version"];
+
+/// wgetrc settings mapped onto `Cli` options, later settings replacing
+/// earlier ones
+#[derive(Debug, Default)]
+pub struct Settings {
+    entries: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Flag(bool),
+    Text(String),
+}
+
+impl Settings {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads the `key = value` lines of a wgetrc file. Blank lines and
+    /// lines starting with `#` are ignored, and so are settings of wget
+    /// features this one lacks (e.g. `passive_ftp` in a stock
+    /// `/etc/wgetrc`); those are returned as warnings.
+    pub fn read(&mut self, path: &Path) -> Result<Vec<String>, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let mut ignored = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let location = format!("{}:{}", path.display(), number + 1);
+            match self.apply(line) {
+                Ok(()) => {}
+                Err(SettingError::Unknown(key)) => {
+                    ignored.push(format!("{}: ignoring unknown setting {:?}", location, key))
+                }
+                Err(e) => return Err(format!("{}: {}", location, e)),
+            }
+        }
+        Ok(ignored)
+    }
+
+    /// Applies one `key = value` setting, as found in a wgetrc file or
+    /// given with `-e`. Keys ignore case, `_` and `-`, like wget's.
+    pub fn apply(&mut self, command: &str) -> Result<(), SettingError> {
+        let (key, value) = command.split_once('=').ok_or_else(|| {
+            SettingError::Invalid(format!(
+                "Invalid setting {:?}, expected key = value",
+                command
+            ))
+        })?;
+        let (key, value) = (key.trim(), value.trim());
+        let name: String = key
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        let (long, inverted) = match INVERTED.iter().find(|(setting, _)| *setting == name) {
+            Some((_, long)) => (long.to_string(), true),
+            None => {
+                let name = ALIASES
+                    .iter()
+                    .find(|(setting, _)| *setting == name)
+                    .map_or(name.clone(), |(_, long)| long.replace('-', ""));
+                (
+                    option_for(&name).ok_or_else(|| SettingError::Unknown(key.to_string()))?,
+                    false,
+                )
+            }
+        };
+        let command = Cli::command();
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .expect("settings map onto options");
+
+        let value = if arg.get_action().takes_values() {
+            check_value(&command, arg, value).map_err(SettingError::Invalid)?;
+            Value::Text(value.to_string())
+        } else {
+            Value::Flag(parse_bool(value).map_err(SettingError::Invalid)? != inverted)
+        };
+        match self.entries.iter_mut().find(|(option, _)| *option == long) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((long, value)),
+        }
+        Ok(())
+    }
+
+    /// Drops the settings ruled out by options given on the command line,
+    /// so that `-v` wins over `quiet = on` and `-a` over `logfile`
+    fn yield_to(&mut self, command_line: &ArgMatches) {
+        let command = Cli::command();
+        let given =
+            |id: &Id| command_line.value_source(id.as_str()) == Some(ValueSource::CommandLine);
+        self.entries.retain(|(long, _)| {
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long))
+            else {
+                return true;
+            };
+            let level_given = OUTPUT_LEVELS.contains(&long.as_str())
+                && command
+                    .get_arguments()
+                    .filter(|other| other.get_long().is_some_and(|l| OUTPUT_LEVELS.contains(&l)))
+                    .any(|other| given(other.get_id()));
+            // Conflicts are declared on one of the two options only
+            let conflict_given = command.get_arguments().any(|other| {
+                given(other.get_id())
+                    && (command.get_arg_conflicts_with(arg).contains(&other)
+                        || command.get_arg_conflicts_with(other).contains(&arg))
+            });
+            !level_given && !conflict_given
+        });
+    }
+
+    /// The settings as long options, to go ahead of the command line's own
+    pub fn args(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|(long, value)| match value {
+                Value::Flag(true) => Some(format!("--{}", long)),
+                Value::Flag(false) => None,
+                Value::Text(text) => Some(format!("--{}={}", long, text)),
+            })
+            .collect()
+    }
+}
+
+/// Why a setting could not be applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingError {
+    /// No option goes by this key
+    Unknown(String),
+    Invalid(String),
+}
+
+impl fmt::Display for SettingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingError::Unknown(key) => write!(f, "Unknown setting {:?}", key),
+            SettingError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Parses `value` as the option would be on the command line, and runs the
+/// checks of `Cli::validate` that need no other option, so that a bad value
+/// is reported with the file and line (or `-e`) it came from
+fn check_value(command: &Command, arg: &Arg, value: &str) -> Result<(), String> {
+    let long = arg.get_long().unwrap_or_default();
+    let parsed = command
+        .clone()
+        .try_get_matches_from(["wget".to_string(), format!("--{}={}", long, value)]);
+    // Only the value matters here, not what the option requires
+    if let Err(e) = parsed
+        && matches!(e.kind(), ErrorKind::InvalidValue | ErrorKind::ValueValidation)
+    {
+        let expected: Vec<_> = arg
+            .get_possible_values()
+            .iter()
+            .map(|possible| possible.get_name().to_string())
+            .collect();
+        return Err(match e.source() {
+            Some(cause) => format!("Invalid value {:?}: {}", value, cause),
+            None if !expected.is_empty() => format!(
+                "Invalid value {:?}, expected one of {}",
+                value,
+                expected.join(", ")
+            ),
+            None => format!("Invalid value {:?}", value),
+        });
+    }
+    Cli::check_value(long, value)
+}
+
+/// The long option whose name, without dashes, is `name`
+fn option_for(name: &str) -> Option<String> {
+    Cli::command()
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .filter(|long| !COMMAND_LINE_ONLY.contains(long))
+        .find(|long| long.replace('-', "") == name)
+        .map(str::to_string)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "yes" | "true" | "1" => Ok(true),
+        "off" | "no" | "false" | "0" => Ok(false),
+        _ => Err(format!("{:?} is not on or off", value)),
+    }
+}
+
+/// The startup files `args` asks for, lowest precedence first: none with
+/// `--no-config`, only the `--config` file when given, otherwise
+/// `/etc/wgetrc` and `$WGETRC` (or `~/.wgetrc`) when they exist
+pub fn startup_files(args: &Cli) -> Result<Vec<PathBuf>, String> {
+    if args.no_config {
+        return Ok(Vec::new());
+    }
+    if let Some(config) = &args.config {
+        return Ok(vec![config.clone()]);
+    }
+    let mut files: Vec<PathBuf> = Vec::new();
+    if Path::new(SYSTEM_WGETRC).is_file() {
+        files.push(SYSTEM_WGETRC.into());
+    }
+    if let Some(wgetrc) = std::env::var_os("WGETRC") {
+        let wgetrc = PathBuf::from(wgetrc);
+        if !wgetrc.is_file() {
+            return Err(format!(
+                "WGETRC points to {}, which does not exist",
+                wgetrc.display()
+            ));
+        }
+        files.push(wgetrc);
+    } else if let Some(home) = std::env::var_os("HOME") {
+        let wgetrc = Path::new(&home).join(".wgetrc");
+        if wgetrc.is_file() {
+            files.push(wgetrc);
+        }
+    }
+    Ok(files)
+}
+
+/// `args` with the startup files and `-e` commands beneath it: the command
+/// line wins over `-e`, which wins over the user file, then the system file
+pub fn load(args: Cli) -> Result<Cli, String> {
+    let files = startup_files(&args)?;
+    load_from(args, &files, std::env::args_os().collect())
+}
+
+/// `load` with the startup files and the command line `argv` (which parsed
+/// to `args`) given
+fn load_from(args: Cli, files: &[PathBuf], argv: Vec<OsString>) -> Result<Cli, String> {
+    let mut settings = Settings::default();
+    for file in files {
+        for warning in settings.read(file)? {
+            if args.verbose || args.debug {
+                eprintln!("{}", warning);
+            }
+        }
+    }
+    for command in &args.execute {
+        settings
+            .apply(command)
+            .map_err(|e| format!("-e {:?}: {}", command, e))?;
+    }
+    settings.yield_to(&Cli::command_with_negations().get_matches_from(&argv));
+    if settings.is_empty() {
+        return Ok(args);
+    }
+    let mut command_line = argv.into_iter();
+    let argv: Vec<OsString> = command_line
+        .next()
+        .into_iter()
+        .chain(settings.args().into_iter().map(OsString::from))
+        .chain(command_line)
+        .collect();
+    Ok(Cli::parse_with_negations(argv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wgetrc_lines_map_onto_options() {
+        let path = std::env::temp_dir().join(format!("wgetrc-test-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# retries\ntries = 5\n\nlimit_rate = 200k\ncheck_certificate = off\nContinue = on\nverbose = on\n",
+        )
+        .unwrap();
+        let mut settings = Settings::default();
+        settings.read(&path).unwrap();
+        settings.apply("verbose = off").unwrap();
+        settings.apply("progress=dot:mega").unwrap();
+        assert_eq!(
+            settings.args(),
+            [
+                "--tries=5",
+                "--rate-limit=200k",
+                "--no-check-certificate",
+                "--continue-download",
+                "--progress=dot:mega"
+            ]
+        );
+
+        std::fs::write(&path, "tries = 5\nfrobnicate = on\n").unwrap();
+        let mut settings = Settings::default();
+        let ignored = settings.read(&path).unwrap();
+        assert!(ignored[0].ends_with(":2: ignoring unknown setting \"frobnicate\""));
+        assert_eq!(settings.args(), ["--tries=5"]);
+        assert!(Settings::default().apply("quiet = maybe").is_err());
+        assert!(Settings::default().apply("config = other").is_err());
+        assert!(Settings::default().apply("timeout = -1").is_err());
+
+        // Bad values are reported where they were written, not as options
+        std::fs::write(&path, "tries = 5\ntries = many\n").unwrap();
+        let error = Settings::default().read(&path).unwrap_err();
+        assert!(error.ends_with(":2: Invalid value \"many\": invalid digit found in string"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_precedence_is_system_user_execute_command_line() {
+        let dir = std::env::temp_dir().join(format!("wgetrc-precedence-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (system, user) = (dir.join("wgetrc"), dir.join(".wgetrc"));
+        std::fs::write(
+            &system,
+            "waitretry = 7\ntimeout = 10\ndns_timeout = 10\ntries = 10\nverbose = on\ncontinue = on\n",
+        )
+        .unwrap();
+        std::fs::write(&user, "timeout = 20\ndns_timeout = 20\ntries = 20\n").unwrap();
+        let argv: Vec<OsString> = [
+            "wget",
+            "-e",
+            "dns_timeout = 30",
+            "-e",
+            "tries = 30",
+            "--tries=40",
+            "--no-verbose",
+            "http://example.com/",
+        ]
+        .map(OsString::from)
+        .into();
+
+        let args = load_from(Cli::parse_with_negations(&argv), &[system, user], argv).unwrap();
+        assert_eq!(args.waitretry, 7);
+        assert_eq!(args.timeout, Some(20.0));
+        assert_eq!(args.dns_timeout, Some(30.0));
+        assert_eq!(args.tries, 40);
+        // Turned on by the system file, off again on the command line
+        assert!(!args.verbose);
+        assert!(args.continue_download);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
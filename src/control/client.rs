@@ -0,0 +1,24 @@
+use crate::control::protocol::Request;
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// Sends `request` to the session listening on `socket` and returns its
+/// JSON reply
+pub async fn send(socket: &Path, request: &Request) -> io::Result<String> {
+    let stream = UnixStream::connect(socket).await?;
+    let (reader, mut writer) = stream.into_split();
+    let line = serde_json::to_string(request).map_err(io::Error::other)?;
+    writer.write_all(format!("{}\n", line).as_bytes()).await?;
+
+    let mut reply = String::new();
+    BufReader::new(reader).read_line(&mut reply).await?;
+    if reply.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the session closed the connection",
+        ));
+    }
+    Ok(reply.trim_end().to_string())
+}
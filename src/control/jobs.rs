@@ -0,0 +1,469 @@
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::io::InputEntry;
+use crate::output::{Event, EventLog};
+use serde::Serialize;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+
+/// The downloads of a run as `wget ctl` sees them: their state and
+/// progress, the pause switch, the URLs added while the run goes on and
+/// whether a signal asked the run to stop. Outcomes are also emitted to
+/// the run's `EventLog`. Clones share everything.
+#[derive(Debug, Clone)]
+pub struct Jobs {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    jobs: Mutex<Vec<Job>>,
+    paused: watch::Sender<bool>,
+    /// Cancelled by the first SIGINT or SIGTERM
+    interrupted: CancellationToken,
+    /// That signal's number
+    signal: AtomicI32,
+    /// Cancelled by a second one: stop waiting for the transfers
+    aborted: CancellationToken,
+    added_tx: mpsc::UnboundedSender<(JobHandle, InputEntry)>,
+    added_rx: tokio::sync::Mutex<mpsc::UnboundedReceiver<(JobHandle, InputEntry)>>,
+    events: EventLog,
+    /// Requests sent again after a transient failure
+    retries: AtomicU64,
+    /// Bytes not downloaded because they were already on disk (`-c`,
+    /// files a `--session` had finished)
+    skipped_bytes: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Job {
+    id: u64,
+    url: String,
+    /// Where the download is saved, once known
+    path: Option<PathBuf>,
+    state: JobState,
+    bytes: Arc<AtomicU64>,
+    started: Option<Instant>,
+    /// Set when the download ended
+    duration: Option<Duration>,
+    error: Option<DownloadError>,
+    cancel: CancellationToken,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a download slot
+    Queued,
+    Running,
+    Done,
+    Failed,
+    /// Left out on purpose (`-Q`, `--max-filesize`)
+    Skipped,
+    Cancelled,
+    /// Stopped by SIGINT or SIGTERM
+    Interrupted,
+    /// Stopped because the program reading `-O -` closed the pipe; not a
+    /// failure, as the reader got all it wanted
+    Closed,
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.pad(match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Skipped => "skipped",
+            JobState::Cancelled => "cancelled",
+            JobState::Interrupted => "interrupted",
+            JobState::Closed => "closed",
+        })
+    }
+}
+
+/// One line of `wget ctl status` and of `--report`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub state: JobState,
+    pub bytes: u64,
+    /// Time since the download started, or until it ended
+    pub duration_ms: u64,
+    /// Average speed since the download started, in bytes per second
+    pub speed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new(EventLog::default())
+    }
+}
+
+impl Jobs {
+    /// Registry whose downloads report their outcome to `events`
+    pub fn new(events: EventLog) -> Self {
+        let (added_tx, added_rx) = mpsc::unbounded_channel();
+        Self {
+            inner: Arc::new(Inner {
+                jobs: Mutex::default(),
+                paused: watch::Sender::new(false),
+                interrupted: CancellationToken::new(),
+                aborted: CancellationToken::new(),
+                signal: AtomicI32::new(0),
+                added_tx,
+                added_rx: tokio::sync::Mutex::new(added_rx),
+                events,
+                retries: AtomicU64::new(0),
+                skipped_bytes: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn events(&self) -> &EventLog {
+        &self.inner.events
+    }
+
+    pub fn count_retry(&self) {
+        self.inner.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn retries(&self) -> u64 {
+        self.inner.retries.load(Ordering::Relaxed)
+    }
+
+    /// Counts `bytes` that were already on disk and not downloaded again
+    pub fn add_skipped_bytes(&self, bytes: u64) {
+        self.inner.skipped_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// `add_skipped_bytes` for a whole file left as it is
+    pub fn add_skipped_file(&self, path: &Path) {
+        self.add_skipped_bytes(std::fs::metadata(path).map_or(0, |metadata| metadata.len()));
+    }
+
+    pub fn skipped_bytes(&self) -> u64 {
+        self.inner.skipped_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Registers a queued download of `url`
+    pub fn add(&self, url: &str) -> JobHandle {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        let job = Job {
+            id: jobs.len() as u64 + 1,
+            url: url.to_string(),
+            path: None,
+            state: JobState::Queued,
+            bytes: Arc::default(),
+            started: None,
+            duration: None,
+            error: None,
+            cancel: CancellationToken::new(),
+        };
+        let handle = JobHandle {
+            id: job.id,
+            cancel: job.cancel.clone(),
+            jobs: self.clone(),
+        };
+        jobs.push(job);
+        handle
+    }
+
+    /// Registers `url` and hands it to the running scheduler (`wget ctl add`)
+    pub fn enqueue(&self, url: &str) -> u64 {
+        let handle = self.add(url);
+        let id = handle.id;
+        let entry = InputEntry {
+            url: url.to_string(),
+            checksum: None,
+        };
+        // The receiver lives as long as `self`, so this cannot fail
+        let _ = self.inner.added_tx.send((handle, entry));
+        id
+    }
+
+    /// URLs added since the scheduler last looked, or `None` while another
+    /// scheduler is taking them
+    pub fn added(
+        &self,
+    ) -> Option<tokio::sync::MutexGuard<'_, mpsc::UnboundedReceiver<(JobHandle, InputEntry)>>> {
+        self.inner.added_rx.try_lock().ok()
+    }
+
+    /// Whether URLs were added that no scheduler has picked up yet
+    pub fn has_added(&self) -> bool {
+        self.inner
+            .added_rx
+            .try_lock()
+            .is_ok_and(|added| !added.is_empty())
+    }
+
+    /// Byte counter of the running download of `url`
+    pub fn progress(&self, url: &str) -> Option<Arc<AtomicU64>> {
+        let jobs = self.inner.jobs.lock().unwrap();
+        jobs.iter()
+            .rev()
+            .find(|job| job.url == url && job.state == JobState::Running)
+            .map(|job| job.bytes.clone())
+    }
+
+    /// Pause switch, checked by every transfer before passing on bytes
+    pub fn paused(&self) -> watch::Receiver<bool> {
+        self.inner.paused.subscribe()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.inner.paused.borrow()
+    }
+
+    /// Holds (or releases) all transfers and keeps new ones from starting
+    pub fn set_paused(&self, paused: bool) {
+        self.inner.paused.send_replace(paused);
+    }
+
+    /// Stops the run after `signal`: no new requests are sent and running
+    /// transfers end at their next chunk. Returns false if the run was
+    /// already interrupted.
+    pub fn interrupt(&self, signal: i32) -> bool {
+        if self.is_interrupted() {
+            return false;
+        }
+        self.inner.signal.store(signal, Ordering::Relaxed);
+        self.inner.interrupted.cancel();
+        true
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.inner.interrupted.is_cancelled()
+    }
+
+    /// The signal that interrupted the run
+    pub fn interrupt_signal(&self) -> Option<i32> {
+        self.is_interrupted()
+            .then(|| self.inner.signal.load(Ordering::Relaxed))
+    }
+
+    /// Completes once the run is interrupted
+    pub fn interrupted(&self) -> WaitForCancellationFuture<'_> {
+        self.inner.interrupted.cancelled()
+    }
+
+    /// Gives up on an interrupted run without waiting for its transfers to
+    /// stop; the command line then cleans up and exits
+    pub fn abort(&self) {
+        self.inner.aborted.cancel();
+    }
+
+    /// Completes once the run is aborted
+    pub fn aborted(&self) -> WaitForCancellationFuture<'_> {
+        self.inner.aborted.cancelled()
+    }
+
+    pub fn interrupt_token(&self) -> CancellationToken {
+        self.inner.interrupted.clone()
+    }
+
+    /// Fails with an `Interrupted` error once the run is interrupted, for
+    /// checks before starting anything new
+    pub fn check_interrupted(&self) -> Result<(), DownloadError> {
+        match self.is_interrupted() {
+            true => Err(DownloadError::interrupted()),
+            false => Ok(()),
+        }
+    }
+
+    /// Cancels a queued or running download. Returns false for unknown ids
+    /// and downloads that already ended.
+    pub fn cancel(&self, id: u64) -> bool {
+        let jobs = self.inner.jobs.lock().unwrap();
+        match jobs.iter().find(|job| job.id == id) {
+            Some(job) if matches!(job.state, JobState::Queued | JobState::Running) => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn status(&self) -> Vec<JobStatus> {
+        let jobs = self.inner.jobs.lock().unwrap();
+        jobs.iter()
+            .map(|job| {
+                let bytes = job.bytes.load(Ordering::Relaxed);
+                let duration = job.duration().unwrap_or_default();
+                let seconds = duration.as_secs_f64();
+                JobStatus {
+                    id: job.id,
+                    url: job.url.clone(),
+                    path: job.path.clone(),
+                    state: job.state,
+                    bytes,
+                    duration_ms: duration.as_millis() as u64,
+                    speed: if seconds > 0.0 {
+                        (bytes as f64 / seconds) as u64
+                    } else {
+                        0
+                    },
+                    error_kind: job.error.as_ref().map(|e| e.kind),
+                    error: job.error.as_ref().map(|e| e.message.clone()),
+                }
+            })
+            .collect()
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Job)) {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            update(job);
+        }
+    }
+}
+
+impl Job {
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.started.map(|started| started.elapsed()))
+    }
+}
+
+/// A registered download, held by the task that performs it
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    id: u64,
+    cancel: CancellationToken,
+    jobs: Jobs,
+}
+
+impl JobHandle {
+    /// Waits while the run is paused, then marks the download as running.
+    /// Call this once the download has its slot.
+    pub async fn start(&self) {
+        let mut paused = self.jobs.paused();
+        tokio::select! {
+            biased;
+            // Never started: the download fails at once and stays queued
+            _ = self.jobs.interrupted() => return,
+            _ = paused.wait_for(|paused| !paused) => {}
+        }
+        self.jobs.update(self.id, |job| {
+            job.state = JobState::Running;
+            job.started = Some(Instant::now());
+        });
+    }
+
+    /// Runs `download` until it ends or the job is cancelled, and records
+    /// the outcome
+    pub async fn run<F>(&self, download: F) -> Result<u64, DownloadError>
+    where
+        F: Future<Output = Result<u64, DownloadError>>,
+    {
+        let outcome = tokio::select! {
+            outcome = download => outcome,
+            _ = self.cancel.cancelled() => Err(DownloadError {
+                kind: ErrorKind::Cancelled,
+                message: "Cancelled by wget ctl".to_string(),
+            }),
+        };
+        self.record(&outcome);
+        outcome
+    }
+
+    /// Where the download is saved, for `status`, events and `--report`
+    pub fn set_path(&self, path: &Path) {
+        self.jobs
+            .update(self.id, |job| job.path = Some(path.to_path_buf()));
+    }
+
+    /// Records how the download ended and emits it as an event; `run` does
+    /// this for downloads it performs
+    pub fn record(&self, outcome: &Result<u64, DownloadError>) {
+        let cancelled = self.cancel.is_cancelled();
+        let mut event = None;
+        self.jobs.update(self.id, |job| {
+            job.duration = job.started.map(|started| started.elapsed());
+            match outcome {
+                Ok(bytes) => {
+                    job.state = JobState::Done;
+                    job.bytes.store(*bytes, Ordering::Relaxed);
+                }
+                Err(e) if cancelled => {
+                    job.state = JobState::Cancelled;
+                    job.error = Some(e.clone());
+                }
+                Err(e) if e.kind == ErrorKind::Interrupted && job.state == JobState::Queued => {}
+                Err(e) => {
+                    job.state = match e.kind {
+                        ErrorKind::Skipped => JobState::Skipped,
+                        ErrorKind::Interrupted => JobState::Interrupted,
+                        ErrorKind::OutputClosed => JobState::Closed,
+                        _ => JobState::Failed,
+                    };
+                    job.error = Some(e.clone());
+                }
+            }
+            let (url, path) = (job.url.clone(), job.path.clone());
+            let bytes = job.bytes.load(Ordering::Relaxed);
+            let duration_ms = job.duration.unwrap_or_default().as_millis() as u64;
+            event = Some(match outcome {
+                Ok(_) => Event::Completed {
+                    url,
+                    path,
+                    bytes,
+                    duration_ms,
+                },
+                Err(e) => Event::Failed {
+                    url,
+                    path,
+                    bytes,
+                    duration_ms,
+                    error_kind: e.kind,
+                    error: e.message.clone(),
+                },
+            });
+        });
+        if let Some(event) = event {
+            self.jobs.events().emit(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jobs_can_be_cancelled_while_queued() {
+        let jobs = Jobs::default();
+        let first = jobs.add("http://example.com/a");
+        let second = jobs.add("http://example.com/b");
+
+        first.start().await;
+        assert_eq!(first.run(async { Ok(10) }).await.unwrap(), 10);
+
+        assert!(jobs.cancel(2));
+        let outcome = second
+            .run(async {
+                second.start().await;
+                std::future::pending().await
+            })
+            .await;
+        assert_eq!(outcome.unwrap_err().kind, ErrorKind::Cancelled);
+        assert!(!jobs.cancel(2));
+
+        let states: Vec<JobState> = jobs.status().iter().map(|job| job.state).collect();
+        assert_eq!(states, [JobState::Done, JobState::Cancelled]);
+        assert_eq!(jobs.status()[0].bytes, 10);
+    }
+}
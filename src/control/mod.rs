@@ -0,0 +1,8 @@
+pub mod client;
+pub mod jobs;
+pub mod protocol;
+pub mod server;
+
+pub use jobs::{JobHandle, Jobs};
+pub use protocol::Request;
+pub use server::ControlSocket;
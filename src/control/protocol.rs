@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// A `wget ctl` command, sent to the control socket as one line of JSON,
+/// e.g. `{"command":"cancel","id":3}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Pause,
+    Resume,
+    Cancel { id: u64 },
+    Add { url: String },
+    SetRate { rate: String },
+}
+
+/// Reply to any request other than `status`: `{"ok":true}`, plus the id of
+/// an added download or the reason a request was refused
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(message.into()),
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::control::jobs::JobStatus;
+use crate::control::protocol::{Reply, Request};
+use crate::download::DownloadOptions;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Answer to `status`
+#[derive(Serialize)]
+struct Status {
+    ok: bool,
+    paused: bool,
+    /// Combined limit in bytes per second; 0 means unlimited
+    rate_limit: u64,
+    /// Bytes downloaded by the whole run
+    downloaded: u64,
+    jobs: Vec<JobStatus>,
+}
+
+/// Unix socket through which `wget ctl` steers a running download session.
+/// The socket file is removed when this is dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listens on `path` and serves requests against the shared jobs, rate
+    /// limits and quota of `options` until the process ends
+    pub fn bind(path: &Path, options: DownloadOptions) -> io::Result<Self> {
+        // A socket left behind by a run that crashed would block the bind
+        if path.exists() && std::os::unix::net::UnixStream::connect(path).is_err() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, options.clone()));
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads one request per line and writes one JSON reply per line
+async fn serve(stream: UnixStream, options: DownloadOptions) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle(request, &options),
+            Err(e) => json(&Reply::error(format!("Invalid request: {}", e))),
+        };
+        if writer
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+fn handle(request: Request, options: &DownloadOptions) -> String {
+    let jobs = &options.jobs;
+    match request {
+        Request::Status => json(&Status {
+            ok: true,
+            paused: jobs.is_paused(),
+            rate_limit: options.rate_limits.total_rate(),
+            downloaded: options.quota.used(),
+            jobs: jobs.status(),
+        }),
+        Request::Pause => {
+            jobs.set_paused(true);
+            json(&Reply::ok())
+        }
+        Request::Resume => {
+            jobs.set_paused(false);
+            json(&Reply::ok())
+        }
+        Request::Cancel { id } if jobs.cancel(id) => json(&Reply::ok()),
+        Request::Cancel { id } => json(&Reply::error(format!(
+            "No queued or running download with id {}",
+            id
+        ))),
+        Request::Add { url } => match url::Url::parse(&url) {
+            Ok(_) => json(&Reply {
+                id: Some(jobs.enqueue(&url)),
+                ..Reply::ok()
+            }),
+            Err(e) => json(&Reply::error(format!("Invalid URL {:?}: {}", url, e))),
+        },
+        Request::SetRate { rate } => match options.rate_limits.set_total(&rate) {
+            Ok(()) => json(&Reply::ok()),
+            Err(e) => json(&Reply::error(format!("Rate limit: {}", e))),
+        },
+    }
+}
+
+fn json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("control replies serialise")
+}
@@ -1,43 +1,93 @@
+use crate::checksum::Checksum;
+use crate::control::{JobHandle, Jobs};
+use crate::download::DownloadOptions;
 use crate::download::progress::MultiProgressManager;
-use crate::http::client::{DownloadError, HttpClient};
-use crate::utils::url::extract_filename;
+use crate::http::client::{DownloadError, ErrorKind, Fetched, HttpClient};
+use crate::http::compression::BodyStream;
+use crate::http::segments::SegmentTable;
+use crate::io::InputEntry;
+use crate::resume::{ResumeHandler, ResumeValidators};
+use crate::session::Session;
+use crate::utils::url::{extract_filename, host_key};
 use futures_util::StreamExt;
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 
 /// Result of a single download operation
 #[derive(Debug)]
 pub struct DownloadResult {
     pub url: String,
-    #[allow(dead_code)]
     pub file_path: PathBuf,
     pub bytes_downloaded: u64,
     pub success: bool,
-    pub error: Option<String>,
+    pub error: Option<DownloadError>,
+}
+
+impl DownloadResult {
+    /// Not downloaded on purpose (`--max-filesize`, `-Q`) rather than failed
+    pub fn skipped(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|e| e.kind == ErrorKind::Skipped)
+    }
+
+    /// Stopped, or never started, because a signal interrupted the run
+    pub fn interrupted(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|e| e.kind == ErrorKind::Interrupted)
+    }
 }
 
 /// Manages concurrent downloads with progress tracking
+#[derive(Clone)]
 pub struct ConcurrentDownloadManager {
     http_client: HttpClient,
     progress_manager: Arc<MultiProgressManager>,
     max_concurrent: usize,
+    max_per_host: Option<usize>,
+    /// Continue partial files (`-c`)
+    resume: bool,
+    segments: usize,
+    /// Registry `wget ctl` reads and steers
+    jobs: Jobs,
+    session: Option<Session>,
 }
 
 impl ConcurrentDownloadManager {
-    pub fn new(max_concurrent: usize) -> Self {
+    pub fn new(options: &DownloadOptions) -> Result<Self, DownloadError> {
+        Ok(Self {
+            http_client: HttpClient::from_options(options)?,
+            progress_manager: Arc::new(MultiProgressManager::new(options.progress)),
+            max_concurrent: options.max_concurrent,
+            max_per_host: options.max_per_host,
+            resume: options.resume,
+            segments: options.segments,
+            jobs: options.jobs.clone(),
+            session: options.session.clone(),
+        })
+    }
+
+    /// This manager, with its HTTP client and connections, for a run that
+    /// registers its downloads in `jobs`
+    pub fn with_jobs(&self, jobs: Jobs) -> Self {
         Self {
-            http_client: HttpClient::new(),
-            progress_manager: Arc::new(MultiProgressManager::new()),
-            max_concurrent,
+            http_client: self.http_client.clone().with_jobs(jobs.clone()),
+            jobs,
+            ..self.clone()
         }
     }
 
-    /// Downloads multiple URLs concurrently with separated request/download phases
+    /// Downloads multiple URLs, at most `max_concurrent` at a time. A request
+    /// is only sent once a slot is free; results are in input order.
     pub async fn download_urls(
         &self,
-        urls: Vec<String>,
+        urls: Vec<InputEntry>,
         output_dir: Option<&Path>,
     ) -> Vec<DownloadResult> {
         self.download_urls_internal(urls, output_dir, false).await
@@ -46,7 +96,7 @@ impl ConcurrentDownloadManager {
     /// Silent version for background downloads
     pub async fn download_urls_silent(
         &self,
-        urls: Vec<String>,
+        urls: Vec<InputEntry>,
         output_dir: Option<&Path>,
     ) -> Vec<DownloadResult> {
         self.download_urls_internal(urls, output_dir, true).await
@@ -54,110 +104,54 @@ impl ConcurrentDownloadManager {
 
     async fn download_urls_internal(
         &self,
-        urls: Vec<String>,
+        urls: Vec<InputEntry>,
         output_dir: Option<&Path>,
         silent: bool,
     ) -> Vec<DownloadResult> {
-        // Phase 1: Send all requests and collect responses
-        if !silent {
-            println!("Phase 1: Sending requests...");
-        }
-        let mut valid_responses = Vec::new();
-        let mut results = Vec::new();
+        let slots = Arc::new(Semaphore::new(self.max_concurrent.max(1)));
+        let mut host_slots: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        let mut tasks = VecDeque::with_capacity(urls.len());
 
-        for url in &urls {
-            if !silent {
-                println!("sending request to {}, awaiting response...", url);
+        if let Some(session) = &self.session {
+            session.add_pending(urls.iter().map(|entry| entry.url.as_str()));
+        }
+        for entry in urls {
+            let job = self.jobs.add(&entry.url);
+            tasks.push_back(self.spawn_download(entry, job, output_dir, silent, &slots, &mut host_slots));
+        }
+        // URLs added with `wget ctl add` join the queue while it runs
+        let mut added = self.jobs.added();
+        if let Some(added) = added.as_mut() {
+            while let Ok((job, entry)) = added.try_recv() {
+                tasks.push_back(self.spawn_download(entry, job, output_dir, silent, &slots, &mut host_slots));
             }
+        }
 
-            match self.http_client.download_silent(&url).await {
-                Ok(response) => {
-                    let status = response.status();
-                    if !silent {
-                        println!(
-                            "status {} {} for {}",
-                            status.as_u16(),
-                            status.canonical_reason().unwrap_or(""),
-                            url
-                        );
-                    }
-
-                    if status.is_success() {
-                        let content_length = response.content_length().unwrap_or(0);
-                        let file_path = Self::determine_file_path(url, output_dir.as_deref());
-                        valid_responses.push((url.clone(), response, content_length, file_path));
-                    } else {
-                        // Failed response - add to results as failed
-                        results.push(DownloadResult {
-                            url: url.clone(),
-                            file_path: Self::determine_file_path(url, output_dir.as_deref()),
-                            bytes_downloaded: 0,
-                            success: false,
-                            error: Some(format!("HTTP error: {}", status)),
-                        });
-                    }
+        // Collect download results in input order
+        let mut results = Vec::with_capacity(tasks.len());
+        while let Some((job, url, file_path, mut task)) = tasks.pop_front() {
+            let joined = loop {
+                let Some(added) = added.as_mut() else {
+                    break (&mut task).await;
+                };
+                tokio::select! {
+                    joined = &mut task => break joined,
+                    Some((job, entry)) = added.recv() => tasks.push_back(
+                        self.spawn_download(entry, job, output_dir, silent, &slots, &mut host_slots),
+                    ),
                 }
+            };
+            match joined {
+                Ok(result) => results.push(result),
+                // The task never got to record its outcome: do it here, so
+                // the download counts as failed and emits its event
                 Err(e) => {
-                    if !silent {
-                        println!("failed to connect to {}: {}", url, e);
-                    }
-                    // Failed request - add to results as failed
-                    results.push(DownloadResult {
-                        url: url.clone(),
-                        file_path: Self::determine_file_path(url, output_dir.as_deref()),
-                        bytes_downloaded: 0,
-                        success: false,
-                        error: Some(e.message),
+                    let outcome = Err(DownloadError {
+                        kind: ErrorKind::Io,
+                        message: format!("Task panicked: {}", e),
                     });
-                }
-            }
-        }
-
-        // Phase 2: Download all valid responses concurrently
-        if !valid_responses.is_empty() {
-            if !silent {
-                println!(
-                    "\nPhase 2: Starting {} concurrent downloads...",
-                    valid_responses.len()
-                );
-            }
-
-            let semaphore = Arc::new(tokio::sync::Semaphore::new(self.max_concurrent));
-            let mut download_tasks = Vec::new();
-
-            for (url, response, content_length, file_path) in valid_responses {
-                let semaphore = semaphore.clone();
-                let progress_manager = self.progress_manager.clone();
-
-                let task = tokio::spawn(async move {
-                    let _permit = semaphore.acquire().await.unwrap();
-                    Self::download_from_response(
-                        url,
-                        response,
-                        content_length,
-                        file_path,
-                        progress_manager,
-                    )
-                    .await
-                });
-
-                download_tasks.push(task);
-            }
-
-            // Collect download results
-            for task in download_tasks {
-                match task.await {
-                    Ok(result) => results.push(result),
-                    Err(e) => {
-                        eprintln!("Download task failed: {}", e);
-                        results.push(DownloadResult {
-                            url: "unknown".to_string(),
-                            file_path: PathBuf::new(),
-                            bytes_downloaded: 0,
-                            success: false,
-                            error: Some(format!("Task panicked: {}", e)),
-                        });
-                    }
+                    job.record(&outcome);
+                    results.push(self.report(url, file_path, outcome, silent).await);
                 }
             }
         }
@@ -165,61 +159,67 @@ impl ConcurrentDownloadManager {
         results
     }
 
-    /// Downloads from an already-received HTTP response
-    async fn download_from_response(
-        url: String,
-        response: reqwest::Response,
-        content_length: u64,
-        file_path: PathBuf,
-        progress_manager: Arc<MultiProgressManager>,
-    ) -> DownloadResult {
-        match Self::perform_download_from_response(
-            &url,
-            &file_path,
-            response,
-            content_length,
-            &progress_manager,
-        )
-        .await
-        {
-            Ok(bytes_downloaded) => {
-                progress_manager.finish_download(&url, true).await;
-                println!("Downloaded [{}] -> {}", url, file_path.display());
-                DownloadResult {
-                    url,
-                    file_path,
-                    bytes_downloaded,
-                    success: true,
-                    error: None,
-                }
-            }
-            Err(e) => {
-                progress_manager.finish_download(&url, false).await;
-                eprintln!("Failed to download [{}]: {}", url, e);
-                DownloadResult {
-                    url,
-                    file_path,
-                    bytes_downloaded: 0,
-                    success: false,
-                    error: Some(e.message),
-                }
+    /// Starts the task for one URL. It waits for a slot (and for its host)
+    /// before sending a request.
+    fn spawn_download(
+        &self,
+        InputEntry { url, checksum }: InputEntry,
+        job: JobHandle,
+        output_dir: Option<&Path>,
+        silent: bool,
+        slots: &Arc<Semaphore>,
+        host_slots: &mut HashMap<String, Arc<Semaphore>>,
+    ) -> (JobHandle, String, PathBuf, JoinHandle<DownloadResult>) {
+        let file_path = Self::determine_file_path(&url, output_dir);
+        job.set_path(&file_path);
+        let host_slot = self.max_per_host.map(|limit| {
+            host_slots
+                .entry(host_key(&url))
+                .or_insert_with(|| Arc::new(Semaphore::new(limit.max(1))))
+                .clone()
+        });
+        let slots = slots.clone();
+        let manager = self.clone();
+
+        let task = tokio::spawn({
+            let (job, url, file_path) = (job.clone(), url.clone(), file_path.clone());
+            async move {
+                let outcome = job
+                    .run(async {
+                        // Wait for the host first so URLs queued behind a busy
+                        // host do not hold slots other hosts could use
+                        let _host_permit = match &host_slot {
+                            Some(host_slot) => Some(host_slot.acquire().await.unwrap()),
+                            None => None,
+                        };
+                        let _permit = slots.acquire().await.unwrap();
+                        job.start().await;
+                        manager
+                            .perform_download(&url, &file_path, checksum.as_ref(), silent)
+                            .await
+                    })
+                    .await;
+                manager.report(url, file_path, outcome, silent).await
             }
-        }
+        });
+        (job, url, file_path, task)
     }
 
-    #[allow(dead_code)]
-    async fn download_single_url(
+    /// Prints the outcome of one download and turns it into its result
+    async fn report(
+        &self,
         url: String,
-        http_client: HttpClient,
-        progress_manager: Arc<MultiProgressManager>,
-        output_dir: Option<PathBuf>,
+        file_path: PathBuf,
+        outcome: Result<u64, DownloadError>,
+        silent: bool,
     ) -> DownloadResult {
-        let file_path = Self::determine_file_path(&url, output_dir.as_deref());
-
-        match Self::perform_download(&url, &file_path, &http_client, &progress_manager).await {
+        match outcome {
             Ok(bytes_downloaded) => {
-                progress_manager.finish_download(&url, true).await;
-                println!("Downloaded [{}] -> {}", url, file_path.display());
+                self.progress_manager.finish_download(&url, true).await;
+                if !silent {
+                    self.progress_manager
+                        .println(&format!("Downloaded [{}] -> {}", url, file_path.display()));
+                }
                 DownloadResult {
                     url,
                     file_path,
@@ -229,99 +229,194 @@ impl ConcurrentDownloadManager {
                 }
             }
             Err(e) => {
-                progress_manager.finish_download(&url, false).await;
-                eprintln!("Failed to download [{}]: {}", url, e);
+                self.progress_manager.finish_download(&url, false).await;
+                match e.kind {
+                    // The partial summary accounts for interrupted downloads
+                    ErrorKind::Interrupted => {}
+                    _ if silent => {}
+                    ErrorKind::Skipped => self
+                        .progress_manager
+                        .println(&format!("Skipped [{}]: {}", url, e.message)),
+                    ErrorKind::Cancelled => {
+                        self.progress_manager.println(&format!("Cancelled [{}]", url))
+                    }
+                    _ => eprintln!("Failed to download [{}]: {}", url, e),
+                }
                 DownloadResult {
                     url,
                     file_path,
                     bytes_downloaded: 0,
                     success: false,
-                    error: Some(e.message),
+                    error: Some(e),
                 }
             }
         }
     }
 
-    #[allow(dead_code)]
+    /// Downloads one URL, through the `--session` journal when there is one
     async fn perform_download(
+        &self,
         url: &str,
         file_path: &Path,
-        http_client: &HttpClient,
-        progress_manager: &MultiProgressManager,
+        checksum: Option<&Checksum>,
+        silent: bool,
     ) -> Result<u64, DownloadError> {
-        let response = http_client.download(url).await?;
-        let content_length = response.content_length().unwrap_or(0);
-
-        // Create progress bar for this download
-        let progress_bar = progress_manager
-            .create_progress_bar(url, content_length)
-            .await;
-
-        let mut file = File::create(file_path).await.map_err(|e| DownloadError {
-            message: format!("Failed to create file {:?}: {}", file_path, e),
-        })?;
-
-        let mut stream = response.bytes_stream();
-        let mut downloaded = 0u64;
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| DownloadError {
-                message: format!("Failed to read chunk: {}", e),
-            })?;
-
-            file.write_all(&chunk).await.map_err(|e| DownloadError {
-                message: format!("Failed to write chunk: {}", e),
-            })?;
+        let Some(session) = &self.session else {
+            return self
+                .fetch(url, file_path, checksum, silent, self.resume)
+                .await
+                .map(|fetched| fetched.bytes);
+        };
+        if session.is_done(url, file_path) {
+            self.jobs.add_skipped_file(file_path);
+            if !silent {
+                self.progress_manager
+                    .println(&format!("Already downloaded in this session: {}", url));
+            }
+            return Ok(0);
+        }
+        session
+            .track(url, file_path, self.resume, |resume| {
+                self.fetch(url, file_path, checksum, silent, resume)
+            })
+            .await
+            .map(|fetched| fetched.bytes)
+    }
 
-            downloaded += chunk.len() as u64;
-            progress_bar.set_position(downloaded);
+    async fn fetch(
+        &self,
+        url: &str,
+        file_path: &Path,
+        checksum: Option<&Checksum>,
+        silent: bool,
+        resume: bool,
+    ) -> Result<Fetched, DownloadError> {
+        self.http_client.quota().check(url)?;
+
+        // Partial files and segmented transfers take the single-file path,
+        // which validates and continues them exactly like command line URLs.
+        // It also reads the `.segments` table, without which the length of
+        // a preallocated file would pass for progress.
+        let partial = resume
+            && (ResumeHandler::get_resume_position(file_path).await > 0
+                || SegmentTable::sidecar_path(file_path).exists());
+        if partial || self.segments > 1 || self.http_client.saves_headers() {
+            return self
+                .http_client
+                .download_to_file_checked(url, file_path, resume, true, checksum)
+                .await;
         }
 
-        file.flush().await.map_err(|e| DownloadError {
-            message: format!("Failed to flush file: {}", e),
-        })?;
+        let response = self.http_client.download_silent(url).await?;
+        let status = response.status();
+        if !silent {
+            self.progress_manager.println(&format!(
+                "status {} {} for {}",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or(""),
+                url
+            ));
+        }
+        if !status.is_success() {
+            return Err(DownloadError {
+                kind: ErrorKind::Http,
+                message: format!("HTTP error: {}", status),
+            });
+        }
 
-        Ok(downloaded)
+        let content_length = if self.http_client.decodes(&response) {
+            0
+        } else {
+            response.content_length().unwrap_or(0)
+        };
+        self.http_client.check_size(url, content_length)?;
+        if content_length > 0 {
+            // Lets a later -c check that the partial file is still current
+            let _ = ResumeValidators::from_response(&response)
+                .save(file_path)
+                .await;
+        }
+        let body = self.http_client.body_stream(response);
+        let fetched = self
+            .perform_download_from_response(url, file_path, body, content_length, checksum, silent)
+            .await?;
+        ResumeValidators::remove(file_path).await;
+        SegmentTable::remove(file_path).await;
+        Ok(fetched)
     }
 
     /// Performs download from an already-received response
     async fn perform_download_from_response(
+        &self,
         url: &str,
         file_path: &Path,
-        response: reqwest::Response,
+        mut stream: BodyStream,
         content_length: u64,
-        progress_manager: &MultiProgressManager,
-    ) -> Result<u64, DownloadError> {
+        checksum: Option<&Checksum>,
+        silent: bool,
+    ) -> Result<Fetched, DownloadError> {
         // Create progress bar for this download
-        let progress_bar = progress_manager
-            .create_progress_bar(url, content_length)
+        let progress_bar = self
+            .progress_manager
+            .create_progress_bar(url, content_length, silent)
             .await;
 
         let mut file = File::create(file_path).await.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to create file {:?}: {}", file_path, e),
         })?;
 
-        let mut stream = response.bytes_stream();
         let mut downloaded = 0u64;
+        let mut hasher = self.http_client.hasher(checksum);
+        let throttle = self.http_client.throttle(url);
 
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| DownloadError {
-                message: format!("Failed to read chunk: {}", e),
-            })?;
-
+            let throttled = match chunk {
+                Ok(chunk) => throttle.consume(chunk.len() as u64).await.map(|()| chunk),
+                Err(e) => Err(DownloadError::read_failed(e)),
+            };
+            let chunk = match throttled {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // Keep what arrived, and its validators, for -c
+                    let _ = file.flush().await;
+                    return Err(e);
+                }
+            };
             file.write_all(&chunk).await.map_err(|e| DownloadError {
+                kind: ErrorKind::Io,
                 message: format!("Failed to write chunk: {}", e),
             })?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
 
             downloaded += chunk.len() as u64;
-            progress_bar.set_position(downloaded);
+            self.http_client.quota().add(chunk.len() as u64);
+            if let Some(ref pb) = progress_bar {
+                pb.set_position(downloaded);
+            }
+            if let Err(e) = self.http_client.check_size(url, downloaded) {
+                drop(file);
+                let _ = tokio::fs::remove_file(file_path).await;
+                return Err(e);
+            }
         }
 
         file.flush().await.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to flush file: {}", e),
         })?;
 
-        Ok(downloaded)
+        let digest = self
+            .http_client
+            .finish_digest(hasher, file_path, checksum)
+            .await?;
+
+        Ok(Fetched {
+            bytes: downloaded,
+            digest,
+        })
     }
 
     fn determine_file_path(url: &str, output_dir: Option<&Path>) -> PathBuf {
@@ -334,8 +429,166 @@ impl ConcurrentDownloadManager {
         }
     }
 
+    /// The HTTP client every download of the manager goes through
+    pub fn client(&self) -> &HttpClient {
+        &self.http_client
+    }
+
     #[allow(dead_code)]
     pub fn get_progress_manager(&self) -> Arc<MultiProgressManager> {
         self.progress_manager.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::Quota;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_requests_wait_for_a_free_slot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let open = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (open_server, peak_server) = (open.clone(), peak.clone());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (open, peak) = (open_server.clone(), peak_server.clone());
+                tokio::spawn(async move {
+                    let now = open.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    let mut buf = vec![0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                        .await;
+                    open.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("wget-scheduler-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entries: Vec<InputEntry> = (0..6)
+            .map(|i| InputEntry {
+                url: format!("http://{}/file{}.txt", addr, i),
+                checksum: None,
+            })
+            .collect();
+        let urls: Vec<String> = entries.iter().map(|entry| entry.url.clone()).collect();
+
+        let options = DownloadOptions {
+            max_per_host: Some(2),
+            ..DownloadOptions::default()
+        };
+        let manager = ConcurrentDownloadManager::new(&options).unwrap();
+        let results = manager.download_urls_silent(entries, Some(&dir)).await;
+
+        assert!(results.iter().all(|result| result.success));
+        assert_eq!(
+            results.iter().map(|result| result.url.clone()).collect::<Vec<_>>(),
+            urls
+        );
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_input_lists_honour_download_options() {
+        // First request fails with 503; the retry must carry the user agent
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let agents = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = agents.clone();
+        tokio::spawn(async move {
+            for attempt in 0.. {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                seen.lock().unwrap().extend(
+                    request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("user-agent: "))
+                        .map(str::to_string),
+                );
+                let response: &[u8] = if attempt == 0 {
+                    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                };
+                let _ = socket.write_all(response).await;
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("wget-options-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = DownloadOptions {
+            user_agent: Some("custom-agent".into()),
+            tries: 2,
+            wait_retry: 0,
+            ..DownloadOptions::default()
+        };
+        let manager = ConcurrentDownloadManager::new(&options).unwrap();
+        let entry = InputEntry {
+            url: format!("http://{}/retry.txt", addr),
+            checksum: None,
+        };
+        let results = manager.download_urls_silent(vec![entry], Some(&dir)).await;
+
+        assert!(results[0].success);
+        assert_eq!(*agents.lock().unwrap(), ["custom-agent", "custom-agent"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quota_and_max_filesize_skip_downloads() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let response = if String::from_utf8_lossy(&buf[..n]).starts_with("GET /big") {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\n{}", "x".repeat(100))
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123456789".to_string()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("wget-quota-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entries: Vec<InputEntry> = ["big", "a", "b", "c"]
+            .iter()
+            .map(|name| InputEntry {
+                url: format!("http://{}/{}.bin", addr, name),
+                checksum: None,
+            })
+            .collect();
+        // One at a time, so the quota is used up after a and b
+        let options = DownloadOptions {
+            max_concurrent: 1,
+            quota: Quota::new(Some(15)),
+            max_filesize: Some(50),
+            ..DownloadOptions::default()
+        };
+        let manager = ConcurrentDownloadManager::new(&options).unwrap();
+        let results = manager.download_urls_silent(entries, Some(&dir)).await;
+
+        let outcome: Vec<(bool, bool)> = results.iter().map(|r| (r.success, r.skipped())).collect();
+        assert_eq!(outcome, [(false, true), (true, false), (true, false), (false, true)]);
+        assert!(!dir.join("big.bin").exists());
+        assert!(!dir.join("c.bin").exists());
+        assert_eq!(options.quota.used(), 20);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
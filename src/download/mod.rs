@@ -1,5 +1,7 @@
 pub mod client;
+pub mod options;
 pub mod progress;
+pub mod quota;
 pub mod resume;
 
 // Concurrent download functionality (for future use)
@@ -7,3 +9,5 @@ pub mod resume;
 pub use client::{ConcurrentDownloadManager, DownloadResult};
 #[allow(unused_imports)]
 pub use progress::MultiProgressManager;
+pub use options::DownloadOptions;
+pub use quota::Quota;
@@ -0,0 +1,110 @@
+use crate::checksum::ChecksumPolicy;
+use crate::cli::Cli;
+use crate::control::Jobs;
+use crate::download::Quota;
+use crate::http::{CompressionConfig, HeaderOutput, ProxyConfig, Timeouts, TlsConfig};
+use crate::output::{EventLog, ProgressDisplay};
+use crate::rate::RateLimits;
+use crate::session::Session;
+use crate::utils::size::parse_size;
+
+/// Settings shared by every download mode, built once from the command line
+/// so that single URLs, `-i` lists, mirrors, Metalink files and background
+/// runs treat the same flags the same way
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// `--rate-limit`, `--limit-rate-per-host` and `--limit-rate-per-download`.
+    /// Clones share the limiters, so every client of a run counts together.
+    pub rate_limits: RateLimits,
+    pub user_agent: Option<String>,
+    /// Attempts per request, including the first (`--tries`)
+    pub tries: u32,
+    /// Seconds to wait before the first retry; later retries wait longer (`--waitretry`)
+    pub wait_retry: u64,
+    pub timeouts: Timeouts,
+    pub proxy: ProxyConfig,
+    pub tls: TlsConfig,
+    pub compression: CompressionConfig,
+    /// `-S`, `--save-headers` and the request headers shown by `-d`
+    pub headers: HeaderOutput,
+    /// `--progress` and `--show-progress`
+    pub progress: ProgressDisplay,
+    /// Continue partially downloaded files (`-c`)
+    pub resume: bool,
+    pub segments: usize,
+    pub checksum_policy: ChecksumPolicy,
+    /// Files downloaded at the same time (`-j`)
+    pub max_concurrent: usize,
+    pub max_per_host: Option<usize>,
+    /// Bytes downloaded across the run, checked against `-Q`. Clones of
+    /// these options share the count.
+    pub quota: Quota,
+    /// Skip files larger than this (`--max-filesize`)
+    pub max_filesize: Option<u64>,
+    /// Downloads of the run, steered by `wget ctl` and reported to the
+    /// `--log-format=json` events. Shared by clones.
+    pub jobs: Jobs,
+    /// Journal of the run (`--session`), opened by the caller
+    pub session: Option<Session>,
+}
+
+impl DownloadOptions {
+    /// Collects the download flags of `args`. Proxy and TLS settings are
+    /// passed in because the caller reports their errors with distinct
+    /// exit codes.
+    pub fn from_cli(args: &Cli, proxy: ProxyConfig, tls: TlsConfig) -> Self {
+        Self {
+            // The rates were checked by `Cli::validate`
+            rate_limits: RateLimits::new(
+                args.rate_limit.as_deref(),
+                args.limit_rate_per_host.as_deref(),
+                args.limit_rate_per_download.as_deref(),
+            )
+            .unwrap_or_default(),
+            user_agent: args.user_agent.clone(),
+            tries: args.tries,
+            wait_retry: args.waitretry,
+            timeouts: args.timeouts(),
+            proxy,
+            tls,
+            compression: args.compression_config(),
+            headers: args.header_output(),
+            progress: args.progress_display(),
+            resume: args.continue_download,
+            segments: args.segments,
+            checksum_policy: args.checksum_mismatch,
+            max_concurrent: args.max_concurrent,
+            max_per_host: args.max_per_host,
+            quota: Quota::new(args.quota.as_deref().and_then(|q| parse_size(q).ok())),
+            max_filesize: args.max_filesize.as_deref().and_then(|m| parse_size(m).ok()),
+            jobs: Jobs::new(EventLog::new(args.log_format, args.output_to_stdout())),
+            session: None,
+        }
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            rate_limits: RateLimits::default(),
+            user_agent: None,
+            tries: 3,
+            wait_retry: 1,
+            timeouts: Timeouts::default(),
+            proxy: ProxyConfig::from_env().unwrap_or_default(),
+            tls: TlsConfig::default(),
+            compression: CompressionConfig::default(),
+            headers: HeaderOutput::default(),
+            progress: ProgressDisplay::default(),
+            resume: false,
+            segments: 1,
+            checksum_policy: ChecksumPolicy::default(),
+            max_concurrent: 4,
+            max_per_host: None,
+            quota: Quota::default(),
+            max_filesize: None,
+            jobs: Jobs::default(),
+            session: None,
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::output::progress::{Progress, ProgressDisplay};
+use indicatif::MultiProgress;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -6,43 +7,37 @@ use tokio::sync::Mutex;
 /// Manages multiple progress bars for concurrent downloads
 pub struct MultiProgressManager {
     multi_progress: Arc<MultiProgress>,
-    progress_bars: Arc<Mutex<HashMap<String, ProgressBar>>>,
+    progress_bars: Arc<Mutex<HashMap<String, Progress>>>,
+    display: ProgressDisplay,
 }
 
 impl MultiProgressManager {
-    pub fn new() -> Self {
+    pub fn new(display: ProgressDisplay) -> Self {
         Self {
             multi_progress: Arc::new(MultiProgress::new()),
             progress_bars: Arc::new(Mutex::new(HashMap::new())),
+            display,
         }
     }
 
-    /// Creates a new progress bar for a specific URL
-    pub async fn create_progress_bar(&self, url: &str, content_length: u64) -> ProgressBar {
-        let progress_bar = ProgressBar::new(content_length);
-
-        // Set a more compact style for multiple downloads
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "{prefix:.bold.dim} {bytes}/{total_bytes} [{bar:40.cyan/red}] {eta_precise}",
-                )
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-
-        // Set a prefix with the filename from URL
+    /// Creates the progress display for a specific URL, named after its
+    /// file. `None` when `--progress=none` or `silent` hide it.
+    pub async fn create_progress_bar(
+        &self,
+        url: &str,
+        content_length: u64,
+        silent: bool,
+    ) -> Option<Progress> {
         let filename = crate::utils::url::extract_filename(url);
-        progress_bar.set_prefix(format!("{:<20}", filename));
-
-        // Add to multi-progress display
-        let pb = self.multi_progress.add(progress_bar.clone());
+        let progress =
+            self.display
+                .start_named(&self.multi_progress, &filename, content_length, silent)?;
 
         // Store in our map
         let mut bars = self.progress_bars.lock().await;
-        bars.insert(url.to_string(), pb.clone());
+        bars.insert(url.to_string(), progress.clone());
 
-        pb
+        Some(progress)
     }
 
     /// Updates progress for a specific URL
@@ -59,13 +54,22 @@ impl MultiProgressManager {
         let mut bars = self.progress_bars.lock().await;
         if let Some(pb) = bars.remove(url) {
             if success {
-                pb.finish_with_message("✓ Complete");
+                pb.finish();
             } else {
-                pb.finish_with_message("✗ Failed");
+                pb.abandon();
             }
         }
     }
 
+    /// Prints a line above the progress bars without garbling them
+    pub fn println(&self, line: &str) {
+        if self.multi_progress.is_hidden() {
+            println!("{}", line);
+        } else {
+            let _ = self.multi_progress.println(line);
+        }
+    }
+
     /// Gets the multi-progress instance for manual management if needed
     #[allow(dead_code)]
     pub fn get_multi_progress(&self) -> Arc<MultiProgress> {
@@ -0,0 +1,63 @@
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::utils::size::format_size;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bytes downloaded during the whole run, shared by every transfer, and the
+/// `-Q/--quota` they are measured against. Once the quota is used up no new
+/// download starts, but running ones finish.
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    /// `None` (or `-Q 0`) means unlimited
+    limit: Option<u64>,
+    used: Arc<AtomicU64>,
+}
+
+impl Quota {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit: limit.filter(|&limit| limit > 0),
+            used: Arc::default(),
+        }
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used() >= limit)
+    }
+
+    /// Error for a download that is not started because the quota is used up
+    pub fn check(&self, url: &str) -> Result<(), DownloadError> {
+        match self.limit {
+            Some(limit) if self.exceeded() => Err(DownloadError {
+                kind: ErrorKind::Skipped,
+                message: format!(
+                    "Download quota of {} exceeded, not downloading {}",
+                    format_size(limit),
+                    url
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Error for a file larger than `--max-filesize`
+pub fn too_large(url: &str, size: u64, max_filesize: u64) -> DownloadError {
+    DownloadError {
+        kind: ErrorKind::Skipped,
+        message: format!(
+            "{} is larger than --max-filesize ({} > {})",
+            url,
+            format_size(size),
+            format_size(max_filesize)
+        ),
+    }
+}
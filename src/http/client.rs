@@ -1,19 +1,168 @@
-use crate::rate::RateLimiter;
-use crate::resume::ResumeHandler;
+use crate::checksum::{Checksum, ChecksumAlgorithm, ChecksumPolicy, StreamHasher};
+use crate::control::Jobs;
+use crate::download::quota::{self, Quota};
+use crate::download::DownloadOptions;
+use crate::http::compression::{self, BodyStream, Compression, CompressionConfig};
+use crate::http::headers::HeaderOutput;
+use crate::http::pinned::PinnedTransport;
+use crate::http::proxy::ProxyConfig;
+use crate::http::segments::{self, SegmentTable};
+use crate::http::timeouts::Timeouts;
+use crate::http::tls::{PinMismatch, TlsConfig};
+use crate::output::Event;
+use crate::output::progress::ProgressDisplay;
+use crate::rate::{RateLimits, Throttle};
+use crate::resume::{ContentRange, ResumeHandler, ResumeValidators};
 use crate::retry::RetryPolicy;
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{Client, Response};
+use reqwest::header::{
+    ACCEPT_ENCODING, AUTHORIZATION, COOKIE, HeaderMap, IF_RANGE, LOCATION, PROXY_AUTHORIZATION,
+    RANGE,
+};
+use reqwest::{Client, Request, RequestBuilder, Response, StatusCode};
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
-use std::time::Duration;
 use tokio::fs::{File, OpenOptions};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
 
-#[derive(Debug)]
+/// Broad category of a download failure, used for reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// DNS, connection or transfer failure
+    Network,
+    /// The server answered with an error status
+    Http,
+    /// Certificate verification, pinning or TLS configuration failure
+    Tls,
+    /// A DNS, connect, read or overall timeout expired
+    Timeout,
+    /// Local file system failure
+    Io,
+    /// Unusable input (URL lists and similar)
+    Input,
+    /// The downloaded file does not match its expected checksum
+    Checksum,
+    /// Deliberately not downloaded: over `--max-filesize` or `-Q` used up
+    Skipped,
+    /// The program reading `-O -` closed the pipe
+    OutputClosed,
+    /// Stopped by SIGINT or SIGTERM; what arrived is kept for `-c`
+    Interrupted,
+    /// Cancelled with `wget ctl cancel`
+    Cancelled,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            ErrorKind::Network => "network",
+            ErrorKind::Http => "http",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Io => "io",
+            ErrorKind::Input => "input",
+            ErrorKind::Checksum => "checksum",
+            ErrorKind::Skipped => "skipped",
+            ErrorKind::OutputClosed => "output_closed",
+            ErrorKind::Interrupted => "interrupted",
+            ErrorKind::Cancelled => "cancelled",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DownloadError {
+    pub kind: ErrorKind,
     pub message: String,
 }
 
+impl DownloadError {
+    /// Classifies a failed `send()`, separating TLS verification failures
+    /// from ordinary network errors.
+    fn request_failed(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return DownloadError {
+                kind: ErrorKind::Timeout,
+                message: format!("Request timed out: {}", e),
+            };
+        }
+        Self::send_failed(&e)
+    }
+
+    /// Classifies a failed request by the types of its causes: TLS errors
+    /// (including a `--pinnedpubkey` mismatch), timeouts, and anything
+    /// else as a network error
+    fn send_failed(e: &(dyn Error + 'static)) -> Self {
+        let mut source = Some(e);
+        while let Some(err) = source {
+            if err.is::<native_tls::Error>()
+                || err.is::<openssl::ssl::Error>()
+                || err.is::<openssl::error::ErrorStack>()
+                || err.is::<PinMismatch>()
+            {
+                return DownloadError {
+                    kind: ErrorKind::Tls,
+                    message: format!("TLS verification failed: {}", err),
+                };
+            }
+            if let Some(io) = err.downcast_ref::<std::io::Error>()
+                && io.kind() == std::io::ErrorKind::TimedOut
+            {
+                return DownloadError {
+                    kind: ErrorKind::Timeout,
+                    message: format!("Request timed out: {}", io),
+                };
+            }
+            source = err.source();
+        }
+        DownloadError {
+            kind: ErrorKind::Network,
+            message: format!("Failed to send request: {}", e),
+        }
+    }
+
+    /// Classifies a failure while reading the body stream
+    pub fn read_failed(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::Interrupted {
+            return Self::interrupted();
+        }
+        let kind = if e.kind() == std::io::ErrorKind::TimedOut {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::Network
+        };
+        DownloadError {
+            kind,
+            message: format!("Failed to read chunk: {}", e),
+        }
+    }
+
+    /// Classifies a failure while writing the body out. A closed pipe is
+    /// not a disk error: the reader simply wants no more data.
+    pub fn write_failed(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::BrokenPipe {
+            return DownloadError {
+                kind: ErrorKind::OutputClosed,
+                message: "Output closed by the reading program".to_string(),
+            };
+        }
+        DownloadError {
+            kind: ErrorKind::Io,
+            message: format!("Failed to write chunk: {}", e),
+        }
+    }
+
+    /// The run was stopped by SIGINT or SIGTERM before this download ended
+    pub fn interrupted() -> Self {
+        DownloadError {
+            kind: ErrorKind::Interrupted,
+            message: "Interrupted".to_string(),
+        }
+    }
+}
+
 impl fmt::Display for DownloadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Download error: {}", self.message)
@@ -22,54 +171,411 @@ impl fmt::Display for DownloadError {
 
 impl Error for DownloadError {}
 
+/// A download saved to a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fetched {
+    /// Bytes transferred by this run; a resumed part is not counted
+    pub bytes: u64,
+    /// Digest of the whole file, computed when there is an expected
+    /// checksum or the client records one (`with_digest`)
+    pub digest: Option<Checksum>,
+}
+
+/// Sent when `--user-agent` is not given
+const DEFAULT_USER_AGENT: &str = "wget-rs/0.1.0";
+
+/// Redirections followed per request, as in GNU wget
+const MAX_REDIRECTS: usize = 20;
+/// Statuses whose `Location` is followed
+const REDIRECTS: [StatusCode; 5] = [
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::FOUND,
+    StatusCode::SEE_OTHER,
+    StatusCode::TEMPORARY_REDIRECT,
+    StatusCode::PERMANENT_REDIRECT,
+];
+
 #[derive(Clone)]
 pub struct HttpClient {
     client: Client,
-    rate_limiter: Option<RateLimiter>,
-    #[allow(dead_code)]
+    /// Connections for `--pinnedpubkey`, checked during the handshake
+    pinned: Option<PinnedTransport>,
+    rate_limits: RateLimits,
+    compression: CompressionConfig,
+    timeouts: Timeouts,
+    headers: HeaderOutput,
+    progress: ProgressDisplay,
+    /// Parallel connections per file (`--segments`)
+    segments: usize,
+    /// What to do with a file whose checksum does not match
+    checksum_policy: ChecksumPolicy,
+    /// Hash every download with this, expected checksum or not
+    digest: Option<ChecksumAlgorithm>,
     retry_policy: RetryPolicy,
-    #[allow(dead_code)]
+    /// Bytes downloaded so far against `-Q`
+    quota: Quota,
+    max_filesize: Option<u64>,
+    /// Pause switch and progress counters of `wget ctl`
+    jobs: Jobs,
     user_agent: Option<String>,
 }
 
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HttpClient {
     pub fn new() -> Self {
-        Self::with_config(None, None, None, 30)
+        Self::from_options(&DownloadOptions::default())
+            .expect("default HTTP client configuration is valid")
+    }
+
+    /// Client configured with the shared download options
+    pub fn from_options(options: &DownloadOptions) -> Result<Self, DownloadError> {
+        let mut client = Self::with_config(
+            None,
+            options.user_agent.clone(),
+            Some(options.tries),
+            options.timeouts,
+            &options.proxy,
+            &options.tls,
+            options.compression,
+        )?
+        .with_segments(options.segments)
+        .with_checksum_policy(options.checksum_policy);
+        client.retry_policy = RetryPolicy::new(options.tries, options.wait_retry);
+        client.quota = options.quota.clone();
+        client.max_filesize = options.max_filesize;
+        client.rate_limits = options.rate_limits.clone();
+        client.jobs = options.jobs.clone();
+        client.headers = options.headers;
+        client.progress = options.progress;
+        if options.session.is_some() {
+            // The journal records what was downloaded, not just its size
+            client = client.with_digest(ChecksumAlgorithm::Sha256);
+        }
+        Ok(client)
     }
 
     pub fn with_config(
         rate_limit: Option<&str>,
         user_agent: Option<String>,
         tries: Option<u32>,
-        timeout_secs: u64,
-    ) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout_secs))
-            .user_agent(user_agent.as_deref().unwrap_or("wget-rs/0.1.0"))
+        timeouts: Timeouts,
+        proxy: &ProxyConfig,
+        tls: &TlsConfig,
+        compression: CompressionConfig,
+    ) -> Result<Self, DownloadError> {
+        let builder = Client::builder()
+            .user_agent(user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            // `send_once` follows redirects itself
+            .redirect(reqwest::redirect::Policy::none());
+        let client = tls
+            .apply(proxy.apply(timeouts.apply(builder)))?
             .build()
-            .unwrap();
+            .map_err(|e| DownloadError {
+                kind: ErrorKind::Tls,
+                message: format!("Failed to initialise HTTP client: {}", e),
+            })?;
+        let pinned = PinnedTransport::new(
+            tls,
+            proxy,
+            timeouts,
+            user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT),
+        )?;
 
-        let rate_limiter = rate_limit.and_then(|r| RateLimiter::new(r).ok());
+        let rate_limits = RateLimits::new(rate_limit, None, None).map_err(|e| DownloadError {
+            kind: ErrorKind::Input,
+            message: format!("Rate limit: {}", e),
+        })?;
         let retry_policy = RetryPolicy::new(tries.unwrap_or(3), 1);
 
-        HttpClient {
+        Ok(HttpClient {
             client,
-            rate_limiter,
+            pinned,
+            rate_limits,
+            compression,
+            timeouts,
+            headers: HeaderOutput::default(),
+            progress: ProgressDisplay::default(),
+            segments: 1,
+            checksum_policy: ChecksumPolicy::default(),
+            digest: None,
             retry_policy,
+            quota: Quota::default(),
+            max_filesize: None,
+            jobs: Jobs::default(),
             user_agent,
+        })
+    }
+
+    /// Splits `download_to_file_with_resume` transfers over up to
+    /// `segments` parallel range requests (`--segments`)
+    pub fn with_segments(mut self, segments: usize) -> Self {
+        self.segments = segments.max(1);
+        self
+    }
+
+    /// Keep or delete files that fail checksum verification
+    pub fn with_checksum_policy(mut self, policy: ChecksumPolicy) -> Self {
+        self.checksum_policy = policy;
+        self
+    }
+
+    /// Reports to the registry `jobs` from now on: its pause switch,
+    /// progress counters and interrupt token
+    pub fn with_jobs(mut self, jobs: Jobs) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Hashes downloads without an expected checksum too, so that
+    /// `Fetched::digest` is always set
+    pub fn with_digest(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.digest = Some(algorithm);
+        self
+    }
+
+    /// Hasher for a download: with the algorithm of `expected`, else the
+    /// one of `with_digest`, else none
+    pub fn hasher(&self, expected: Option<&Checksum>) -> Option<StreamHasher> {
+        expected
+            .map(|expected| expected.algorithm)
+            .or(self.digest)
+            .map(StreamHasher::new)
+    }
+
+    /// Finishes the hasher of a download saved to `file_path`, checking
+    /// the digest against `expected`
+    pub async fn finish_digest(
+        &self,
+        hasher: Option<StreamHasher>,
+        file_path: &std::path::Path,
+        expected: Option<&Checksum>,
+    ) -> Result<Option<Checksum>, DownloadError> {
+        let Some(hasher) = hasher else {
+            return Ok(None);
+        };
+        let digest = hasher.finish();
+        if let Some(expected) = expected {
+            expected
+                .verify(&digest.digest, file_path, self.checksum_policy)
+                .await?;
         }
+        Ok(Some(digest))
+    }
+
+    /// Sends a request and runs the checks shared by every download path.
+    /// Network failures, timeouts and transient statuses such as 503 are
+    /// retried according to `--tries` and `--waitretry`.
+    async fn send(&self, mut request: RequestBuilder) -> Result<Response, DownloadError> {
+        let url = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .map_or(String::new(), |request| request.url().to_string());
+        let mut attempt = 1;
+        loop {
+            self.jobs.check_interrupted()?;
+            let retry = request
+                .try_clone()
+                .filter(|_| self.retry_policy.allows_retry(attempt));
+            let result = self.send_once(request).await;
+            let transient = match &result {
+                Ok(response) => RetryPolicy::is_transient_status(response.status()),
+                Err(e) => matches!(e.kind, ErrorKind::Network | ErrorKind::Timeout),
+            };
+            match retry {
+                Some(next) if transient => {
+                    let delay = self.retry_policy.delay(attempt);
+                    let (error_kind, error) = match &result {
+                        Ok(response) => (None, format!("HTTP error: {}", response.status())),
+                        Err(e) => (Some(e.kind), e.message.clone()),
+                    };
+                    self.jobs.count_retry();
+                    self.jobs.events().emit(Event::Retry {
+                        url: url.clone(),
+                        attempt,
+                        delay_ms: delay.as_millis() as u64,
+                        error_kind,
+                        error,
+                    });
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = self.jobs.interrupted() => {}
+                    }
+                    request = next;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    /// Sends one request, following redirects itself so that every hop
+    /// can be reported
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response, DownloadError> {
+        let (client, request) = request.build_split();
+        let mut request = request.map_err(DownloadError::request_failed)?;
+        let events = self.jobs.events();
+        for _ in 0..=MAX_REDIRECTS {
+            let next = request.try_clone();
+            let url = request.url().clone();
+            events.emit(Event::RequestSent {
+                url: url.to_string(),
+                method: request.method().to_string(),
+            });
+            self.headers.request(
+                request.method(),
+                request.url(),
+                request.headers(),
+                self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT),
+            );
+            let response = match self.timeouts.response_wait() {
+                Some(limit) => tokio::time::timeout(limit, self.execute(&client, request))
+                    .await
+                    .map_err(|_| DownloadError {
+                        kind: ErrorKind::Timeout,
+                        message: format!("No response within {:.1}s", limit.as_secs_f64()),
+                    })?,
+                None => self.execute(&client, request).await,
+            }?;
+            self.headers.response(&response);
+
+            let status = response.status();
+            if events.enabled() {
+                events.emit(Event::Response {
+                    url: url.to_string(),
+                    status: status.as_u16(),
+                    headers: header_map(response.headers()),
+                });
+            }
+            let location = REDIRECTS
+                .contains(&status)
+                .then(|| response.headers().get(LOCATION))
+                .flatten()
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok());
+            let (Some(location), Some(mut next)) = (location, next) else {
+                return Ok(response);
+            };
+            events.emit(Event::Redirect {
+                url: url.to_string(),
+                location: location.to_string(),
+                status: status.as_u16(),
+            });
+            // Credentials are only for the server they were meant for
+            if location.origin() != url.origin() {
+                for header in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+                    next.headers_mut().remove(header);
+                }
+            }
+            *next.url_mut() = location;
+            request = next;
+        }
+        Err(DownloadError {
+            kind: ErrorKind::Http,
+            message: format!("More than {} redirections", MAX_REDIRECTS),
+        })
+    }
+
+    /// Sends one request; HTTPS goes through the pinning transport when
+    /// `--pinnedpubkey` is set
+    async fn execute(&self, client: &Client, request: Request) -> Result<Response, DownloadError> {
+        match &self.pinned {
+            Some(pinned) if request.url().scheme() == "https" => pinned
+                .execute(request)
+                .await
+                .map_err(|e| DownloadError::send_failed(&*e)),
+            _ => client
+                .execute(request)
+                .await
+                .map_err(DownloadError::request_failed),
+        }
+    }
+
+    /// GET request advertising the configured content codings
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.client
+            .get(url)
+            .header(ACCEPT_ENCODING, self.compression.mode.accept_encoding())
+    }
+
+    /// Body of `response` as a chunk stream, decoded according to
+    /// `--compression` and guarded by the read timeout
+    pub fn body_stream(&self, response: Response) -> BodyStream {
+        let body = self.timeouts.watch_body(self.compression.body_stream(response));
+        interruptible(body, self.jobs.interrupt_token())
+    }
+
+    /// Whether `body_stream` will decode this response (its size is then unknown)
+    pub fn decodes(&self, response: &Response) -> bool {
+        self.compression.decodes(response)
+    }
+
+    /// Reads the whole (decoded) body of the response to `url` into memory
+    pub async fn read_body(&self, url: &str, response: Response) -> Result<Vec<u8>, DownloadError> {
+        use futures_util::StreamExt;
+        if !self.decodes(&response) {
+            self.check_size(url, response.content_length().unwrap_or(0))?;
+        }
+        let throttle = self.throttle(url);
+        let mut stream = self.body_stream(response);
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(DownloadError::read_failed)?;
+            throttle.consume(chunk.len() as u64).await?;
+            self.quota.add(chunk.len() as u64);
+            body.extend_from_slice(&chunk);
+            self.check_size(url, body.len() as u64)?;
+        }
+        Ok(body)
+    }
+
+    /// Run-wide byte count and `-Q` limit
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// Refuses files bigger than `--max-filesize`
+    pub fn check_size(&self, url: &str, size: u64) -> Result<(), DownloadError> {
+        match self.max_filesize {
+            Some(max) if size > max => Err(quota::too_large(url, size, max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether saved files start with the response headers (`--save-headers`).
+    /// Such files cannot be resumed or split into segments.
+    pub fn saves_headers(&self) -> bool {
+        self.headers.save_headers
+    }
+
+    /// How transfers show their progress (`--progress`, `--show-progress`)
+    pub fn progress(&self) -> ProgressDisplay {
+        self.progress
+    }
+
+    /// Downloads of the run, for callers that register their own
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    /// Bandwidth limits for a new transfer of `url`, which also stops
+    /// while `wget ctl pause` is in effect, counts progress for its job and
+    /// ends the transfer once the run is interrupted
+    pub fn throttle(&self, url: &str) -> Throttle {
+        self.rate_limits.throttle(url).with_control(
+            self.jobs.paused(),
+            self.jobs.interrupt_token(),
+            self.jobs.progress(url),
+        )
     }
     pub async fn download(&self, url: &str) -> Result<Response, DownloadError> {
         print!("sending request, awaiting response... ");
 
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| DownloadError {
-                message: format!("Failed to send request: {}", e),
-            })?;
+        let response = self.send(self.get(url)).await?;
 
         let status = response.status();
         println!(
@@ -80,6 +586,29 @@ impl HttpClient {
 
         if !status.is_success() {
             return Err(DownloadError {
+                kind: ErrorKind::Http,
+                message: format!("HTTP error: {}", status),
+            });
+        }
+        Ok(response)
+    }
+
+    /// Checks that `url` exists without downloading it (`--spider`). Uses
+    /// HEAD, falling back to a GET whose body is never read for servers
+    /// that refuse HEAD.
+    pub async fn probe(&self, url: &str) -> Result<Response, DownloadError> {
+        let mut response = self.send(self.client.head(url)).await?;
+        if matches!(
+            response.status(),
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        ) {
+            response = self.send(self.get(url)).await?;
+        }
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(DownloadError {
+                kind: ErrorKind::Http,
                 message: format!("HTTP error: {}", status),
             });
         }
@@ -88,18 +617,12 @@ impl HttpClient {
 
     /// Silent version of download that doesn't print status messages
     pub async fn download_silent(&self, url: &str) -> Result<Response, DownloadError> {
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| DownloadError {
-                message: format!("Failed to send request: {}", e),
-            })?;
+        let response = self.send(self.get(url)).await?;
 
         let status = response.status();
         if !status.is_success() {
             return Err(DownloadError {
+                kind: ErrorKind::Http,
                 message: format!("HTTP error: {}", status),
             });
         }
@@ -113,7 +636,11 @@ impl HttpClient {
         file_path: &std::path::Path,
     ) -> Result<u64, DownloadError> {
         let response = self.download(url).await?;
-        let content_length = response.content_length().unwrap_or(0);
+        let content_length = if self.decodes(&response) {
+            0
+        } else {
+            response.content_length().unwrap_or(0)
+        };
         if content_length > 0 {
             println!(
                 "content size: {} [~{:.2}MB]",
@@ -123,41 +650,42 @@ impl HttpClient {
         }
         println!("saving file to: ./{}", file_path.display());
 
-        let progress_bar = ProgressBar::new(content_length);
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-                .template("{bytes}/{total_bytes} [{bar:80.cyan/red}] {eta_precise}")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
+        let progress_bar = self.progress.start(content_length, 0, false);
 
         let mut file = File::create(file_path).await.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to create file: {}", e),
         })?;
 
-        let mut stream = response.bytes_stream();
+        let mut stream = self.body_stream(response);
         let mut download = 0u64;
 
         use futures_util::StreamExt;
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| DownloadError {
-                message: format!("Failed to read chunk: {}", e),
-            })?;
+            let chunk = chunk.map_err(DownloadError::read_failed)?;
             file.write_all(&chunk).await.map_err(|e| DownloadError {
+                kind: ErrorKind::Io,
                 message: format!("Failed to write chunk: {}", e),
             })?;
             download += chunk.len() as u64;
-            progress_bar.set_position(download);
+            if let Some(ref pb) = progress_bar {
+                pb.set_position(download);
+            }
         }
         file.flush().await.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to flush file: {}", e),
         })?;
 
+        if let Some(pb) = progress_bar {
+            pb.finish();
+        }
         println!();
         println!("Downloaded [{}]", url);
         Ok(download)
     }
 
+    #[allow(dead_code)]
     pub async fn download_to_file_silent(
         &self,
         url: &str,
@@ -166,6 +694,7 @@ impl HttpClient {
         self.download_to_file_with_resume(url, file_path, false, true).await
     }
 
+    #[allow(dead_code)]
     pub async fn download_to_file_with_resume(
         &self,
         url: &str,
@@ -173,21 +702,126 @@ impl HttpClient {
         resume: bool,
         silent: bool,
     ) -> Result<u64, DownloadError> {
-        let mut rate_limiter = self.rate_limiter.clone();
-        
-        let resume_pos = if resume {
-            ResumeHandler::get_resume_position(file_path).await
+        self.download_to_file_checked(url, file_path, resume, silent, None)
+            .await
+            .map(|fetched| fetched.bytes)
+    }
+
+    /// `download_to_file_with_resume`, verifying the finished file against
+    /// `expected`. The digest is computed from the chunks as they are written.
+    pub async fn download_to_file_checked(
+        &self,
+        url: &str,
+        file_path: &std::path::Path,
+        resume: bool,
+        silent: bool,
+        expected: Option<&Checksum>,
+    ) -> Result<Fetched, DownloadError> {
+        self.quota.check(url)?;
+        let throttle = self.throttle(url);
+
+        let byte_ranges = self.byte_ranges();
+
+        if self.segments > 1
+            && byte_ranges
+            && !self.saves_headers()
+            && let Some(fetched) = segments::download(
+                self,
+                &[url],
+                file_path,
+                self.segments,
+                resume,
+                silent,
+                throttle.clone(),
+            )
+            .await?
+        {
+            // Segments arrive out of order, so this is the one path that
+            // has to hash the finished file
+            return Ok(Fetched {
+                bytes: fetched,
+                digest: self.digest_file(file_path, expected).await?,
+            });
+        }
+
+        let mut resume_pos = if resume && byte_ranges && !self.saves_headers() {
+            segments::resume_position(file_path).await
         } else {
             0
         };
 
         let response = if resume_pos > 0 {
-            self.download_with_range(url, resume_pos).await?
+            let saved = ResumeValidators::load(file_path).await.unwrap_or_default();
+            let response = self
+                .download_with_range(url, resume_pos, saved.if_range())
+                .await?;
+            match response.status() {
+                // `resume_pos` never counts the zeros of a preallocated
+                // segmented file, so matching the total means complete
+                StatusCode::RANGE_NOT_SATISFIABLE => {
+                    let total = ContentRange::from_response(&response)
+                        .and_then(|range| range.total)
+                        .or(saved.length);
+                    if total == Some(resume_pos) {
+                        ResumeValidators::remove(file_path).await;
+                        self.jobs.add_skipped_bytes(resume_pos);
+                        if !silent {
+                            println!("The file is already fully retrieved; nothing to do.");
+                        }
+                        return Ok(Fetched {
+                            bytes: 0,
+                            digest: self.digest_file(file_path, expected).await?,
+                        });
+                    }
+                    if !silent {
+                        println!("Local file does not match the remote one, restarting");
+                    }
+                    resume_pos = 0;
+                    None
+                }
+                // Never continue a decoded stream at a byte offset
+                StatusCode::PARTIAL_CONTENT
+                    if saved.continues(&response, resume_pos)
+                        && compression::content_encoding(&response).is_none() =>
+                {
+                    Some(response)
+                }
+                // Range ignored, or If-Range saw a newer file: this is the whole body
+                StatusCode::OK if compression::content_encoding(&response).is_none() => {
+                    if !silent {
+                        println!("Server sent the whole file, restarting from the beginning");
+                    }
+                    resume_pos = 0;
+                    Some(response)
+                }
+                _ => {
+                    if !silent {
+                        println!("Remote file changed since the partial download, restarting");
+                    }
+                    resume_pos = 0;
+                    None
+                }
+            }
         } else {
-            if silent { self.download_silent(url).await? } else { self.download(url).await? }
+            None
         };
-
-        let content_length = response.content_length().unwrap_or(0) + resume_pos;
+        let response = match response {
+            Some(response) => response,
+            None if silent => self.download_silent(url).await?,
+            None => self.download(url).await?,
+        };
+        self.jobs.add_skipped_bytes(resume_pos);
+        // The decoded size is unknown up front
+        let content_length = if self.decodes(&response) {
+            0
+        } else {
+            response.content_length().unwrap_or(0) + resume_pos
+        };
+        self.check_size(url, content_length)?;
+        if resume_pos == 0 && byte_ranges {
+            // Best effort: without a sidecar a later -c still resumes, just unchecked
+            let _ = ResumeValidators::from_response(&response).save(file_path).await;
+        }
         if !silent && content_length > 0 {
             println!(
                 "content size: {} [~{:.2}MB]",
@@ -199,52 +833,73 @@ impl HttpClient {
             println!("saving file to: ./{}", file_path.display());
         }
 
-        let progress_bar = if !silent {
-            let pb = ProgressBar::new(content_length);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{bytes}/{total_bytes} [{bar:80.cyan/red}] {eta_precise}")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-            pb.set_position(resume_pos);
-            Some(pb)
-        } else {
-            None
-        };
+        let progress_bar = self.progress.start(content_length, resume_pos, silent);
 
         let mut file = if resume_pos > 0 {
             OpenOptions::new().append(true).open(file_path).await
         } else {
             File::create(file_path).await
         }.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to open file: {}", e),
         })?;
+        if let Some(head) = self.headers.saved(&response) {
+            file.write_all(&head).await.map_err(|e| DownloadError {
+                kind: ErrorKind::Io,
+                message: format!("Failed to write headers: {}", e),
+            })?;
+        }
+
+        let mut hasher = self.hasher(expected);
+        if resume_pos > 0
+            && let Some(hasher) = hasher.as_mut()
+        {
+            hasher.update_from_file(file_path).await?;
+        }
 
-        let mut stream = response.bytes_stream();
+        let mut stream = self.body_stream(response);
         let mut download = resume_pos;
 
         use futures_util::StreamExt;
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| DownloadError {
-                message: format!("Failed to read chunk: {}", e),
-            })?;
-            
-            if let Some(limiter) = rate_limiter.as_mut() {
-                limiter.consume(chunk.len() as u64).await;
-            }
-            
+            let throttled = match chunk {
+                Ok(chunk) => throttle.consume(chunk.len() as u64).await.map(|()| chunk),
+                Err(e) => Err(DownloadError::read_failed(e)),
+            };
+            let chunk = match throttled {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // Keep what arrived, and its validators, for -c
+                    let _ = file.flush().await;
+                    return Err(e);
+                }
+            };
+
             file.write_all(&chunk).await.map_err(|e| DownloadError {
+                kind: ErrorKind::Io,
                 message: format!("Failed to write chunk: {}", e),
             })?;
             
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+            
             download += chunk.len() as u64;
+            self.quota.add(chunk.len() as u64);
             if let Some(ref pb) = progress_bar {
                 pb.set_position(download);
             }
+            if let Err(e) = self.check_size(url, download) {
+                // Servers without Content-Length are only caught while streaming
+                drop(file);
+                let _ = tokio::fs::remove_file(file_path).await;
+                ResumeValidators::remove(file_path).await;
+                return Err(e);
+            }
         }
         
         file.flush().await.map_err(|e| DownloadError {
+            kind: ErrorKind::Io,
             message: format!("Failed to flush file: {}", e),
         })?;
 
@@ -252,33 +907,419 @@ impl HttpClient {
             pb.finish();
             println!();
         }
+        ResumeValidators::remove(file_path).await;
+        SegmentTable::remove(file_path).await;
+        let digest = self.finish_digest(hasher, file_path, expected).await?;
+        if let Some(expected) = expected
+            && !silent
+        {
+            println!("Checksum OK ({})", expected.algorithm.name());
+        }
         if !silent {
             println!("Downloaded [{}]", url);
         }
         
-        Ok(download - resume_pos)
+        Ok(Fetched {
+            bytes: download - resume_pos,
+            digest,
+        })
     }
 
-    async fn download_with_range(&self, url: &str, start: u64) -> Result<Response, DownloadError> {
-        let range_header = ResumeHandler::create_range_header(start).unwrap();
-        
-        let response = self
-            .client
-            .get(url)
-            .header("Range", range_header)
-            .send()
-            .await
-            .map_err(|e| DownloadError {
-                message: format!("Failed to send request: {}", e),
-            })?;
+    /// Streams the body of `url` into `out`, which is stdout for `-O -` or
+    /// an `-O` file shared by several URLs. Nothing is resumed or split into
+    /// segments, and status lines and the progress bar go to stderr so only
+    /// the body reaches a pipe.
+    pub async fn download_to_writer<W: AsyncWrite + Unpin>(
+        &self,
+        url: &str,
+        out: &mut W,
+        quiet: bool,
+        expected: Option<&Checksum>,
+    ) -> Result<u64, DownloadError> {
+        self.quota.check(url)?;
+        let throttle = self.throttle(url);
+        let response = self.download_silent(url).await?;
+        let content_length = if self.decodes(&response) {
+            0
+        } else {
+            response.content_length().unwrap_or(0)
+        };
+        self.check_size(url, content_length)?;
+        if !quiet {
+            eprintln!("status {} for {}", response.status(), url);
+        }
+        if let Some(head) = self.headers.saved(&response) {
+            out.write_all(&head).await.map_err(DownloadError::write_failed)?;
+        }
+
+        let progress_bar = self.progress.start(content_length, 0, quiet);
+
+        let mut hasher = expected.map(|expected| StreamHasher::new(expected.algorithm));
+        let mut stream = self.body_stream(response);
+        let mut download = 0;
+
+        use futures_util::StreamExt;
+        while let Some(chunk) = stream.next().await {
+            let throttled = match chunk {
+                Ok(chunk) => throttle.consume(chunk.len() as u64).await.map(|()| chunk),
+                Err(e) => Err(DownloadError::read_failed(e)),
+            };
+            let chunk = match throttled {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = out.flush().await;
+                    return Err(e);
+                }
+            };
+            // Bytes already written cannot be taken back, so the size limit
+            // is checked before a chunk goes out
+            self.check_size(url, download + chunk.len() as u64)?;
+            out.write_all(&chunk).await.map_err(DownloadError::write_failed)?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+            download += chunk.len() as u64;
+            self.quota.add(chunk.len() as u64);
+            if let Some(ref pb) = progress_bar {
+                pb.set_position(download);
+            }
+        }
+        out.flush().await.map_err(DownloadError::write_failed)?;
+
+        if let Some(pb) = progress_bar {
+            pb.finish();
+            eprintln!();
+        }
+        // The bytes are gone (or share a file with other downloads), so a
+        // mismatch is reported but there is no file to keep or delete
+        if let (Some(expected), Some(hasher)) = (expected, hasher) {
+            expected.compare(&hasher.finish().digest, url)?;
+            if !quiet {
+                eprintln!("Checksum OK ({})", expected.algorithm.name());
+            }
+        }
+        Ok(download)
+    }
+
+    /// Downloads one file served by several mirrors, listed in order of
+    /// preference. With `--segments` the connections are spread over all
+    /// mirrors; otherwise the mirrors are tried in turn until one succeeds.
+    pub async fn download_from_mirrors(
+        &self,
+        mirrors: &[&str],
+        file_path: &std::path::Path,
+        resume: bool,
+        silent: bool,
+        expected: Option<&Checksum>,
+    ) -> Result<u64, DownloadError> {
+        if mirrors.len() > 1
+            && self.segments > 1
+            && self.byte_ranges()
+            && let Some(fetched) = segments::download(
+                self,
+                mirrors,
+                file_path,
+                self.segments,
+                resume,
+                silent,
+                self.throttle(mirrors[0]),
+            )
+            .await?
+        {
+            if let Some(expected) = expected {
+                self.digest_file(file_path, Some(expected)).await?;
+            }
+            return Ok(fetched);
+        }
+
+        let mut last_error = None;
+        for mirror in mirrors {
+            match self
+                .download_to_file_checked(mirror, file_path, resume, silent, expected)
+                .await
+            {
+                Ok(fetched) => return Ok(fetched.bytes),
+                // Another mirror serves the same file, so it would be skipped
+                // too; an interrupted run starts nothing new
+                Err(e) if matches!(e.kind, ErrorKind::Skipped | ErrorKind::Interrupted) => {
+                    return Err(e);
+                }
+                Err(e) => {
+                    if !silent {
+                        eprintln!("Mirror {} failed: {}", mirror, e.message);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(DownloadError {
+            kind: ErrorKind::Input,
+            message: "No mirrors to download from".to_string(),
+        }))
+    }
+
+    /// Whether stored bytes line up with a range response. A file saved
+    /// with --keep-encoded holds encoded bytes, which have no offset mapping
+    /// onto the identity body a range request returns.
+    fn byte_ranges(&self) -> bool {
+        !(self.compression.keep_encoded && self.compression.mode != Compression::None)
+    }
+
+    /// Hashes a file already on disk, checking it against `expected`
+    async fn digest_file(
+        &self,
+        file_path: &std::path::Path,
+        expected: Option<&Checksum>,
+    ) -> Result<Option<Checksum>, DownloadError> {
+        let mut hasher = self.hasher(expected);
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update_from_file(file_path).await?;
+        }
+        self.finish_digest(hasher, file_path, expected).await
+    }
+
+    /// Range request for resuming at `start`. A 416 is passed back to the
+    /// caller, which may find the file already complete.
+    async fn download_with_range(
+        &self,
+        url: &str,
+        start: u64,
+        if_range: Option<&str>,
+    ) -> Result<Response, DownloadError> {
+        let response = self.request_range(url, start, None, if_range).await?;
 
         let status = response.status();
-        if !status.is_success() && status.as_u16() != 206 {
+        if !status.is_success() && status != StatusCode::RANGE_NOT_SATISFIABLE {
             return Err(DownloadError {
+                kind: ErrorKind::Http,
                 message: format!("HTTP error: {}", status),
             });
         }
         
         Ok(response)
     }
+
+    /// Sends `Range: bytes=start-end` (open-ended without `end`), always
+    /// asking for the identity coding so offsets match the stored file.
+    /// With `if_range`, a server whose copy changed answers 200 instead.
+    pub(crate) async fn request_range(
+        &self,
+        url: &str,
+        start: u64,
+        end: Option<u64>,
+        if_range: Option<&str>,
+    ) -> Result<Response, DownloadError> {
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => ResumeHandler::create_range_header(start).unwrap_or_else(|| "bytes=0-".into()),
+        };
+        let mut request = self
+            .client
+            .get(url)
+            .header(RANGE, range)
+            .header(ACCEPT_ENCODING, "identity");
+        if let Some(validator) = if_range {
+            request = request.header(IF_RANGE, validator);
+        }
+        self.send(request).await
+    }
+}
+
+/// Response headers as text, for events
+fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Ends `body` with an `Interrupted` read error once `stop` is cancelled,
+/// so a stalled server cannot hold up a SIGINT
+fn interruptible(body: BodyStream, stop: CancellationToken) -> BodyStream {
+    Box::pin(futures_util::stream::unfold(Some(body), move |state| {
+        let stop = stop.clone();
+        async move {
+            use futures_util::StreamExt;
+            let mut body = state?;
+            tokio::select! {
+                chunk = body.next() => chunk.map(|chunk| (chunk, Some(body))),
+                _ = stop.cancelled() => Some((
+                    Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "interrupted")),
+                    None,
+                )),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_writer_output_concatenates_and_stops_on_closed_pipe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                // "GET /a HTTP/1.1": the body repeats the path's letter
+                let name = buf[5];
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n{}{}{}",
+                    name as char, name as char, name as char
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let client = HttpClient::new();
+        let mut out = Vec::new();
+        for name in ["a", "b"] {
+            let url = format!("http://{}/{}", addr, name);
+            client.download_to_writer(&url, &mut out, true, None).await.unwrap();
+        }
+        assert_eq!(out, b"aaabbb");
+
+        let (mut writer, reader) = tokio::io::duplex(64);
+        drop(reader);
+        let url = format!("http://{}/c", addr);
+        let error = client
+            .download_to_writer(&url, &mut writer, true, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::OutputClosed);
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_keeps_partial_file_and_stops_new_requests() {
+        // Sends half the body, then stalls until the client is gone
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let head = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nAccept-Ranges: bytes\r\n\r\n01234";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            let _ = socket.read(&mut buf).await;
+        });
+
+        let dir = std::env::temp_dir().join(format!("wget-interrupt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("half.bin");
+        let options = DownloadOptions::default();
+        let client = HttpClient::from_options(&options).unwrap();
+        let url = format!("http://{}/half.bin", addr);
+
+        let jobs = options.jobs.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            jobs.interrupt(libc::SIGINT);
+        });
+        let error = client
+            .download_to_file_checked(&url, &file_path, false, true, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Interrupted);
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"01234");
+        assert!(ResumeValidators::load(&file_path).await.is_some());
+
+        let error = client.download_silent(&url).await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Interrupted);
+        assert_eq!(options.jobs.interrupt_signal(), Some(libc::SIGINT));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Answers `/echo` with the request it got, `/hops/N` with a redirect
+    /// to `/hops/N-1` until `/hops/0`, and anything else with a redirect to
+    /// `location`
+    async fn redirect_server(location: Option<String>) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+                let hops = path
+                    .strip_prefix("/hops/")
+                    .and_then(|n| n.parse::<u32>().ok());
+                let response = match (path.as_str(), hops, &location) {
+                    ("/echo", _, _) | (_, Some(0), _) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        request.len(),
+                        request
+                    ),
+                    (_, Some(n), _) => format!(
+                        "HTTP/1.1 302 Found\r\nLocation: /hops/{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        n - 1
+                    ),
+                    (_, None, location) => format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        location.as_deref().unwrap_or("/echo")
+                    ),
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_redirects_drop_credentials_across_origins_and_stop_at_limit() {
+        let other = redirect_server(None).await;
+        let cross = redirect_server(Some(format!("http://{}/echo", other))).await;
+        let same = redirect_server(None).await;
+        let client = HttpClient::new();
+        let with_credentials = |url: String| {
+            client
+                .get(&url)
+                .header(AUTHORIZATION, "Basic c2VjcmV0")
+                .header(COOKIE, "session=1")
+        };
+
+        let response = client.send(with_credentials(format!("http://{}/a", same)));
+        let kept = response.await.unwrap().text().await.unwrap();
+        assert!(kept.contains("authorization: basic c2vjcmv0"));
+        let response = client.send(with_credentials(format!("http://{}/a", cross)));
+        let dropped = response.await.unwrap().text().await.unwrap();
+        assert!(dropped.starts_with("get /echo "));
+        assert!(!dropped.contains("authorization") && !dropped.contains("cookie"));
+
+        let url = format!("http://{}/hops/{}", same, MAX_REDIRECTS);
+        assert!(client.send(client.get(&url)).await.is_ok());
+        let url = format!("http://{}/hops/{}", same, MAX_REDIRECTS + 1);
+        let error = client.send(client.get(&url)).await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Http);
+    }
+
+    #[test]
+    fn test_invalid_rate_limit_is_an_error() {
+        let client = |rate| {
+            HttpClient::with_config(
+                Some(rate),
+                None,
+                None,
+                Timeouts::default(),
+                &ProxyConfig::default(),
+                &TlsConfig::default(),
+                CompressionConfig::default(),
+            )
+        };
+        assert!(client("500k").is_ok());
+        let error = client("fast").err().unwrap();
+        assert_eq!(error.kind, ErrorKind::Input);
+    }
 }
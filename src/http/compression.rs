@@ -0,0 +1,164 @@
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+use bytes::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use reqwest::Response;
+use reqwest::header::CONTENT_ENCODING;
+use std::io;
+use std::pin::Pin;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Response body as a stream of chunks, decoded when requested.
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, io::Error>> + Send>>;
+
+/// Content codings offered with `--compression`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    /// Offer every supported coding
+    Auto,
+    Gzip,
+    Br,
+    Zstd,
+    /// Ask for the identity coding only
+    #[default]
+    None,
+}
+
+impl Compression {
+    /// Value of the `Accept-Encoding` header for full (non-range) requests.
+    pub fn accept_encoding(self) -> &'static str {
+        match self {
+            Compression::Auto => "gzip, deflate, br, zstd",
+            Compression::Gzip => "gzip",
+            Compression::Br => "br",
+            Compression::Zstd => "zstd",
+            Compression::None => "identity",
+        }
+    }
+}
+
+/// How bodies are negotiated and stored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompressionConfig {
+    pub mode: Compression,
+    /// Save the body exactly as sent instead of decoding it
+    pub keep_encoded: bool,
+}
+
+impl CompressionConfig {
+    /// Whether `response` will be decoded before it reaches the caller.
+    pub fn decodes(&self, response: &Response) -> bool {
+        !self.keep_encoded && content_encoding(response).is_some()
+    }
+
+    /// Turns a response into a chunk stream, decoding it on the fly unless
+    /// `keep_encoded` is set. Unknown codings are passed through untouched.
+    pub fn body_stream(&self, response: Response) -> BodyStream {
+        let encoding = if self.keep_encoded {
+            None
+        } else {
+            content_encoding(&response)
+        };
+        let raw = response.bytes_stream().map_err(io::Error::other);
+
+        let Some(encoding) = encoding else {
+            return Box::pin(raw);
+        };
+        let reader = StreamReader::new(raw);
+        match encoding.as_str() {
+            "gzip" | "x-gzip" => Box::pin(ReaderStream::new(GzipDecoder::new(reader))),
+            "deflate" => Box::pin(ReaderStream::new(ZlibDecoder::new(reader))),
+            "br" => Box::pin(ReaderStream::new(BrotliDecoder::new(reader))),
+            "zstd" => Box::pin(ReaderStream::new(ZstdDecoder::new(reader))),
+            _ => Box::pin(ReaderStream::new(reader)),
+        }
+    }
+}
+
+/// The response's `Content-Encoding`, ignoring `identity`.
+pub fn content_encoding(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty() && value != "identity")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpClient, ProxyConfig, Timeouts, TlsConfig};
+    use async_compression::tokio::write::GzipEncoder;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(data).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_body_is_negotiated_and_decoded() {
+        let page = b"<html><a href=\"/a.html\">a</a></html>".repeat(50);
+        let encoded = gzip(&page).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    encoded.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&encoded).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase());
+            }
+            (requests, encoded)
+        });
+
+        let url = format!("http://{}/page.html", addr);
+        let decoding = CompressionConfig {
+            mode: Compression::Auto,
+            keep_encoded: false,
+        };
+        let client = HttpClient::with_config(
+            None,
+            None,
+            None,
+            Timeouts::default(),
+            &ProxyConfig::default(),
+            &TlsConfig::default(),
+            decoding,
+        )
+        .unwrap();
+        let response = client.download_silent(&url).await.unwrap();
+        assert_eq!(client.read_body(&url, response).await.unwrap(), page);
+
+        let raw = CompressionConfig {
+            keep_encoded: true,
+            ..decoding
+        };
+        let client = HttpClient::with_config(
+            None,
+            None,
+            None,
+            Timeouts::default(),
+            &ProxyConfig::default(),
+            &TlsConfig::default(),
+            raw,
+        )
+        .unwrap();
+        let response = client.download_silent(&url).await.unwrap();
+        let body = client.read_body(&url, response).await.unwrap();
+
+        let (requests, encoded) = server.await.unwrap();
+        assert_eq!(body, encoded);
+        assert!(requests[0].contains("accept-encoding: gzip, deflate, br, zstd"));
+    }
+}
@@ -0,0 +1,144 @@
+use reqwest::header::{AUTHORIZATION, COOKIE, HeaderMap, HeaderName, PROXY_AUTHORIZATION};
+use reqwest::{Method, Response, StatusCode, Version};
+use url::Url;
+
+/// Headers whose values never appear in debug output
+const REDACTED: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+
+/// What is shown or kept of request and response headers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeaderOutput {
+    /// Print the request line and response headers of every hop (`-S`)
+    pub server_response: bool,
+    /// Write the response headers at the start of saved files (`--save-headers`)
+    pub save_headers: bool,
+    /// Also print outgoing request headers, credentials redacted (`-d`)
+    pub debug: bool,
+}
+
+impl HeaderOutput {
+    /// Shows a request about to be sent. Everything goes to stderr so that
+    /// `-O -` keeps stdout for the body.
+    pub fn request(&self, method: &Method, url: &Url, headers: &HeaderMap, user_agent: &str) {
+        if self.debug {
+            eprint!("{}", request_text(method, url, headers, user_agent));
+        } else if self.server_response {
+            eprintln!("  {} {}", method, url);
+        }
+    }
+
+    pub fn response(&self, response: &Response) {
+        if self.server_response || self.debug {
+            eprint!(
+                "{}",
+                response_text(response.version(), response.status(), response.headers())
+            );
+        }
+    }
+
+    /// Block to write ahead of the body of `response`, with `--save-headers`
+    pub fn saved(&self, response: &Response) -> Option<Vec<u8>> {
+        self.save_headers
+            .then(|| raw_headers(response.version(), response.status(), response.headers()))
+    }
+}
+
+/// The request as wget's debug output shows it
+pub fn request_text(method: &Method, url: &Url, headers: &HeaderMap, user_agent: &str) -> String {
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let mut text = String::from("---request begin---\n");
+    text.push_str(&format!("{} {} HTTP/1.1\n", method, path));
+    if let Some(host) = url.host_str() {
+        match url.port() {
+            Some(port) => text.push_str(&format!("Host: {}:{}\n", host, port)),
+            None => text.push_str(&format!("Host: {}\n", host)),
+        }
+    }
+    if !headers.contains_key(reqwest::header::USER_AGENT) {
+        text.push_str(&format!("User-Agent: {}\n", user_agent));
+    }
+    for (name, value) in headers {
+        let value = if REDACTED.contains(name) {
+            "<redacted>"
+        } else {
+            value.to_str().unwrap_or("<binary>")
+        };
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+    text.push_str("---request end---\n");
+    text
+}
+
+/// Status line and headers, indented like wget's `-S` output
+pub fn response_text(version: Version, status: StatusCode, headers: &HeaderMap) -> String {
+    let mut text = format!(
+        "  {:?} {} {}\n",
+        version,
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    );
+    for (name, value) in headers {
+        text.push_str(&format!(
+            "  {}: {}\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    text
+}
+
+/// Status line and headers as they came over the wire, ending with the
+/// blank line that separates them from the body
+pub fn raw_headers(version: Version, status: StatusCode, headers: &HeaderMap) -> Vec<u8> {
+    let mut raw = format!(
+        "{:?} {} {}\r\n",
+        version,
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in headers {
+        raw.extend_from_slice(name.as_str().as_bytes());
+        raw.extend_from_slice(b": ");
+        raw.extend_from_slice(value.as_bytes());
+        raw.extend_from_slice(b"\r\n");
+    }
+    raw.extend_from_slice(b"\r\n");
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{ACCEPT_ENCODING, CONTENT_LENGTH, HeaderValue};
+
+    #[test]
+    fn test_debug_request_redacts_credentials() {
+        let url = Url::parse("http://example.com:8080/file.iso?mirror=1").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+        headers.insert(COOKIE, HeaderValue::from_static("session=secret"));
+
+        let text = request_text(&Method::GET, &url, &headers, "wget-rs/0.1.0");
+        assert!(text.contains("GET /file.iso?mirror=1 HTTP/1.1\n"));
+        assert!(text.contains("Host: example.com:8080\n"));
+        assert!(text.contains("User-Agent: wget-rs/0.1.0\n"));
+        assert!(text.contains("accept-encoding: identity\n"));
+        assert!(text.contains("authorization: <redacted>\n"));
+        assert!(text.contains("cookie: <redacted>\n"));
+        assert!(!text.contains("dXNlcjpwYXNz") && !text.contains("secret"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("3"));
+        let raw = raw_headers(Version::HTTP_11, StatusCode::OK, &headers);
+        assert_eq!(raw, b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\n");
+    }
+}
@@ -1,2 +1,14 @@
 pub mod client;
+pub mod compression;
+pub mod headers;
+pub mod pinned;
+pub mod proxy;
+pub mod segments;
+pub mod timeouts;
+pub mod tls;
 pub use client::HttpClient;
+pub use compression::{Compression, CompressionConfig};
+pub use headers::HeaderOutput;
+pub use proxy::ProxyConfig;
+pub use timeouts::Timeouts;
+pub use tls::TlsConfig;
@@ -0,0 +1,302 @@
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::http::proxy::ProxyConfig;
+use crate::http::timeouts::Timeouts;
+use crate::http::tls::TlsConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hyper::Uri;
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use reqwest::header::{ACCEPT, HeaderValue, USER_AGENT};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_native_tls::TlsStream;
+use tokio_socks::tcp::Socks5Stream;
+use url::Url;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Longest proxy answer to `CONNECT` read before giving up
+const MAX_CONNECT_RESPONSE: usize = 16 * 1024;
+
+/// HTTPS transport for `--pinnedpubkey`. reqwest has no hook between the
+/// TLS handshake and the request, so pinned HTTPS requests go through this
+/// hyper client instead. Its connector checks the server key before handing
+/// the connection over, so a mismatch fails before any request byte
+/// (`Authorization` and `Cookie` included) is written.
+#[derive(Clone)]
+pub struct PinnedTransport {
+    client: hyper::Client<PinnedConnector>,
+    user_agent: HeaderValue,
+    total: Option<Duration>,
+}
+
+impl PinnedTransport {
+    /// `None` when no key is pinned
+    pub fn new(
+        tls: &TlsConfig,
+        proxy: &ProxyConfig,
+        timeouts: Timeouts,
+        user_agent: &str,
+    ) -> Result<Option<Self>, DownloadError> {
+        if tls.pinned_pubkeys.is_empty() {
+            return Ok(None);
+        }
+        let connector = PinnedConnector {
+            tls: tls.native_connector()?.into(),
+            config: Arc::new(tls.clone()),
+            proxy: proxy.clone(),
+            timeouts,
+        };
+        let user_agent = HeaderValue::from_str(user_agent).map_err(|e| DownloadError {
+            kind: ErrorKind::Input,
+            message: format!("Invalid user agent {:?}: {}", user_agent, e),
+        })?;
+        Ok(Some(Self {
+            client: hyper::Client::builder().build(connector),
+            user_agent,
+            total: timeouts.total,
+        }))
+    }
+
+    /// Sends a bodiless request with the headers reqwest would add. The
+    /// `--deadline` limit covers the wait for the response headers.
+    pub async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, BoxError> {
+        let mut outgoing = hyper::Request::builder()
+            .method(request.method().clone())
+            .uri(request.url().as_str())
+            .version(request.version())
+            .body(hyper::Body::empty())?;
+        *outgoing.headers_mut() = request.headers().clone();
+        let headers = outgoing.headers_mut();
+        if !headers.contains_key(USER_AGENT) {
+            headers.insert(USER_AGENT, self.user_agent.clone());
+        }
+        if !headers.contains_key(ACCEPT) {
+            headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        }
+
+        let sent = self.client.request(outgoing);
+        let response = match self.total {
+            Some(limit) => tokio::time::timeout(limit, sent)
+                .await
+                .map_err(|_| timed_out("request", limit))??,
+            None => sent.await?,
+        };
+        Ok(response.map(reqwest::Body::from).into())
+    }
+}
+
+/// Opens TCP connections (through the proxy, if any), runs the TLS
+/// handshake and checks the server key against the pins
+#[derive(Clone)]
+struct PinnedConnector {
+    tls: tokio_native_tls::TlsConnector,
+    config: Arc<TlsConfig>,
+    proxy: ProxyConfig,
+    timeouts: Timeouts,
+}
+
+impl Service<Uri> for PinnedConnector {
+    type Response = PinnedStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<PinnedStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move { connector.connect(uri).await })
+    }
+}
+
+impl PinnedConnector {
+    async fn connect(self, uri: Uri) -> Result<PinnedStream, BoxError> {
+        let authority = uri.authority().ok_or("URL without a host")?;
+        let host = authority.host().trim_matches(['[', ']']).to_string();
+        let port = uri.port_u16().unwrap_or(443);
+        let tcp = match self.proxy.proxy_for(&Url::parse(&uri.to_string())?) {
+            Some(proxy) => self.tunnel(&proxy, authority.host(), &host, port).await?,
+            None => self.tcp(&host, port).await?,
+        };
+
+        let handshake = self.tls.connect(&host, tcp);
+        let stream = match self.timeouts.connect {
+            Some(limit) => tokio::time::timeout(limit, handshake)
+                .await
+                .map_err(|_| timed_out("TLS handshake", limit))??,
+            None => handshake.await?,
+        };
+        let der = stream
+            .get_ref()
+            .peer_certificate()?
+            .ok_or("server sent no certificate")?
+            .to_der()?;
+        self.config.check_pins(&der)?;
+        Ok(PinnedStream(stream))
+    }
+
+    /// TCP connection bounded by the DNS and connect timeouts
+    async fn tcp(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let addrs = within(self.timeouts.dns, "DNS lookup", async {
+            tokio::net::lookup_host((host, port)).await
+        })
+        .await?;
+        let mut last = io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses found for {}", host),
+        );
+        for addr in addrs {
+            match within(self.timeouts.connect, "connect", TcpStream::connect(addr)).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    /// Connection to `host:port` through `proxy`: a `CONNECT` tunnel for
+    /// HTTP proxies, or a SOCKS5 session (`socks5h` lets the proxy resolve
+    /// `host`)
+    async fn tunnel(
+        &self,
+        proxy: &Url,
+        authority_host: &str,
+        host: &str,
+        port: u16,
+    ) -> Result<TcpStream, BoxError> {
+        let proxy_host = proxy.host_str().ok_or("proxy URL without a host")?;
+        let proxy_port = proxy.port_or_known_default().unwrap_or(1080);
+        let mut stream = self
+            .tcp(proxy_host.trim_matches(['[', ']']), proxy_port)
+            .await?;
+        let (user, password) = (proxy.username(), proxy.password().unwrap_or(""));
+
+        match proxy.scheme() {
+            "http" => {
+                let target = format!("{}:{}", authority_host, port);
+                let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+                if !user.is_empty() {
+                    let credentials = BASE64.encode(format!("{}:{}", user, password));
+                    request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+                }
+                request.push_str("\r\n");
+                stream.write_all(request.as_bytes()).await?;
+
+                let head = read_head(&mut stream).await?;
+                let status = head.lines().next().unwrap_or_default();
+                if status.split_whitespace().nth(1) != Some("200") {
+                    return Err(format!("proxy refused the tunnel: {}", status).into());
+                }
+                Ok(stream)
+            }
+            "socks5" | "socks5h" => {
+                let socks = if proxy.scheme() == "socks5h" {
+                    connect_socks(stream, (host, port), user, password).await?
+                } else {
+                    let addr = tokio::net::lookup_host((host, port))
+                        .await?
+                        .next()
+                        .ok_or_else(|| format!("no addresses found for {}", host))?;
+                    connect_socks(stream, addr, user, password).await?
+                };
+                Ok(socks.into_inner())
+            }
+            other => Err(format!("{}:// proxies cannot be used with --pinnedpubkey", other).into()),
+        }
+    }
+}
+
+async fn connect_socks<'t>(
+    stream: TcpStream,
+    target: impl tokio_socks::IntoTargetAddr<'t>,
+    user: &str,
+    password: &str,
+) -> Result<Socks5Stream<TcpStream>, tokio_socks::Error> {
+    if user.is_empty() {
+        Socks5Stream::connect_with_socket(stream, target).await
+    } else {
+        Socks5Stream::connect_with_password_and_socket(stream, target, user, password).await
+    }
+}
+
+/// The proxy's answer to `CONNECT`, up to the blank line
+async fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_CONNECT_RESPONSE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "proxy answer to CONNECT too long",
+            ));
+        }
+        head.push(stream.read_u8().await?);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// `future`, failing with `TimedOut` once `limit` (if any) has passed
+async fn within<T>(
+    limit: Option<Duration>,
+    what: &str,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    let Some(limit) = limit else {
+        return future.await;
+    };
+    tokio::time::timeout(limit, future)
+        .await
+        .unwrap_or_else(|_| Err(timed_out(what, limit)))
+}
+
+fn timed_out(what: &str, limit: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("{} timed out after {:.1}s", what, limit.as_secs_f64()),
+    )
+}
+
+/// A TLS connection whose server key matched a pin
+struct PinnedStream(TlsStream<TcpStream>);
+
+impl Connection for PinnedStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for PinnedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PinnedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
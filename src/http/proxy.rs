@@ -0,0 +1,354 @@
+use reqwest::{ClientBuilder, Proxy};
+use std::net::IpAddr;
+use url::Url;
+
+/// Proxy settings discovered from the `*_proxy` environment variables
+/// and adjusted by the command line.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    http: Option<Url>,
+    https: Option<Url>,
+    all: Option<Url>,
+    no_proxy: NoProxyList,
+}
+
+impl ProxyConfig {
+    /// Reads `http_proxy`, `https_proxy`, `all_proxy` and `no_proxy`
+    /// (lowercase first, then uppercase) from the process environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup<F>(lookup: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| {
+            lookup(name)
+                .or_else(|| lookup(&name.to_uppercase()))
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        Ok(Self {
+            http: var("http_proxy").map(|v| parse_proxy_url(&v)).transpose()?,
+            https: var("https_proxy")
+                .map(|v| parse_proxy_url(&v))
+                .transpose()?,
+            all: var("all_proxy").map(|v| parse_proxy_url(&v)).transpose()?,
+            no_proxy: var("no_proxy")
+                .map(|v| NoProxyList::parse(&v))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Applies `--proxy-user` / `--proxy-password` to every configured proxy,
+    /// overriding any credentials embedded in the environment variables.
+    pub fn with_credentials(mut self, user: Option<&str>, password: Option<&str>) -> Self {
+        if user.is_none() && password.is_none() {
+            return self;
+        }
+        for proxy in [&mut self.http, &mut self.https, &mut self.all]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(user) = user {
+                let _ = proxy.set_username(user);
+            }
+            if let Some(password) = password {
+                let _ = proxy.set_password(Some(password));
+            }
+        }
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.http.is_some() || self.https.is_some() || self.all.is_some()
+    }
+
+    /// Picks the proxy to use for `target`, honouring `no_proxy`.
+    pub fn proxy_for(&self, target: &Url) -> Option<Url> {
+        if self.no_proxy.matches(target) {
+            return None;
+        }
+        let specific = match target.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        };
+        specific.or(self.all.as_ref()).cloned()
+    }
+
+    /// Installs this configuration on a client builder. reqwest's own
+    /// environment discovery is always switched off so that `--no-proxy`
+    /// and the matching rules here are the only source of truth.
+    pub fn apply(&self, builder: ClientBuilder) -> ClientBuilder {
+        let builder = builder.no_proxy();
+        if !self.is_enabled() {
+            return builder;
+        }
+        let config = self.clone();
+        builder.proxy(Proxy::custom(move |url| config.proxy_for(url)))
+    }
+}
+
+fn parse_proxy_url(value: &str) -> Result<Url, String> {
+    let value = value.trim();
+    let with_scheme = if value.contains("://") {
+        value.to_string()
+    } else {
+        format!("http://{}", value)
+    };
+
+    let url =
+        Url::parse(&with_scheme).map_err(|e| format!("Invalid proxy URL {:?}: {}", value, e))?;
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(url),
+        other => Err(format!(
+            "Unsupported proxy scheme {:?} in {:?} (use http, https, socks5 or socks5h)",
+            other, value
+        )),
+    }
+}
+
+/// Parsed `no_proxy` list: `*`, IP addresses, CIDR blocks and domain suffixes.
+#[derive(Debug, Clone, Default)]
+pub struct NoProxyList {
+    entries: Vec<NoProxyEntry>,
+}
+
+#[derive(Debug, Clone)]
+enum NoProxyEntry {
+    Wildcard,
+    Network(IpAddr, u8),
+    Domain(String),
+}
+
+impl NoProxyList {
+    pub fn parse(value: &str) -> Self {
+        let entries = value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                if entry == "*" {
+                    return NoProxyEntry::Wildcard;
+                }
+                if let Some((addr, prefix)) = entry.split_once('/')
+                    && let (Ok(addr), Ok(prefix)) = (addr.parse::<IpAddr>(), prefix.parse::<u8>())
+                {
+                    return NoProxyEntry::Network(addr, prefix);
+                }
+                let bare = entry.trim_start_matches('[').trim_end_matches(']');
+                if let Ok(addr) = bare.parse::<IpAddr>() {
+                    let bits = if addr.is_ipv4() { 32 } else { 128 };
+                    return NoProxyEntry::Network(addr, bits);
+                }
+                let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+                NoProxyEntry::Domain(domain.to_ascii_lowercase())
+            })
+            .collect();
+
+        Self { entries }
+    }
+
+    pub fn matches(&self, target: &Url) -> bool {
+        let Some(host) = target.host_str() else {
+            return false;
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        let ip = host.parse::<IpAddr>().ok();
+
+        self.entries.iter().any(|entry| match entry {
+            NoProxyEntry::Wildcard => true,
+            NoProxyEntry::Network(network, prefix) => {
+                ip.is_some_and(|ip| ip_in_network(ip, *network, *prefix))
+            }
+            NoProxyEntry::Domain(domain) => {
+                host == *domain || host.ends_with(&format!(".{}", domain))
+            }
+        })
+    }
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let prefix = u32::from(prefix.min(32));
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let prefix = u32::from(prefix.min(128));
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CompressionConfig, HttpClient, Timeouts, TlsConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_proxy_selection_by_scheme() {
+        let config = ProxyConfig::from_lookup(env(&[
+            ("http_proxy", "proxy.local:3128"),
+            ("ALL_PROXY", "socks5h://socks.local"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            config
+                .proxy_for(&url("http://example.com/"))
+                .unwrap()
+                .as_str(),
+            "http://proxy.local:3128/"
+        );
+        assert_eq!(
+            config
+                .proxy_for(&url("https://example.com/"))
+                .unwrap()
+                .scheme(),
+            "socks5h"
+        );
+        assert!(ProxyConfig::from_lookup(env(&[("https_proxy", "ftp://x")])).is_err());
+    }
+
+    #[test]
+    fn test_no_proxy_matching() {
+        let list = NoProxyList::parse("localhost, .corp.example, 10.0.0.0/8, ::1, 192.168.1.5");
+
+        assert!(list.matches(&url("http://localhost:8080/")));
+        assert!(list.matches(&url("http://build.corp.example/")));
+        assert!(list.matches(&url("http://corp.example/")));
+        assert!(!list.matches(&url("http://notcorp.example/")));
+        assert!(list.matches(&url("http://10.42.0.7/")));
+        assert!(!list.matches(&url("http://11.0.0.1/")));
+        assert!(list.matches(&url("http://[::1]/")));
+        assert!(list.matches(&url("http://192.168.1.5/")));
+        assert!(!list.matches(&url("http://192.168.1.6/")));
+        assert!(NoProxyList::parse("*").matches(&url("https://anything.test/")));
+    }
+
+    #[tokio::test]
+    async fn test_http_proxy_stand_in() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase()
+        });
+
+        let proxy_url = format!("http://{}", addr);
+        let config =
+            ProxyConfig::from_lookup(move |name| (name == "http_proxy").then(|| proxy_url.clone()))
+                .unwrap()
+                .with_credentials(Some("user"), Some("secret"));
+        let client = HttpClient::with_config(
+            None,
+            None,
+            None,
+            Timeouts::default(),
+            &config,
+            &TlsConfig::default(),
+            CompressionConfig::default(),
+        )
+        .unwrap();
+
+        let response = client
+            .download_silent("http://files.example.invalid/a.txt")
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("get http://files.example.invalid/a.txt http/1.1"));
+        assert!(request.contains("proxy-authorization: basic dxnlcjpzzwnyzxq="));
+    }
+
+    #[tokio::test]
+    async fn test_socks5h_stand_in_resolves_remotely() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            // Greeting: version, method count, methods; answer "no auth".
+            let mut greeting = [0u8; 2];
+            socket.read_exact(&mut greeting).await.unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            socket.read_exact(&mut methods).await.unwrap();
+            socket.write_all(&[5, 0]).await.unwrap();
+
+            // CONNECT request; address type 3 means the hostname was sent as-is.
+            let mut head = [0u8; 4];
+            socket.read_exact(&mut head).await.unwrap();
+            assert_eq!(head[3], 3, "hostname should be resolved by the proxy");
+            let mut len = [0u8; 1];
+            socket.read_exact(&mut len).await.unwrap();
+            let mut host = vec![0u8; len[0] as usize + 2];
+            socket.read_exact(&mut host).await.unwrap();
+            socket
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+                .await
+                .unwrap();
+
+            let mut buf = vec![0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nsocks",
+                )
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&host[..len[0] as usize]).to_string()
+        });
+
+        let proxy_url = format!("socks5h://{}", addr);
+        let config =
+            ProxyConfig::from_lookup(move |name| (name == "all_proxy").then(|| proxy_url.clone()))
+                .unwrap();
+        let client = HttpClient::with_config(
+            None,
+            None,
+            None,
+            Timeouts::default(),
+            &config,
+            &TlsConfig::default(),
+            CompressionConfig::default(),
+        )
+        .unwrap();
+
+        let response = client
+            .download_silent("http://only-the-proxy-knows.invalid/")
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "socks");
+        assert_eq!(proxy.await.unwrap(), "only-the-proxy-knows.invalid");
+    }
+}
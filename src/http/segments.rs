@@ -0,0 +1,628 @@
+use crate::http::HttpClient;
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::output::progress::Progress;
+use crate::rate::Throttle;
+use crate::resume::{ContentRange, ResumeHandler, ResumeValidators};
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// Smallest range worth its own connection, and the least a stolen half may hold
+const MIN_SEGMENT_SIZE: u64 = 256 * 1024;
+/// How often each connection flushes its writes and the segment progress
+/// is written to the sidecar file
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One byte range of the output file; `pos` is the next byte to fetch and
+/// `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    start: u64,
+    end: u64,
+    pos: u64,
+    /// Bytes before this are flushed to the file; the sidecar records this
+    /// rather than `pos`, so it never claims bytes still in flight
+    flushed: u64,
+    /// A connection is currently filling this segment (never persisted)
+    active: bool,
+}
+
+impl Segment {
+    fn new(start: u64, end: u64) -> Self {
+        Self {
+            start,
+            end,
+            pos: start,
+            flushed: start,
+            active: false,
+        }
+    }
+
+    fn remaining(&self) -> u64 {
+        self.end.saturating_sub(self.pos)
+    }
+}
+
+/// Layout and progress of a segmented download. It is saved next to the
+/// output file as `<file>.segments` so that `-c` continues every segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentTable {
+    total: u64,
+    segments: Vec<Segment>,
+}
+
+impl SegmentTable {
+    /// Splits `total` bytes into at most `count` equal segments of at least
+    /// `MIN_SEGMENT_SIZE` each
+    pub fn split(total: u64, count: usize) -> Self {
+        let count = (count as u64).min(total / MIN_SEGMENT_SIZE).max(1);
+        let size = total.div_ceil(count);
+        let segments = (0..count)
+            .map(|i| Segment::new(i * size, ((i + 1) * size).min(total)))
+            .filter(|segment| segment.start < segment.end)
+            .collect();
+        Self { total, segments }
+    }
+
+    pub fn sidecar_path(file_path: &Path) -> PathBuf {
+        let mut name = file_path.as_os_str().to_owned();
+        name.push(".segments");
+        PathBuf::from(name)
+    }
+
+    /// Drops the sidecar of `file_path`, if any
+    pub async fn remove(file_path: &Path) {
+        let _ = fs::remove_file(Self::sidecar_path(file_path)).await;
+    }
+
+    /// A `total N` line followed by one `start end pos` line per segment
+    fn render(&self) -> String {
+        let mut text = format!("total {}\n", self.total);
+        for segment in &self.segments {
+            text.push_str(&format!(
+                "{} {} {}\n",
+                segment.start, segment.end, segment.flushed
+            ));
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        let total = lines.next()?.strip_prefix("total ")?.trim().parse().ok()?;
+        let mut segments = Vec::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .map(|field| field.parse().ok())
+                .collect::<Option<_>>()?;
+            let [start, end, pos] = fields[..] else {
+                return None;
+            };
+            if start > pos || pos > end || end > total {
+                return None;
+            }
+            segments.push(Segment {
+                pos,
+                flushed: pos,
+                ..Segment::new(start, end)
+            });
+        }
+        Some(Self { total, segments })
+    }
+
+    fn downloaded(&self) -> u64 {
+        self.segments.iter().map(|s| s.pos - s.start).sum()
+    }
+
+    /// Bytes filled without a gap from the start of the file
+    fn contiguous(&self) -> u64 {
+        let mut segments = self.segments.clone();
+        segments.sort_by_key(|s| s.start);
+        let mut filled = 0;
+        for segment in segments {
+            if segment.start != filled {
+                break;
+            }
+            filled = segment.pos;
+            if segment.pos < segment.end {
+                break;
+            }
+        }
+        filled
+    }
+
+    /// Hands out the next piece of work: an idle unfinished segment, or else
+    /// the back half of the busy segment with the most bytes left
+    fn claim(&mut self) -> Option<usize> {
+        if let Some(index) = self
+            .segments
+            .iter()
+            .position(|s| !s.active && s.remaining() > 0)
+        {
+            self.segments[index].active = true;
+            return Some(index);
+        }
+
+        let (index, victim) = self
+            .segments
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.active)
+            .max_by_key(|(_, s)| s.remaining())?;
+        if victim.remaining() < 2 * MIN_SEGMENT_SIZE {
+            return None;
+        }
+        let mid = victim.pos + victim.remaining() / 2;
+        let end = victim.end;
+        self.segments[index].end = mid;
+        self.segments.push(Segment {
+            active: true,
+            ..Segment::new(mid, end)
+        });
+        Some(self.segments.len() - 1)
+    }
+}
+
+/// State shared by the connections of one segmented download
+struct Transfer<'a> {
+    client: &'a HttpClient,
+    /// Mirrors of the file; connections spread over them and fail over
+    urls: &'a [&'a str],
+    silent: bool,
+    file_path: &'a Path,
+    /// Pins every range request to the version seen by the probe
+    if_range: Option<String>,
+    sidecar: PathBuf,
+    table: Mutex<SegmentTable>,
+    /// Shared by all connections; per-host limits follow the first mirror
+    throttle: Throttle,
+    progress: Option<Progress>,
+    last_save: tokio::sync::Mutex<Instant>,
+}
+
+impl Transfer<'_> {
+    /// One connection: keeps claiming segments until nothing is left to do.
+    /// Connection `worker` starts on mirror `worker % mirrors` and moves to
+    /// the next mirror when a request fails, giving up once all have failed.
+    async fn run_worker(&self, worker: usize) -> Result<(), DownloadError> {
+        let mut mirror = worker % self.urls.len();
+        let mut failures = 0;
+        loop {
+            let Some(index) = self.table.lock().unwrap().claim() else {
+                return Ok(());
+            };
+            let result = self.fetch_segment(index, self.urls[mirror]).await;
+            self.table.lock().unwrap().segments[index].active = false;
+            match result {
+                Ok(()) => failures = 0,
+                Err(e) if e.kind == ErrorKind::Interrupted => return Err(e),
+                Err(e) => {
+                    failures += 1;
+                    if failures >= self.urls.len() {
+                        return Err(e);
+                    }
+                    if !self.silent {
+                        println!("{}: {}, trying the next mirror", self.urls[mirror], e.message);
+                    }
+                    mirror = (mirror + 1) % self.urls.len();
+                }
+            }
+        }
+    }
+
+    async fn fetch_segment(&self, index: usize, url: &str) -> Result<(), DownloadError> {
+        let Segment { mut pos, end, .. } = self.table.lock().unwrap().segments[index];
+        let response = self
+            .client
+            .request_range(url, pos, Some(end - 1), self.if_range.as_deref())
+            .await?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(DownloadError {
+                kind: ErrorKind::Http,
+                message: format!("Server answered a range request with {}", response.status()),
+            });
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.file_path)
+            .await
+            .map_err(|e| io_error("Failed to open file", e))?;
+        file.seek(SeekFrom::Start(pos))
+            .await
+            .map_err(|e| io_error("Failed to seek", e))?;
+
+        let mut stream = self.client.body_stream(response);
+        let mut last_flush = Instant::now();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(DownloadError::read_failed)?;
+            // The segment may have shrunk since another connection stole its tail
+            let end = self.table.lock().unwrap().segments[index].end;
+            let take = chunk.len().min(end.saturating_sub(pos) as usize);
+
+            self.throttle.consume(take as u64).await?;
+            file.write_all(&chunk[..take])
+                .await
+                .map_err(|e| io_error("Failed to write chunk", e))?;
+
+            pos += take as u64;
+            self.client.quota().add(take as u64);
+            let (advanced, end) = {
+                let mut table = self.table.lock().unwrap();
+                let segment = &mut table.segments[index];
+                let advanced = pos.min(segment.end).saturating_sub(segment.pos);
+                segment.pos += advanced;
+                (advanced, segment.end)
+            };
+            if let Some(pb) = &self.progress {
+                pb.inc(advanced);
+            }
+            if pos >= end || last_flush.elapsed() >= SAVE_INTERVAL {
+                self.flush(&mut file, index, pos).await?;
+                last_flush = Instant::now();
+                self.save(false).await?;
+            }
+            if pos >= end {
+                return Ok(());
+            }
+        }
+        self.flush(&mut file, index, pos).await?;
+
+        let end = self.table.lock().unwrap().segments[index].end;
+        if pos < end {
+            return Err(DownloadError {
+                kind: ErrorKind::Network,
+                message: format!(
+                    "Connection closed {} bytes before the end of a segment",
+                    end - pos
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Flushes what this connection wrote to segment `index`, up to `pos`,
+    /// so that the next save may record it
+    async fn flush(&self, file: &mut File, index: usize, pos: u64) -> Result<(), DownloadError> {
+        file.flush()
+            .await
+            .map_err(|e| io_error("Failed to flush file", e))?;
+        let mut table = self.table.lock().unwrap();
+        let segment = &mut table.segments[index];
+        segment.flushed = segment.flushed.max(pos.min(segment.end));
+        Ok(())
+    }
+
+    /// Writes the sidecar, at most once per `SAVE_INTERVAL` unless forced
+    async fn save(&self, force: bool) -> Result<(), DownloadError> {
+        let mut last_save = self.last_save.lock().await;
+        if !force && last_save.elapsed() < SAVE_INTERVAL {
+            return Ok(());
+        }
+        let text = self.table.lock().unwrap().render();
+        fs::write(&self.sidecar, text)
+            .await
+            .map_err(|e| io_error("Failed to save segment progress", e))?;
+        *last_save = Instant::now();
+        Ok(())
+    }
+}
+
+/// Downloads `urls` (one URL, or mirrors of the same file) into `file_path`
+/// over up to `count` parallel range requests. Returns `Ok(None)` when no
+/// server can serve ranges or the file is too small to split, so the caller
+/// falls back to a single stream.
+pub async fn download(
+    client: &HttpClient,
+    urls: &[&str],
+    file_path: &Path,
+    count: usize,
+    resume: bool,
+    silent: bool,
+    throttle: Throttle,
+) -> Result<Option<u64>, DownloadError> {
+    let mut probed = None;
+    for url in urls {
+        match probe_length(client, url).await {
+            Ok(Some(found)) => {
+                probed = Some(found);
+                break;
+            }
+            Ok(None) => {}
+            Err(e) if urls.len() == 1 => return Err(e),
+            // Another mirror may still answer
+            Err(_) => {}
+        }
+    }
+    let Some((total, mut validators)) = probed else {
+        if !silent {
+            println!("Server does not support byte ranges, using a single connection");
+        }
+        return Ok(None);
+    };
+    client.check_size(urls[0], total)?;
+    if urls.len() > 1 {
+        // Mirrors have their own ETags and dates; only the length is shared
+        validators = ResumeValidators {
+            length: Some(total),
+            ..ResumeValidators::default()
+        };
+    }
+
+    let sidecar = SegmentTable::sidecar_path(file_path);
+    let file_len = ResumeHandler::get_resume_position(file_path).await;
+    // Segment progress only counts if the remote file is still the same version
+    let same_version = ResumeValidators::load(file_path)
+        .await
+        .is_some_and(|saved| saved == validators);
+    let saved = if resume && file_len == total && same_version {
+        fs::read_to_string(&sidecar)
+            .await
+            .ok()
+            .and_then(|text| SegmentTable::parse(&text))
+            .filter(|table| table.total == total)
+    } else {
+        None
+    };
+
+    let table = match saved {
+        Some(table) => table,
+        // A partial file from a single-stream download is continued as one
+        None if resume && file_len > 0 => return Ok(None),
+        None => {
+            let table = SegmentTable::split(total, count);
+            if table.segments.len() < 2 {
+                return Ok(None);
+            }
+            let file = File::create(file_path)
+                .await
+                .map_err(|e| io_error("Failed to create file", e))?;
+            file.set_len(total)
+                .await
+                .map_err(|e| io_error("Failed to preallocate file", e))?;
+            let _ = validators.save(file_path).await;
+            table
+        }
+    };
+
+    let already = table.downloaded();
+    client.jobs().add_skipped_bytes(already);
+    if !silent {
+        println!(
+            "content size: {} [~{:.2}MB], {} connections, {} mirror(s)",
+            total,
+            total as f64 / 1_048_576.0,
+            count,
+            urls.len()
+        );
+        println!("saving file to: ./{}", file_path.display());
+    }
+    let progress = client.progress().start(total, already, silent);
+
+    let transfer = Transfer {
+        client,
+        urls,
+        silent,
+        file_path,
+        if_range: validators.if_range().map(str::to_string),
+        sidecar,
+        table: Mutex::new(table),
+        throttle,
+        progress,
+        last_save: tokio::sync::Mutex::new(Instant::now()),
+    };
+    let workers = (0..count).map(|worker| transfer.run_worker(worker));
+    if let Err(e) = futures_util::future::try_join_all(workers).await {
+        // Keep what was fetched so that -c can continue each segment
+        let _ = transfer.save(true).await;
+        return Err(e);
+    }
+
+    SegmentTable::remove(file_path).await;
+    ResumeValidators::remove(file_path).await;
+    if let Some(pb) = transfer.progress {
+        pb.finish();
+        println!();
+    }
+    if !silent {
+        println!("Downloaded [{}]", urls[0]);
+    }
+    Ok(Some(total - already))
+}
+
+/// Where a single stream continues `file_path` from with `-c`. A file left
+/// by an interrupted segmented download is preallocated to its full size,
+/// so its length means nothing while `<file>.segments` exists: only the
+/// bytes filled from the start count. The file is cut back to those and the
+/// segment table dropped, or truncated when the table is unreadable.
+pub async fn resume_position(file_path: &Path) -> u64 {
+    let sidecar = SegmentTable::sidecar_path(file_path);
+    let Ok(text) = fs::read_to_string(&sidecar).await else {
+        return ResumeHandler::get_resume_position(file_path).await;
+    };
+    let filled = SegmentTable::parse(&text).map_or(0, |table| table.contiguous());
+    let cut = match OpenOptions::new().write(true).open(file_path).await {
+        Ok(file) => file.set_len(filled).await.is_ok(),
+        Err(_) => false,
+    };
+    let _ = fs::remove_file(&sidecar).await;
+    if cut { filled } else { 0 }
+}
+
+/// Asks for the first byte only. A 206 with `Content-Range: bytes 0-0/N`
+/// means ranges are served and the file is N bytes long.
+async fn probe_length(
+    client: &HttpClient,
+    url: &str,
+) -> Result<Option<(u64, ResumeValidators)>, DownloadError> {
+    let response = client.request_range(url, 0, Some(0), None).await?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Ok(None);
+    }
+    let total = ContentRange::from_response(&response).and_then(|range| range.total);
+    Ok(total.map(|total| (total, ResumeValidators::from_response(&response))))
+}
+
+fn io_error(context: &str, e: std::io::Error) -> DownloadError {
+    DownloadError {
+        kind: ErrorKind::Io,
+        message: format!("{}: {}", context, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_claim_steals_back_half_of_busiest_segment() {
+        let mut table = SegmentTable::split(4 * MIN_SEGMENT_SIZE, 2);
+        assert_eq!(table.segments.len(), 2);
+        assert_eq!(table.claim(), Some(0));
+        assert_eq!(table.claim(), Some(1));
+
+        table.segments[1].pos = table.segments[1].end;
+        table.segments[1].flushed = table.segments[1].end;
+        table.segments[1].active = false;
+        assert_eq!(table.claim(), Some(2));
+        assert_eq!(table.segments[0].end, MIN_SEGMENT_SIZE);
+        assert_eq!(table.segments[2].start, MIN_SEGMENT_SIZE);
+        assert_eq!(table.segments[2].end, 2 * MIN_SEGMENT_SIZE);
+
+        // Halves below the minimum are not split again
+        assert_eq!(table.claim(), None);
+
+        // Only flushed bytes are saved
+        table.segments[0].pos += 10;
+        let saved = SegmentTable::parse(&table.render()).unwrap();
+        assert_eq!(saved.total, table.total);
+        assert_eq!(saved.downloaded(), table.downloaded() - 10);
+        assert!(saved.segments.iter().all(|s| !s.active));
+    }
+
+    /// Serves `body`, honouring `Range: bytes=a-b` and `bytes=a-` unless
+    /// `ranges` is false
+    async fn serve(body: Vec<u8>, ranges: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let body = body.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                    let range = request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("range: bytes="))
+                        .and_then(|range| range.trim().split_once('-'))
+                        .map(|(a, b)| {
+                            let start = a.parse::<usize>().unwrap();
+                            (start, b.parse::<usize>().unwrap_or(body.len().max(1) - 1))
+                        });
+                    let (head, part) = match range {
+                        Some((start, _)) if ranges && start >= body.len() => (
+                            format!(
+                                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n",
+                                body.len()
+                            ),
+                            &body[..0],
+                        ),
+                        Some((start, end)) if ranges => (
+                            format!(
+                                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n",
+                                start,
+                                end,
+                                body.len(),
+                                end - start + 1
+                            ),
+                            &body[start..=end],
+                        ),
+                        _ => (
+                            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", body.len()),
+                            &body[..],
+                        ),
+                    };
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(b"Connection: close\r\n\r\n").await;
+                    let _ = socket.write_all(part).await;
+                });
+            }
+        });
+        format!("http://{}/big.bin", addr)
+    }
+
+    #[tokio::test]
+    async fn test_segmented_download_and_fallback() {
+        let body: Vec<u8> = (0..4 * MIN_SEGMENT_SIZE as usize + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let dir = std::env::temp_dir().join(format!("wget-segments-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.bin");
+        let client = HttpClient::new();
+
+        let url = serve(body.clone(), true).await;
+        let fetched = download(&client, &[&url], &path, 4, false, true, Throttle::default())
+            .await
+            .unwrap();
+        assert_eq!(fetched, Some(body.len() as u64));
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert!(!SegmentTable::sidecar_path(&path).exists());
+
+        let url = serve(body.clone(), false).await;
+        assert_eq!(
+            download(&client, &[&url], &path, 4, false, true, Throttle::default())
+                .await
+                .unwrap(),
+            None
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_single_stream_resume_ignores_preallocated_length() {
+        let body: Vec<u8> = (0..4 * MIN_SEGMENT_SIZE as usize)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let dir = std::env::temp_dir().join(format!("wget-segments-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.bin");
+
+        // An interrupted 4-way download: the first segment and part of the
+        // third are done, the rest of the full-length file is zeros
+        let mut partial = vec![0u8; body.len()];
+        let quarter = MIN_SEGMENT_SIZE as usize;
+        partial[..quarter].copy_from_slice(&body[..quarter]);
+        partial[2 * quarter..2 * quarter + 100]
+            .copy_from_slice(&body[2 * quarter..2 * quarter + 100]);
+        std::fs::write(&path, &partial).unwrap();
+        let mut table = SegmentTable::split(body.len() as u64, 4);
+        table.segments[0].flushed = table.segments[0].end;
+        table.segments[2].flushed += 100;
+        std::fs::write(SegmentTable::sidecar_path(&path), table.render()).unwrap();
+
+        // A single stream must neither take the file for complete (the
+        // server answers 416 past the end) nor keep the zeros
+        let url = serve(body.clone(), true).await;
+        let fetched = HttpClient::new()
+            .download_to_file_with_resume(&url, &path, true, true)
+            .await
+            .unwrap();
+        assert_eq!(fetched, (body.len() - quarter) as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert!(!SegmentTable::sidecar_path(&path).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use crate::http::compression::BodyStream;
+use futures_util::StreamExt;
+use hyper::client::connect::dns::Name;
+use reqwest::ClientBuilder;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// wget's default `--read-timeout`
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(900);
+
+/// Separate limits for each phase of a transfer. `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    /// Longest idle gap while waiting for headers or between body chunks
+    pub read: Option<Duration>,
+    /// Overall deadline for a single request, body included
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            dns: None,
+            connect: None,
+            read: Some(DEFAULT_READ_TIMEOUT),
+            total: None,
+        }
+    }
+}
+
+impl Timeouts {
+    /// Builds timeouts from the command line, where `--timeout` sets the DNS,
+    /// connect and read limits and the specific flags override it. Zero
+    /// disables a limit, as in wget.
+    pub fn from_secs(
+        timeout: Option<f64>,
+        dns: Option<f64>,
+        connect: Option<f64>,
+        read: Option<f64>,
+        total: Option<f64>,
+    ) -> Self {
+        // Values come through `check_secs`; anything it would reject means no limit
+        let limit = |secs: f64| {
+            (secs > 0.0)
+                .then(|| Duration::try_from_secs_f64(secs).ok())
+                .flatten()
+        };
+        let defaults = Self::default();
+
+        Self {
+            dns: dns.or(timeout).and_then(limit),
+            connect: connect.or(timeout).and_then(limit),
+            read: match read.or(timeout) {
+                Some(secs) => limit(secs),
+                None => defaults.read,
+            },
+            total: total.and_then(limit),
+        }
+    }
+
+    /// Checks one timeout given in seconds: a non-negative number small
+    /// enough to be held as a `Duration`.
+    pub fn check_secs(secs: f64) -> Result<(), String> {
+        if secs.is_nan() || secs < 0.0 {
+            return Err("Timeouts must be non-negative numbers of seconds".into());
+        }
+        Duration::try_from_secs_f64(secs)
+            .map(|_| ())
+            .map_err(|_| format!("Timeout {:?} is out of range", secs))
+    }
+
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(connect) = self.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        if let Some(total) = self.total {
+            builder = builder.timeout(total);
+        }
+        if let Some(dns) = self.dns {
+            builder = builder.dns_resolver(Arc::new(TimeoutResolver { timeout: dns }));
+        }
+        builder
+    }
+
+    /// Budget for getting response headers back after sending a request:
+    /// name lookup and connect (when limited) plus one read interval.
+    pub fn response_wait(&self) -> Option<Duration> {
+        self.read
+            .map(|read| {
+                read.saturating_add(self.dns.unwrap_or_default())
+                    .saturating_add(self.connect.unwrap_or_default())
+            })
+    }
+
+    /// Fails the body stream when no chunk arrives within the read timeout,
+    /// so stalled transfers are detected even if the connection stays open.
+    pub fn watch_body(&self, body: BodyStream) -> BodyStream {
+        let Some(limit) = self.read else {
+            return body;
+        };
+
+        Box::pin(futures_util::stream::unfold(
+            Some(body),
+            move |state| async move {
+                let mut body = state?;
+                match tokio::time::timeout(limit, body.next()).await {
+                    Ok(Some(chunk)) => Some((chunk, Some(body))),
+                    Ok(None) => None,
+                    Err(_) => Some((
+                        Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!(
+                                "no data received for {:.1}s (read timeout)",
+                                limit.as_secs_f64()
+                            ),
+                        )),
+                        None,
+                    )),
+                }
+            },
+        ))
+    }
+}
+
+/// System resolver with an upper bound on lookup time (`--dns-timeout`)
+struct TimeoutResolver {
+    timeout: Duration,
+}
+
+impl Resolve for TimeoutResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let limit = self.timeout;
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs = tokio::time::timeout(limit, tokio::net::lookup_host((host.as_str(), 0)))
+                .await
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("DNS lookup of {} timed out", host),
+                    )
+                })??;
+            Ok(Box::new(addrs.collect::<Vec<_>>().into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_timeout_flag_precedence() {
+        let timeouts = Timeouts::from_secs(Some(10.0), None, Some(2.5), None, None);
+        assert_eq!(timeouts.dns, Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.connect, Some(Duration::from_millis(2500)));
+        assert_eq!(timeouts.read, Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.total, None);
+
+        let timeouts = Timeouts::from_secs(None, None, None, Some(0.0), Some(60.0));
+        assert_eq!(timeouts.read, None);
+        assert_eq!(timeouts.total, Some(Duration::from_secs(60)));
+        assert_eq!(
+            Timeouts::from_secs(None, None, None, None, None),
+            Timeouts::default()
+        );
+
+        assert!(Timeouts::check_secs(1e30).is_err());
+        assert!(Timeouts::check_secs(f64::INFINITY).is_err());
+        assert!(Timeouts::check_secs(-1.0).is_err());
+        let huge = Timeouts {
+            dns: Some(Duration::MAX),
+            connect: Some(Duration::MAX),
+            ..Timeouts::default()
+        };
+        assert_eq!(huge.response_wait(), Some(Duration::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_body_hits_read_timeout() {
+        let first = futures_util::stream::once(async { Ok(Bytes::from_static(b"partial")) });
+        let stalled = first.chain(futures_util::stream::pending());
+        let timeouts = Timeouts {
+            read: Some(Duration::from_millis(50)),
+            ..Timeouts::default()
+        };
+
+        let mut body = timeouts.watch_body(Box::pin(stalled));
+        assert_eq!(body.next().await.unwrap().unwrap(), "partial");
+        let err = body.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(body.next().await.is_none());
+    }
+}
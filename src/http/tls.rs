@@ -0,0 +1,457 @@
+use crate::http::client::{DownloadError, ErrorKind};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use reqwest::ClientBuilder;
+use reqwest::tls::{Certificate, Identity, Version};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format of the client certificate passed with `--certificate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CertificateType {
+    #[value(name = "PEM")]
+    Pem,
+    #[value(name = "P12")]
+    Pkcs12,
+}
+
+/// TLS options applied when building the HTTP client.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub ca_certificate: Option<PathBuf>,
+    pub ca_directory: Option<PathBuf>,
+    pub certificate: Option<PathBuf>,
+    pub certificate_type: Option<CertificateType>,
+    pub certificate_password: Option<String>,
+    pub private_key: Option<PathBuf>,
+    pub check_certificate: bool,
+    pub pinned_pubkeys: Vec<[u8; 32]>,
+    pub min_version: Option<Version>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            ca_certificate: None,
+            ca_directory: None,
+            certificate: None,
+            certificate_type: None,
+            certificate_password: None,
+            private_key: None,
+            check_certificate: true,
+            pinned_pubkeys: Vec::new(),
+            min_version: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Installs CA roots, the client identity and verification settings on
+    /// a client builder. Files are read here so errors surface before any
+    /// request is sent.
+    pub fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, DownloadError> {
+        for cert in self.root_certificates()? {
+            builder = builder.add_root_certificate(Certificate::from_der(&cert).map_err(ca_error)?);
+        }
+
+        if let Some(identity) = self.load_identity()? {
+            let identity = match identity {
+                ClientIdentity::Pkcs12 { der, password } => {
+                    Identity::from_pkcs12_der(&der, &password)
+                }
+                ClientIdentity::Pkcs8 { cert, key } => Identity::from_pkcs8_pem(&cert, &key),
+            };
+            builder = builder.identity(identity.map_err(|e| self.identity_error(e))?);
+        }
+
+        if !self.check_certificate {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        if let Some(version) = self.checked_min_version()? {
+            builder = builder.min_tls_version(version);
+        }
+
+        Ok(builder)
+    }
+
+    /// The same settings as a native-tls connector, for connections that
+    /// check `--pinnedpubkey` during the handshake
+    pub fn native_connector(&self) -> Result<native_tls::TlsConnector, DownloadError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for cert in self.root_certificates()? {
+            builder
+                .add_root_certificate(native_tls::Certificate::from_der(&cert).map_err(ca_error)?);
+        }
+
+        if let Some(identity) = self.load_identity()? {
+            let identity = match identity {
+                ClientIdentity::Pkcs12 { der, password } => {
+                    native_tls::Identity::from_pkcs12(&der, &password)
+                }
+                ClientIdentity::Pkcs8 { cert, key } => {
+                    native_tls::Identity::from_pkcs8(&cert, &key)
+                }
+            };
+            builder.identity(identity.map_err(|e| self.identity_error(e))?);
+        }
+
+        builder.danger_accept_invalid_certs(!self.check_certificate);
+
+        if let Some(version) = self.checked_min_version()? {
+            let protocol = if version == Version::TLS_1_0 {
+                native_tls::Protocol::Tlsv10
+            } else if version == Version::TLS_1_1 {
+                native_tls::Protocol::Tlsv11
+            } else {
+                native_tls::Protocol::Tlsv12
+            };
+            builder.min_protocol_version(Some(protocol));
+        }
+
+        builder.build().map_err(|e| DownloadError {
+            kind: ErrorKind::Tls,
+            message: format!("Failed to initialise TLS: {}", e),
+        })
+    }
+
+    /// Checks a server certificate (DER) against `--pinnedpubkey`
+    pub fn check_pins(&self, der: &[u8]) -> Result<(), PinMismatch> {
+        let digest = spki_sha256(der)
+            .map_err(|e| PinMismatch(format!("unreadable server certificate: {}", e)))?;
+        if self.pinned_pubkeys.contains(&digest) {
+            Ok(())
+        } else {
+            Err(PinMismatch(format!(
+                "public key sha256//{} does not match --pinnedpubkey",
+                BASE64.encode(digest)
+            )))
+        }
+    }
+
+    /// `--secure-protocol`, unless the native TLS backend cannot honour it
+    fn checked_min_version(&self) -> Result<Option<Version>, DownloadError> {
+        if self.min_version == Some(Version::TLS_1_3) {
+            return Err(DownloadError {
+                kind: ErrorKind::Tls,
+                message: "TLSv1_3 as a minimum version is not supported by the native TLS backend"
+                    .into(),
+            });
+        }
+        Ok(self.min_version)
+    }
+
+    /// DER encodings of the `--ca-certificate` and `--ca-directory` roots
+    fn root_certificates(&self) -> Result<Vec<Vec<u8>>, DownloadError> {
+        let mut certs = Vec::new();
+        if let Some(path) = &self.ca_certificate {
+            certs.extend(load_certificates(path)?);
+        }
+
+        if let Some(dir) = &self.ca_directory {
+            let entries = fs::read_dir(dir).map_err(|e| config_error(dir, e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() {
+                    certs.extend(load_certificates(&path)?);
+                }
+            }
+        }
+        Ok(certs)
+    }
+
+    fn identity_error(&self, e: impl std::fmt::Display) -> DownloadError {
+        config_error(self.certificate.as_deref().unwrap_or(Path::new("")), e)
+    }
+
+    fn load_identity(&self) -> Result<Option<ClientIdentity>, DownloadError> {
+        let Some(cert_path) = &self.certificate else {
+            return Ok(None);
+        };
+        let cert = fs::read(cert_path).map_err(|e| config_error(cert_path, e))?;
+
+        let is_pkcs12 = match self.certificate_type {
+            Some(kind) => kind == CertificateType::Pkcs12,
+            None => cert_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("p12") || ext.eq_ignore_ascii_case("pfx")
+                }),
+        };
+
+        if is_pkcs12 {
+            let password = self.certificate_password.clone().unwrap_or_default();
+            Ok(Some(ClientIdentity::Pkcs12 {
+                der: cert,
+                password,
+            }))
+        } else {
+            // The key may live in the certificate file itself; native-tls
+            // only accepts PKCS#8, so normalise PKCS#1 and SEC1 keys first.
+            let key_pem = match &self.private_key {
+                Some(key_path) => fs::read(key_path).map_err(|e| config_error(key_path, e))?,
+                None => cert.clone(),
+            };
+            let key = PKey::private_key_from_pem(&key_pem)
+                .and_then(|key| key.private_key_to_pem_pkcs8())
+                .map_err(|e| config_error(self.private_key.as_deref().unwrap_or(cert_path), e))?;
+            Ok(Some(ClientIdentity::Pkcs8 { cert, key }))
+        }
+    }
+}
+
+/// `--certificate` as read from disk, for either TLS stack
+enum ClientIdentity {
+    Pkcs12 { der: Vec<u8>, password: String },
+    Pkcs8 { cert: Vec<u8>, key: Vec<u8> },
+}
+
+/// The server key is not one of the `--pinnedpubkey` keys. Returned by the
+/// pinning connector, so the handshake fails before any request is sent.
+#[derive(Debug)]
+pub struct PinMismatch(String);
+
+impl std::fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+/// Parses `--pinnedpubkey`: either `sha256//<base64>` hashes separated by
+/// `;`, or a path to a PEM or DER public key.
+pub fn parse_pinned_pubkeys(value: &str) -> Result<Vec<[u8; 32]>, String> {
+    if value.trim_start().starts_with("sha256//") {
+        return value
+            .split(';')
+            .map(|pin| {
+                let encoded = pin
+                    .trim()
+                    .strip_prefix("sha256//")
+                    .ok_or_else(|| format!("Invalid pin {:?}: expected sha256//<base64>", pin))?;
+                let bytes = BASE64
+                    .decode(encoded)
+                    .map_err(|e| format!("Invalid pin {:?}: {}", pin, e))?;
+                <[u8; 32]>::try_from(bytes.as_slice())
+                    .map_err(|_| format!("Invalid pin {:?}: not a SHA-256 digest", pin))
+            })
+            .collect();
+    }
+
+    let data = fs::read(value).map_err(|e| format!("Cannot read public key {:?}: {}", value, e))?;
+    let key = PKey::public_key_from_pem(&data)
+        .or_else(|_| PKey::public_key_from_der(&data))
+        .map_err(|e| format!("Invalid public key {:?}: {}", value, e))?;
+    let spki = key
+        .public_key_to_der()
+        .map_err(|e| format!("Invalid public key {:?}: {}", value, e))?;
+    Ok(vec![openssl::sha::sha256(&spki)])
+}
+
+/// Parses `--secure-protocol`; `auto` leaves the backend default in place.
+pub fn parse_secure_protocol(value: &str) -> Result<Option<Version>, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "auto" => Ok(None),
+        "tlsv1" | "tlsv1_0" => Ok(Some(Version::TLS_1_0)),
+        "tlsv1_1" => Ok(Some(Version::TLS_1_1)),
+        "tlsv1_2" => Ok(Some(Version::TLS_1_2)),
+        "tlsv1_3" => Ok(Some(Version::TLS_1_3)),
+        _ => Err(format!(
+            "Unknown secure protocol {:?} (use auto, TLSv1, TLSv1_1, TLSv1_2 or TLSv1_3)",
+            value
+        )),
+    }
+}
+
+fn spki_sha256(der: &[u8]) -> Result<[u8; 32], openssl::error::ErrorStack> {
+    let cert = X509::from_der(der)?;
+    let spki = cert.public_key()?.public_key_to_der()?;
+    Ok(openssl::sha::sha256(&spki))
+}
+
+/// DER certificates of a PEM bundle or a DER file
+fn load_certificates(path: &Path) -> Result<Vec<Vec<u8>>, DownloadError> {
+    let data = fs::read(path).map_err(|e| config_error(path, e))?;
+    let certs = if data.starts_with(b"-----") || data.windows(11).any(|w| w == b"-----BEGIN ") {
+        X509::stack_from_pem(&data)
+    } else {
+        X509::from_der(&data).map(|cert| vec![cert])
+    };
+    certs
+        .and_then(|certs| certs.iter().map(|cert| cert.to_der()).collect())
+        .map_err(|e| config_error(path, e))
+}
+
+fn ca_error(e: impl std::fmt::Display) -> DownloadError {
+    DownloadError {
+        kind: ErrorKind::Tls,
+        message: format!("Invalid CA certificate: {}", e),
+    }
+}
+
+fn config_error(path: &Path, e: impl std::fmt::Display) -> DownloadError {
+    DownloadError {
+        kind: ErrorKind::Tls,
+        message: format!("Cannot load {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CompressionConfig, HttpClient, ProxyConfig, Timeouts};
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use openssl::x509::X509NameBuilder;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn self_signed() -> (X509, PKey<openssl::pkey::Private>) {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    /// Serves `connections` HTTPS requests with a fixed body on a background
+    /// thread, counting the requests that arrive
+    fn serve(
+        cert: &X509,
+        key: &PKey<openssl::pkey::Private>,
+        connections: usize,
+    ) -> (u16, Arc<AtomicUsize>) {
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(key).unwrap();
+        acceptor.set_certificate(cert).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let Ok(mut stream) = acceptor.accept(stream.unwrap()) else {
+                    continue;
+                };
+                let mut buf = [0u8; 4096];
+                if !matches!(stream.read(&mut buf), Ok(n) if n > 0) {
+                    continue;
+                }
+                received.fetch_add(1, Ordering::SeqCst);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nsecure",
+                );
+            }
+        });
+        (port, requests)
+    }
+
+    fn client(tls: &TlsConfig) -> Result<HttpClient, DownloadError> {
+        HttpClient::with_config(
+            None,
+            None,
+            None,
+            Timeouts::default(),
+            &ProxyConfig::default(),
+            tls,
+            CompressionConfig::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_and_pinning() {
+        let (cert, key) = self_signed();
+        let (port, requests) = serve(&cert, &key, 4);
+        let url = format!("https://localhost:{}/", port);
+
+        let err = client(&TlsConfig::default())
+            .unwrap()
+            .download_silent(&url)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Tls);
+
+        let dir = std::env::temp_dir().join(format!("wget-rs-tls-{}", port));
+        fs::create_dir_all(&dir).unwrap();
+        let ca = dir.join("ca.pem");
+        fs::write(&ca, cert.to_pem().unwrap()).unwrap();
+
+        let trusted = TlsConfig {
+            ca_certificate: Some(ca.clone()),
+            ..TlsConfig::default()
+        };
+        let response = client(&trusted)
+            .unwrap()
+            .download_silent(&url)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "secure");
+
+        let pin = BASE64.encode(openssl::sha::sha256(&key.public_key_to_der().unwrap()));
+        let pinned = TlsConfig {
+            pinned_pubkeys: parse_pinned_pubkeys(&format!("sha256//{}", pin)).unwrap(),
+            ..trusted.clone()
+        };
+        assert!(client(&pinned).unwrap().download_silent(&url).await.is_ok());
+
+        // A wrong pin fails the handshake: the server never sees the request
+        let wrong_pin = TlsConfig {
+            pinned_pubkeys: vec![[0u8; 32]],
+            ..trusted
+        };
+        let answered = requests.load(Ordering::SeqCst);
+        let err = client(&wrong_pin)
+            .unwrap()
+            .download_silent(&url)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Tls);
+        assert!(err.message.contains("does not match --pinnedpubkey"));
+        assert_eq!(requests.load(Ordering::SeqCst), answered);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_parse_secure_protocol() {
+        assert_eq!(parse_secure_protocol("auto").unwrap(), None);
+        assert_eq!(
+            parse_secure_protocol("TLSv1_2").unwrap(),
+            Some(Version::TLS_1_2)
+        );
+        assert!(parse_secure_protocol("SSLv3").is_err());
+        assert!(parse_pinned_pubkeys("sha256//AAAA").is_err());
+    }
+}
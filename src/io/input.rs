@@ -1,12 +1,31 @@
-use crate::http::client::DownloadError;
+use crate::checksum::Checksum;
+use crate::http::client::{DownloadError, ErrorKind};
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
+/// One line of an input file: a URL, optionally followed by the checksum
+/// the downloaded file must have (`https://host/a.iso sha256:<hex>`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputEntry {
+    pub url: String,
+    pub checksum: Option<Checksum>,
+}
+
 /// Reads URLs from a given file (one per line).
 /// Ignores empty lines and lines starting with `#` (comments).
 pub async fn read_urls_from_file(path: &Path) -> Result<Vec<String>, DownloadError> {
+    Ok(read_entries_from_file(path)
+        .await?
+        .into_iter()
+        .map(|entry| entry.url)
+        .collect())
+}
+
+/// Like `read_urls_from_file`, keeping any per-line checksums
+pub async fn read_entries_from_file(path: &Path) -> Result<Vec<InputEntry>, DownloadError> {
     let file = File::open(path).await.map_err(|e| DownloadError {
+        kind: ErrorKind::Io,
         message: format!("Failed to open input file {:?}: {}", path, e),
     })?;
 
@@ -15,6 +34,7 @@ pub async fn read_urls_from_file(path: &Path) -> Result<Vec<String>, DownloadErr
     let mut urls = Vec::new();
 
     while let Some(line) = lines.next_line().await.map_err(|e| DownloadError {
+        kind: ErrorKind::Io,
         message: format!("Failed to read line from file {:?}: {}", path, e),
     })? {
         let trimmed = line.trim();
@@ -24,9 +44,22 @@ pub async fn read_urls_from_file(path: &Path) -> Result<Vec<String>, DownloadErr
             continue;
         }
 
+        let mut fields = trimmed.split_whitespace();
+        let url = fields.next().unwrap_or_default();
+        let checksum = match fields.next().map(Checksum::parse).transpose() {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                eprintln!("Warning: Skipping {}: {}", url, e);
+                continue;
+            }
+        };
+
         // Basic URL validation - check if it starts with http:// or https://
-        if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
-            urls.push(trimmed.to_string());
+        if url.starts_with("http://") || url.starts_with("https://") {
+            urls.push(InputEntry {
+                url: url.to_string(),
+                checksum,
+            });
         } else {
             eprintln!("Warning: Skipping invalid URL: {}", trimmed);
         }
@@ -34,6 +67,7 @@ pub async fn read_urls_from_file(path: &Path) -> Result<Vec<String>, DownloadErr
 
     if urls.is_empty() {
         return Err(DownloadError {
+            kind: ErrorKind::Input,
             message: format!("No valid URLs found in file {:?}", path),
         });
     }
@@ -1,2 +1,2 @@
 pub mod input;
-pub use input::read_urls_from_file;
+pub use input::{InputEntry, read_entries_from_file, read_urls_from_file};
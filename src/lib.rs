@@ -0,0 +1,34 @@
+//! A wget clone, usable as a library: build a [`Downloader`], then
+//! [`download`](Downloader::download) single URLs, stream the events of a
+//! [`Batch`] or crawl a site with a [`Mirror`]. None of these print; the
+//! `wget` binary is a thin command line over the same modules (see [`cli`]).
+//! The modules themselves are crate-private; the types a program needs are
+//! re-exported here.
+
+pub(crate) mod api;
+pub(crate) mod background;
+pub(crate) mod checksum;
+pub mod cli;
+pub(crate) mod config;
+pub(crate) mod control;
+pub(crate) mod download;
+pub(crate) mod http;
+pub(crate) mod io;
+pub(crate) mod metalink;
+pub(crate) mod mirror;
+pub(crate) mod output;
+pub(crate) mod rate;
+pub(crate) mod resume;
+pub(crate) mod retry;
+pub(crate) mod session;
+pub(crate) mod signals;
+pub(crate) mod spider;
+pub(crate) mod utils;
+
+pub use api::{Batch, Downloader, DownloaderBuilder, Mirror};
+pub use checksum::{Checksum, ChecksumAlgorithm, ChecksumPolicy};
+pub use download::DownloadResult;
+pub use http::client::{DownloadError, ErrorKind};
+pub use http::{Compression, ProxyConfig, Timeouts, TlsConfig};
+pub use output::{Event, RunSummary};
+pub use rate::RateSchedule;
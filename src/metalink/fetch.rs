@@ -0,0 +1,107 @@
+use crate::http::HttpClient;
+use crate::http::client::{DownloadError, ErrorKind};
+use crate::metalink::MetalinkFile;
+use std::path::Path;
+
+/// Downloads each file of a Metalink document (or the single file of a
+/// mirror list) into `output_dir`, failing over between its mirrors and
+/// verifying the declared size and strongest hash. Returns the failures.
+pub async fn download_files(
+    client: &HttpClient,
+    files: &[MetalinkFile],
+    output_dir: Option<&Path>,
+    preferred_location: Option<&str>,
+    resume: bool,
+    silent: bool,
+) -> Vec<DownloadError> {
+    let mut errors = Vec::new();
+    for file in files {
+        let file_path = match output_dir {
+            Some(dir) => dir.join(&file.name),
+            None => file.name.clone(),
+        };
+        // Registered under the mirror tried first
+        let url = file
+            .ordered_mirrors(preferred_location)
+            .first()
+            .map_or_else(|| file.name.display().to_string(), |mirror| mirror.url.clone());
+        let job = client.jobs().add(&url);
+        job.set_path(&file_path);
+        let outcome = job
+            .run(async {
+                job.start().await;
+                download_file(client, file, &file_path, preferred_location, resume, silent).await
+            })
+            .await;
+        if let Err(e) = outcome {
+            if e.kind == ErrorKind::Interrupted {
+                errors.push(e);
+                break;
+            }
+            if e.kind == ErrorKind::Skipped {
+                println!("Skipped [{}]: {}", file.name.display(), e.message);
+            } else if e.kind == ErrorKind::Cancelled {
+                println!("Cancelled [{}]", file.name.display());
+            } else {
+                eprintln!("Download failed for [{}]: {}", file.name.display(), e);
+            }
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+async fn download_file(
+    client: &HttpClient,
+    file: &MetalinkFile,
+    file_path: &Path,
+    preferred_location: Option<&str>,
+    resume: bool,
+    silent: bool,
+) -> Result<u64, DownloadError> {
+    if let Some(parent) = file_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| DownloadError {
+                kind: ErrorKind::Io,
+                message: format!("Failed to create directory {:?}: {}", parent, e),
+            })?;
+    }
+
+    let mirrors: Vec<&str> = file
+        .ordered_mirrors(preferred_location)
+        .iter()
+        .map(|mirror| mirror.url.as_str())
+        .collect();
+    if !silent {
+        println!(
+            "{}: {} mirror(s), trying {} first",
+            file.name.display(),
+            mirrors.len(),
+            mirrors[0]
+        );
+    }
+
+    let fetched = client
+        .download_from_mirrors(&mirrors, file_path, resume, silent, file.strongest_hash())
+        .await?;
+
+    if let Some(size) = file.size {
+        let actual = tokio::fs::metadata(file_path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if actual != size {
+            return Err(DownloadError {
+                kind: ErrorKind::Checksum,
+                message: format!(
+                    "Size mismatch for {}: expected {} bytes, got {}",
+                    file_path.display(),
+                    size,
+                    actual
+                ),
+            });
+        }
+    }
+    Ok(fetched)
+}
@@ -0,0 +1,5 @@
+pub mod fetch;
+pub mod parser;
+
+pub use fetch::download_files;
+pub use parser::{MetalinkFile, parse_metalink};
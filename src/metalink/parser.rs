@@ -0,0 +1,216 @@
+use crate::checksum::digest::{Checksum, ChecksumAlgorithm};
+use std::path::{Component, Path, PathBuf};
+
+const METALINK_NS: &str = "urn:ietf:params:xml:ns:metalink";
+
+/// One place a file can be downloaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    pub url: String,
+    /// 1 is the most preferred; mirrors without a priority come last
+    pub priority: Option<u32>,
+    /// ISO 3166-1 country code of the server
+    pub location: Option<String>,
+}
+
+/// A file described by a Metalink document or a mirror list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkFile {
+    /// Relative path to save the file under
+    pub name: PathBuf,
+    pub size: Option<u64>,
+    pub hashes: Vec<Checksum>,
+    pub mirrors: Vec<Mirror>,
+}
+
+impl MetalinkFile {
+    /// A file whose mirrors come from a plain list of URLs, most preferred
+    /// first (`--mirror-list`)
+    pub fn from_mirror_list(name: PathBuf, urls: Vec<String>) -> Self {
+        let mirrors = urls
+            .into_iter()
+            .enumerate()
+            .map(|(i, url)| Mirror {
+                url,
+                priority: Some(i as u32 + 1),
+                location: None,
+            })
+            .collect();
+        Self {
+            name,
+            size: None,
+            hashes: Vec::new(),
+            mirrors,
+        }
+    }
+
+    /// Mirrors in the order they should be tried: those in
+    /// `preferred_location` first, then by priority
+    pub fn ordered_mirrors(&self, preferred_location: Option<&str>) -> Vec<&Mirror> {
+        let mut mirrors: Vec<&Mirror> = self.mirrors.iter().collect();
+        mirrors.sort_by_key(|mirror| {
+            let elsewhere = match (preferred_location, mirror.location.as_deref()) {
+                (Some(preferred), Some(location)) => !preferred.eq_ignore_ascii_case(location),
+                _ => true,
+            };
+            (elsewhere, mirror.priority.unwrap_or(u32::MAX))
+        });
+        mirrors
+    }
+
+    /// The strongest declared hash this client can check
+    pub fn strongest_hash(&self) -> Option<&Checksum> {
+        let rank = |algorithm| match algorithm {
+            ChecksumAlgorithm::Sha512 => 0,
+            ChecksumAlgorithm::Sha256 => 1,
+            ChecksumAlgorithm::Blake3 => 2,
+            ChecksumAlgorithm::Sha1 => 3,
+            ChecksumAlgorithm::Md5 => 4,
+        };
+        self.hashes
+            .iter()
+            .min_by_key(|checksum| rank(checksum.algorithm))
+    }
+}
+
+/// Parses a Metalink v4 document (RFC 5854). Hash types this client does
+/// not know and elements such as `<pieces>` or `<metaurl>` are ignored.
+pub fn parse_metalink(xml: &str) -> Result<Vec<MetalinkFile>, String> {
+    let document =
+        roxmltree::Document::parse(xml).map_err(|e| format!("Invalid Metalink XML: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name((METALINK_NS, "metalink")) {
+        return Err(
+            "Not a Metalink v4 document (expected <metalink> in the RFC 5854 namespace)".into(),
+        );
+    }
+
+    let text = |node: &roxmltree::Node| node.text().unwrap_or_default().trim().to_string();
+
+    let mut files = Vec::new();
+    for file in element(&root, "file") {
+        let name = file
+            .attribute("name")
+            .ok_or("<file> without a name attribute")?;
+        let name = safe_relative_path(name)?;
+
+        let size = match element(&file, "size").first() {
+            Some(size) => Some(
+                text(size)
+                    .parse()
+                    .map_err(|_| format!("Invalid <size> for {}", name.display()))?,
+            ),
+            None => None,
+        };
+
+        let hashes = element(&file, "hash")
+            .iter()
+            .filter_map(|hash| {
+                let algorithm = ChecksumAlgorithm::parse(hash.attribute("type")?)?;
+                Checksum::from_hex(algorithm, &text(hash)).ok()
+            })
+            .collect();
+
+        let mirrors: Vec<Mirror> = element(&file, "url")
+            .iter()
+            .map(|url| Mirror {
+                url: text(url),
+                priority: url.attribute("priority").and_then(|p| p.parse().ok()),
+                location: url.attribute("location").map(str::to_string),
+            })
+            .filter(|mirror| {
+                mirror.url.starts_with("http://") || mirror.url.starts_with("https://")
+            })
+            .collect();
+        if mirrors.is_empty() {
+            return Err(format!("No HTTP(S) URLs for {}", name.display()));
+        }
+
+        files.push(MetalinkFile {
+            name,
+            size,
+            hashes,
+            mirrors,
+        });
+    }
+
+    if files.is_empty() {
+        return Err("Metalink document lists no files".into());
+    }
+    Ok(files)
+}
+
+/// Child elements of `node` named `name` in the Metalink namespace
+fn element<'a, 'input>(
+    node: &roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Vec<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .filter(|child| child.has_tag_name((METALINK_NS, name)))
+        .collect()
+}
+
+/// File names come from the document, so they must stay below the
+/// download directory
+fn safe_relative_path(name: &str) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    let safe = !name.is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if safe {
+        Ok(path.to_path_buf())
+    } else {
+        Err(format!("Unsafe file name {:?} in Metalink document", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="images/distro.iso">
+    <size>14471447</size>
+    <hash type="sha-1">a9993e364706816aba3e25717850c26c9cd0d89d</hash>
+    <hash type="sha-256">ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad</hash>
+    <url location="us" priority="2">https://us.example.com/distro.iso</url>
+    <url location="de" priority="3">https://de.example.com/distro.iso</url>
+    <url priority="1">https://cdn.example.com/distro.iso</url>
+    <url>ftp://old.example.com/distro.iso</url>
+  </file>
+</metalink>"#;
+
+    #[test]
+    fn test_parse_and_order_mirrors() {
+        let files = parse_metalink(DOCUMENT).unwrap();
+        let file = &files[0];
+        assert_eq!(file.name, PathBuf::from("images/distro.iso"));
+        assert_eq!(file.size, Some(14471447));
+        assert_eq!(
+            file.strongest_hash().unwrap().algorithm,
+            ChecksumAlgorithm::Sha256
+        );
+        assert_eq!(file.mirrors.len(), 3);
+
+        let urls = |location| {
+            file.ordered_mirrors(location)
+                .iter()
+                .map(|mirror| mirror.url.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            urls(None),
+            [
+                "https://cdn.example.com/distro.iso",
+                "https://us.example.com/distro.iso",
+                "https://de.example.com/distro.iso"
+            ]
+        );
+        assert_eq!(urls(Some("DE"))[0], "https://de.example.com/distro.iso");
+
+        let escaping = DOCUMENT.replace("images/distro.iso", "../distro.iso");
+        assert!(parse_metalink(&escaping).is_err());
+    }
+}
@@ -1,8 +1,14 @@
+use crate::control::Jobs;
+use crate::download::DownloadOptions;
 use crate::http::HttpClient;
+use crate::http::client::{DownloadError, ErrorKind};
 use crate::mirror::parser;
+use crate::session::Session;
+use crate::spider::SpiderReport;
 use crate::utils::url::extract_filename;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use tokio::fs;
 use url::Url;
 
@@ -12,24 +18,57 @@ pub struct MirrorCrawler {
     queue: VecDeque<String>,
     base_url: Url,
     output_dir: PathBuf,
+    /// Check links without saving anything (`--spider`)
+    spider: bool,
+    /// Pages linking to each queued URL, for the broken links report
+    referrers: HashMap<String, Vec<String>>,
+    report: SpiderReport,
+    /// `--session` journal the frontier is saved to and restored from
+    session: Option<Session>,
+    /// Tells the crawl to stop after SIGINT or SIGTERM
+    jobs: Jobs,
 }
 
 impl MirrorCrawler {
-    pub fn new(base_url: &str, output_dir: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Crawler fetching through `client`, which should report to
+    /// `options.jobs`
+    pub fn new(
+        base_url: &str,
+        output_dir: Option<&Path>,
+        client: HttpClient,
+        options: &DownloadOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let parsed_url = Url::parse(base_url)?;
         let dir = output_dir.unwrap_or_else(|| Path::new(".")).to_path_buf();
         
         Ok(MirrorCrawler {
-            client: HttpClient::new(),
+            client,
             visited: HashSet::new(),
             queue: VecDeque::new(),
             base_url: parsed_url,
             output_dir: dir,
+            spider: false,
+            referrers: HashMap::new(),
+            report: SpiderReport::new(),
+            session: options.session.clone(),
+            jobs: options.jobs.clone(),
         })
     }
 
-    pub fn new_silent(base_url: &str, output_dir: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
-        Self::new(base_url, output_dir)
+    /// Crawler for `--spider`: follows links through HTML pages but saves
+    /// nothing, collecting a broken links report instead
+    pub fn new_spider(
+        base_url: &str,
+        client: HttpClient,
+        options: &DownloadOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut crawler = Self::new(base_url, None, client, options)?;
+        crawler.spider = true;
+        Ok(crawler)
+    }
+
+    pub fn spider_report(&self) -> &SpiderReport {
+        &self.report
     }
 
     pub async fn mirror(
@@ -37,16 +76,15 @@ impl MirrorCrawler {
         reject_suffixes: &Option<String>,
         exclude_dirs: &Option<String>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.mirror_internal(reject_suffixes, exclude_dirs, false, None).await
+        self.mirror_internal(reject_suffixes, exclude_dirs, false).await
     }
 
     pub async fn mirror_silent(
         &mut self,
         reject_suffixes: &Option<String>,
         exclude_dirs: &Option<String>,
-        logger: &crate::background::BackgroundLogger,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.mirror_internal(reject_suffixes, exclude_dirs, true, Some(logger)).await
+        self.mirror_internal(reject_suffixes, exclude_dirs, true).await
     }
 
     async fn mirror_internal(
@@ -54,11 +92,30 @@ impl MirrorCrawler {
         reject_suffixes: &Option<String>,
         exclude_dirs: &Option<String>,
         silent: bool,
-        logger: Option<&crate::background::BackgroundLogger>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.queue.push_back(self.base_url.to_string());
-        
+        match self.session.as_ref().and_then(Session::frontier) {
+            Some((visited, queue)) => {
+                if !silent {
+                    println!("Resuming crawl: {} pages done, {} queued", visited.len(), queue.len());
+                }
+                self.visited = visited;
+                self.queue = queue;
+            }
+            None => self.enqueue(self.base_url.to_string()),
+        }
+
         while let Some(url) = self.queue.pop_front() {
+            if self.jobs.is_interrupted() {
+                self.queue.push_front(url);
+                break;
+            }
+            if !self.spider && self.client.quota().exceeded() {
+                if !silent {
+                    println!("Download quota exceeded, not following further links");
+                }
+                break;
+            }
+
             if self.visited.contains(&url) {
                 continue;
             }
@@ -73,39 +130,96 @@ impl MirrorCrawler {
             
             self.visited.insert(url.clone());
             
-            match self.download_and_parse_internal(&url, silent, logger).await {
+            let outcome = if self.spider {
+                self.check_and_parse(&url, silent).await
+            } else {
+                self.download_page(&url, silent).await
+            };
+
+            // A page cut short by a signal stays queued
+            if self.jobs.is_interrupted() {
+                self.visited.remove(&url);
+                self.queue.push_front(url);
+                break;
+            }
+
+            match outcome {
                 Ok(links) => {
+                    if self.spider {
+                        self.report.record_ok();
+                    }
                     for link in links {
+                        if self.spider {
+                            let pages = self.referrers.entry(link.clone()).or_default();
+                            if !pages.contains(&url) {
+                                pages.push(url.clone());
+                            }
+                        }
                         if !self.visited.contains(&link) {
-                            self.queue.push_back(link);
+                            self.enqueue(link);
                         }
                     }
                 }
+                Err(e) if self.spider => {
+                    let message = match e.downcast_ref::<DownloadError>() {
+                        Some(e) => e.message.clone(),
+                        None => e.to_string(),
+                    };
+                    if !silent {
+                        println!("Broken: {} ({})", url, message);
+                    }
+                    self.report.record_broken(&url, &message, Vec::new());
+                }
+                Err(ref e) if let Some(reason) = skip_reason(e.as_ref()) => {
+                    if !silent {
+                        println!("Skipped {}: {}", url, reason);
+                    }
+                }
                 Err(e) => {
-                    if silent {
-                        if let Some(logger) = logger {
-                            logger.log_error(&url, &e.to_string());
-                        }
-                    } else {
+                    if !silent {
                         eprintln!("Failed to download {}: {}", url, e);
                     }
                 }
             }
+            // Only now, with its links queued, is the page done for a rerun
+            if let Some(session) = &self.session {
+                session.record_visited(&url);
+            }
+        }
+
+        if self.jobs.is_interrupted() && !silent {
+            println!(
+                "Interrupted: {} pages done, {} still queued",
+                self.visited.len(),
+                self.queue.len()
+            );
+        }
+
+        // Pages found later may link to URLs that already failed, so the
+        // referrers are only attached once the crawl is over
+        if self.spider {
+            self.report.attach_referrers(&self.referrers);
         }
         
         Ok(())
     }
 
+    fn enqueue(&mut self, url: String) {
+        if let Some(session) = &self.session {
+            session.record_queued(&url);
+        }
+        self.queue.push_back(url);
+    }
+
     #[allow(dead_code)]
     async fn download_and_parse(&self, url: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        self.download_and_parse_internal(url, false, None).await
+        self.download_and_parse_internal(url, false).await
     }
 
     async fn download_and_parse_internal(
         &self, 
         url: &str, 
-        silent: bool, 
-        logger: Option<&crate::background::BackgroundLogger>
+        silent: bool,
     ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
         let response = self.client.download_silent(url).await?;
         let file_path = self.get_local_path(url);
@@ -115,22 +229,19 @@ impl MirrorCrawler {
             fs::create_dir_all(parent).await?;
         }
         
-        let content = response.text().await?;
+        let body = self.client.read_body(url, response).await?;
         
         // Save file
-        fs::write(&file_path, &content).await?;
+        fs::write(&file_path, &body).await?;
+        let content = String::from_utf8_lossy(&body);
         
-        if silent {
-            if let Some(logger) = logger {
-                logger.log(&format!("Downloaded: {} -> {}", url, file_path.display()));
-            }
-        } else {
+        if !silent {
             println!("Downloaded: {} -> {}", url, file_path.display());
         }
         
         // Extract links if it's HTML
         let links = if self.is_html_content(url, &content) {
-            parser::extract_links(&content, &self.base_url)
+            parser::extract_links(&content, &Url::parse(url)?)
         } else {
             Vec::new()
         };
@@ -138,6 +249,59 @@ impl MirrorCrawler {
         Ok(links)
     }
 
+    /// `download_and_parse_internal` as a download of the run, so the page
+    /// shows up in `wget ctl status`, JSON events and `--report`
+    async fn download_page(
+        &self,
+        url: &str,
+        silent: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let job = self.jobs.add(url);
+        job.set_path(&self.get_local_path(url));
+        job.start().await;
+        let outcome = self.download_and_parse_internal(url, silent).await;
+        job.record(&match &outcome {
+            Ok(_) => Ok(self
+                .jobs
+                .progress(url)
+                .map_or(0, |bytes| bytes.load(Ordering::Relaxed))),
+            Err(e) => Err(match e.downcast_ref::<DownloadError>() {
+                Some(e) => e.clone(),
+                None => DownloadError {
+                    kind: ErrorKind::Io,
+                    message: e.to_string(),
+                },
+            }),
+        });
+        outcome
+    }
+
+    /// Spider counterpart of `download_and_parse_internal`: probes the URL
+    /// and only fetches bodies of HTML pages, which are parsed but not saved
+    async fn check_and_parse(
+        &self,
+        url: &str,
+        silent: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let response = self.client.probe(url).await?;
+        if !silent {
+            println!("Checked: {} - {}", url, response.status());
+        }
+
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("html"));
+        if !is_html {
+            return Ok(Vec::new());
+        }
+
+        let response = self.client.download_silent(url).await?;
+        let body = self.client.read_body(url, response).await?;
+        Ok(parser::extract_links(&String::from_utf8_lossy(&body), &Url::parse(url)?))
+    }
+
     fn get_local_path(&self, url: &str) -> PathBuf {
         if let Ok(parsed) = Url::parse(url) {
             let mut path = self.output_dir.clone();
@@ -173,4 +337,12 @@ impl MirrorCrawler {
         content.trim_start().starts_with("<!DOCTYPE") ||
         content.contains("<html")
     }
-}
\ No newline at end of file
+}
+
+/// Why a page was deliberately not downloaded (`-Q`, `--max-filesize`)
+fn skip_reason<'a>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a str> {
+    error
+        .downcast_ref::<DownloadError>()
+        .filter(|e| e.kind == ErrorKind::Skipped)
+        .map(|e| e.message.as_str())
+}
@@ -0,0 +1,187 @@
+use crate::control::Jobs;
+use crate::control::jobs::JobState;
+use crate::http::client::ErrorKind;
+use crate::output::summary::RunSummary;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How messages are written (`--log-format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Messages for people
+    #[default]
+    Text,
+    /// One JSON event per line
+    Json,
+}
+
+/// Where JSON events go: stdout (the log under `-B`, `-o` and `-a`), or
+/// stderr when stdout carries the data (`-O -`), or a channel for library
+/// callers. Disabled by default, so emitting costs nothing in text mode.
+/// Clones share the stream.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    sink: Option<Arc<Sink>>,
+}
+
+#[derive(Debug)]
+enum Sink {
+    Stdout,
+    Stderr,
+    /// Events handed over as they are, for `api::Batch`
+    Channel(mpsc::UnboundedSender<Event>),
+}
+
+/// One line of `--log-format=json`
+#[derive(Debug, Serialize)]
+struct Line<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Something that happened during the run. Durations are in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A message that text mode prints as prose
+    Log {
+        message: String,
+    },
+    RequestSent {
+        url: String,
+        method: String,
+    },
+    Response {
+        url: String,
+        status: u16,
+        headers: BTreeMap<String, String>,
+    },
+    Redirect {
+        url: String,
+        location: String,
+        status: u16,
+    },
+    /// A request failed in a way worth retrying and will be sent again
+    Retry {
+        url: String,
+        attempt: u32,
+        delay_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error_kind: Option<ErrorKind>,
+        error: String,
+    },
+    Progress {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+        bytes: u64,
+        duration_ms: u64,
+        /// Average since the download started, in bytes per second
+        speed: u64,
+    },
+    Completed {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+        bytes: u64,
+        duration_ms: u64,
+    },
+    Failed {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+        bytes: u64,
+        duration_ms: u64,
+        error_kind: ErrorKind,
+        error: String,
+    },
+    /// What the run did, once it ends
+    Summary(RunSummary),
+}
+
+impl EventLog {
+    /// Events for `format`; `stderr` when stdout is taken by `-O -`
+    pub fn new(format: LogFormat, stderr: bool) -> Self {
+        let sink = match (format, stderr) {
+            (LogFormat::Text, _) => None,
+            (LogFormat::Json, false) => Some(Arc::new(Sink::Stdout)),
+            (LogFormat::Json, true) => Some(Arc::new(Sink::Stderr)),
+        };
+        Self { sink }
+    }
+
+    /// Events sent to the returned receiver instead of being written
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<Event>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let events = Self {
+            sink: Some(Arc::new(Sink::Channel(sender))),
+        };
+        (events, receiver)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Writes `event` as one line. The line goes out in a single write so
+    /// events of concurrent downloads never interleave.
+    pub fn emit(&self, event: Event) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        if let Sink::Channel(sender) = sink.as_ref() {
+            let _ = sender.send(event);
+            return;
+        }
+        let line = Line {
+            time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            event: &event,
+        };
+        let mut text = serde_json::to_string(&line).expect("events serialise");
+        text.push('\n');
+        let _ = match sink.as_ref() {
+            Sink::Stdout => std::io::stdout().lock().write_all(text.as_bytes()),
+            Sink::Stderr => std::io::stderr().lock().write_all(text.as_bytes()),
+            Sink::Channel(_) => Ok(()),
+        };
+    }
+
+    pub fn log(&self, message: &str) {
+        self.emit(Event::Log {
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Emits a `progress` event for every running download of `jobs` once per
+/// `interval`, until the returned task is aborted or the run ends
+pub fn report_progress(jobs: Jobs, interval: Duration) -> Option<JoinHandle<()>> {
+    if !jobs.events().enabled() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            for job in jobs.status() {
+                if job.state == JobState::Running {
+                    jobs.events().emit(Event::Progress {
+                        url: job.url,
+                        path: job.path,
+                        bytes: job.bytes,
+                        duration_ms: job.duration_ms,
+                        speed: job.speed,
+                    });
+                }
+            }
+        }
+    }))
+}
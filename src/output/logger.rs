@@ -1,3 +1,5 @@
+use crate::output::{Event, EventLog, RunSummary};
+
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum OutputLevel {
     Quiet = 0,
@@ -8,30 +10,70 @@ pub enum OutputLevel {
 
 pub struct OutputLogger {
     level: OutputLevel,
+    /// Print everything on stderr, keeping stdout for `-O -`
+    stderr: bool,
+    /// With `--log-format=json`, messages become `log` events
+    events: EventLog,
 }
 
 impl OutputLogger {
     pub fn new(level: OutputLevel) -> Self {
-        Self { level }
+        Self {
+            level,
+            stderr: false,
+            events: EventLog::default(),
+        }
+    }
+
+    pub fn with_stderr(mut self, stderr: bool) -> Self {
+        self.stderr = stderr;
+        self
+    }
+
+    pub fn with_events(mut self, events: EventLog) -> Self {
+        self.events = events;
+        self
     }
 
     pub fn info(&self, msg: &str) {
         if self.level >= OutputLevel::Normal {
-            println!("{}", msg);
+            self.print(msg);
         }
     }
 
     #[allow(dead_code)]
     pub fn verbose(&self, msg: &str) {
         if self.level >= OutputLevel::Verbose {
-            println!("{}", msg);
+            self.print(msg);
         }
     }
 
     #[allow(dead_code)]
     pub fn debug(&self, msg: &str) {
         if self.level >= OutputLevel::Debug {
-            println!("DEBUG: {}", msg);
+            self.print(&format!("DEBUG: {}", msg));
+        }
+    }
+
+    /// The end-of-run summary, as a `summary` event with `--log-format=json`
+    pub fn summary(&self, summary: &RunSummary) {
+        if self.level < OutputLevel::Normal {
+            return;
+        }
+        if self.events.enabled() {
+            self.events.emit(Event::Summary(summary.clone()));
+        } else {
+            self.print(summary.render().trim_end());
+        }
+    }
+
+    fn print(&self, msg: &str) {
+        if self.events.enabled() {
+            self.events.log(msg);
+        } else if self.stderr {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
         }
     }
 
@@ -1,3 +1,10 @@
+pub mod events;
 pub mod logger;
+pub mod progress;
+pub mod report;
+pub mod summary;
 
-pub use logger::{OutputLevel, OutputLogger};
\ No newline at end of file
+pub use events::{Event, EventLog, LogFormat};
+pub use logger::{OutputLevel, OutputLogger};
+pub use progress::{ProgressDisplay, ProgressMode};
+pub use summary::RunSummary;
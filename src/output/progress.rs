@@ -0,0 +1,436 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How transfers show their progress (`--progress`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// A bar as wide as the terminal, with speed and ETA
+    Bar,
+    /// wget's dot lines, which read well in logs and CI output
+    Dot(DotStyle),
+    None,
+}
+
+/// Bytes per dot and dots per cluster and per line, as in GNU wget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotStyle {
+    /// 1K dots, 10 per cluster, 50K per line
+    Default,
+    /// 8K dots, 16 per cluster, 384K per line
+    Binary,
+    /// 64K dots, 8 per cluster, 3M per line
+    Mega,
+    /// 1M dots, 8 per cluster, 32M per line
+    Giga,
+}
+
+impl DotStyle {
+    /// (bytes per dot, dots per cluster, dots per line)
+    fn layout(self) -> (u64, u64, u64) {
+        match self {
+            DotStyle::Default => (1 << 10, 10, 50),
+            DotStyle::Binary => (8 << 10, 16, 48),
+            DotStyle::Mega => (64 << 10, 8, 48),
+            DotStyle::Giga => (1 << 20, 8, 32),
+        }
+    }
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, style) = match value.split_once(':') {
+            Some((kind, style)) => (kind, Some(style)),
+            None => (value, None),
+        };
+        let style = match (kind, style) {
+            ("bar", None) => return Ok(ProgressMode::Bar),
+            ("none", None) => return Ok(ProgressMode::None),
+            ("dot", None | Some("default")) => DotStyle::Default,
+            ("dot", Some("binary")) => DotStyle::Binary,
+            ("dot", Some("mega")) => DotStyle::Mega,
+            ("dot", Some("giga")) => DotStyle::Giga,
+            _ => {
+                return Err(format!(
+                    "invalid progress {:?} (bar, dot[:default|binary|mega|giga] or none)",
+                    value
+                ));
+            }
+        };
+        Ok(ProgressMode::Dot(style))
+    }
+}
+
+impl fmt::Display for ProgressMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgressMode::Bar => write!(f, "bar"),
+            ProgressMode::Dot(DotStyle::Default) => write!(f, "dot"),
+            ProgressMode::Dot(DotStyle::Binary) => write!(f, "dot:binary"),
+            ProgressMode::Dot(DotStyle::Mega) => write!(f, "dot:mega"),
+            ProgressMode::Dot(DotStyle::Giga) => write!(f, "dot:giga"),
+            ProgressMode::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Progress settings of a run: the mode, and whether it shows even where
+/// messages are silenced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressDisplay {
+    pub mode: ProgressMode,
+    /// `--show-progress`: progress shows with `-q` and in silent modes too
+    pub always: bool,
+}
+
+impl Default for ProgressDisplay {
+    fn default() -> Self {
+        Self {
+            mode: ProgressMode::Bar,
+            always: false,
+        }
+    }
+}
+
+impl ProgressDisplay {
+    /// Settings for `--progress` and `--show-progress`. Without an explicit
+    /// `--progress`, a stderr that is not a terminal gets dots, since a
+    /// redrawn bar turns into noise in logs.
+    pub fn new(requested: Option<ProgressMode>, always: bool) -> Self {
+        let mode = requested.unwrap_or(if std::io::stderr().is_terminal() {
+            ProgressMode::Bar
+        } else {
+            ProgressMode::Dot(DotStyle::Default)
+        });
+        Self { mode, always }
+    }
+
+    /// Progress of one transfer of `total` bytes (0 when unknown), `start`
+    /// of which are already on disk. `None` when nothing is to be shown.
+    pub fn start(&self, total: u64, start: u64, silent: bool) -> Option<Progress> {
+        if silent && !self.always {
+            return None;
+        }
+        match self.mode {
+            ProgressMode::Bar => {
+                let pb = ProgressBar::new(total);
+                pb.set_style(bar_style(total, false));
+                pb.set_position(start);
+                Some(Progress::Bar(pb))
+            }
+            ProgressMode::Dot(style) => Some(Progress::dots(style, total, start, None)),
+            ProgressMode::None => None,
+        }
+    }
+
+    /// Like `start`, for one of several transfers running at once: bars
+    /// join `multi` and dot lines are prefixed with `name`
+    pub fn start_named(
+        &self,
+        multi: &MultiProgress,
+        name: &str,
+        total: u64,
+        silent: bool,
+    ) -> Option<Progress> {
+        if silent && !self.always {
+            return None;
+        }
+        match self.mode {
+            ProgressMode::Bar => {
+                let pb = ProgressBar::new(total);
+                pb.set_style(bar_style(total, true));
+                pb.set_prefix(format!("{:<20}", name));
+                Some(Progress::Bar(multi.add(pb)))
+            }
+            ProgressMode::Dot(style) => Some(Progress::dots(
+                style,
+                total,
+                0,
+                Some(format!("{:<20}", name)),
+            )),
+            ProgressMode::None => None,
+        }
+    }
+}
+
+fn bar_style(total: u64, named: bool) -> ProgressStyle {
+    let prefix = if named { "{prefix:.bold.dim} " } else { "" };
+    let template = if total > 0 {
+        "{bytes}/{total_bytes} [{wide_bar:.cyan/red}] {binary_bytes_per_sec} eta {eta}"
+    } else {
+        "{bytes} {binary_bytes_per_sec} {elapsed}"
+    };
+    ProgressStyle::default_bar()
+        .template(&format!("{}{}", prefix, template))
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+/// Progress of one transfer, on stderr
+#[derive(Clone)]
+pub enum Progress {
+    Bar(ProgressBar),
+    Dots(Arc<Mutex<Dots>>),
+}
+
+impl Progress {
+    fn dots(style: DotStyle, total: u64, start: u64, prefix: Option<String>) -> Self {
+        let mut dots = Dots::new(style, total, start, prefix);
+        dots.begin();
+        Progress::Dots(Arc::new(Mutex::new(dots)))
+    }
+
+    pub fn set_position(&self, position: u64) {
+        match self {
+            Progress::Bar(pb) => pb.set_position(position),
+            Progress::Dots(dots) => dots.lock().unwrap().advance_to(position),
+        }
+    }
+
+    pub fn inc(&self, bytes: u64) {
+        match self {
+            Progress::Bar(pb) => pb.inc(bytes),
+            Progress::Dots(dots) => {
+                let mut dots = dots.lock().unwrap();
+                let position = dots.position + bytes;
+                dots.advance_to(position);
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        match self {
+            Progress::Bar(pb) => pb.finish(),
+            Progress::Dots(dots) => dots.lock().unwrap().finish(),
+        }
+    }
+
+    /// Stops a transfer that failed where it is
+    pub fn abandon(&self) {
+        match self {
+            Progress::Bar(pb) => pb.abandon(),
+            Progress::Dots(dots) => dots.lock().unwrap().abandon(),
+        }
+    }
+}
+
+/// State of a dot display. A line starts with the offset it covers and
+/// ends with the percentage, the speed over that line and the ETA.
+pub struct Dots {
+    bytes_per_dot: u64,
+    dots_per_cluster: u64,
+    dots_per_line: u64,
+    total: u64,
+    /// Bytes on disk before this run; their dots show as commas
+    start: u64,
+    position: u64,
+    /// Dots (and commas) drawn on the current line
+    drawn: u64,
+    /// Offset at which the current line starts
+    line_start: u64,
+    line_started: Instant,
+    started: Instant,
+    /// When `position` was last advanced
+    updated: Instant,
+    /// Set when several transfers share stderr: every line carries the
+    /// name and is written only once complete
+    prefix: Option<String>,
+    line: String,
+}
+
+impl Dots {
+    fn new(style: DotStyle, total: u64, start: u64, prefix: Option<String>) -> Self {
+        let (bytes_per_dot, dots_per_cluster, dots_per_line) = style.layout();
+        let line_bytes = bytes_per_dot * dots_per_line;
+        Self {
+            bytes_per_dot,
+            dots_per_cluster,
+            dots_per_line,
+            total,
+            start,
+            position: start,
+            drawn: 0,
+            line_start: start / line_bytes * line_bytes,
+            line_started: Instant::now(),
+            started: Instant::now(),
+            updated: Instant::now(),
+            prefix,
+            line: String::new(),
+        }
+    }
+
+    fn begin(&mut self) {
+        self.open_line();
+        while self.line_start + (self.drawn + 1) * self.bytes_per_dot <= self.start {
+            self.draw(',');
+        }
+        self.flush();
+    }
+
+    fn open_line(&mut self) {
+        if let Some(prefix) = &self.prefix {
+            self.line.push_str(prefix);
+            self.line.push(' ');
+        }
+        self.line
+            .push_str(&format!("{:>6}K", self.line_start / 1024));
+    }
+
+    fn draw(&mut self, mark: char) {
+        if self.drawn.is_multiple_of(self.dots_per_cluster) {
+            self.line.push(' ');
+        }
+        self.line.push(mark);
+        self.drawn += 1;
+    }
+
+    fn advance_to(&mut self, position: u64) {
+        let now = Instant::now();
+        let (from, since) = (self.position, self.updated);
+        self.position = position;
+        self.updated = now;
+        while self.line_start + (self.drawn + 1) * self.bytes_per_dot <= position {
+            self.draw('.');
+            if self.drawn == self.dots_per_line {
+                // One chunk can fill several lines; each line ends at the
+                // moment its last byte arrived, as far as can be told
+                let end = self.line_start + self.drawn * self.bytes_per_dot;
+                let share = (end - from) as f64 / (position - from).max(1) as f64;
+                let at = since + now.duration_since(since).mul_f64(share);
+                self.close_line(end, at);
+                self.line_start = end;
+                self.drawn = 0;
+                self.line_started = at;
+                self.open_line();
+            }
+        }
+        self.flush();
+    }
+
+    /// Ends a full line, reached offset `end` at `at`, with the percentage,
+    /// the speed over the line and the ETA
+    fn close_line(&mut self, end: u64, at: Instant) {
+        self.push_percent(end);
+        let line_bytes = end - self.line_start.max(self.start);
+        let elapsed = at.duration_since(self.line_started).as_secs_f64();
+        self.line.push_str(&format!(
+            " {}",
+            format_rate(line_bytes as f64 / elapsed.max(0.001))
+        ));
+        let done = end.saturating_sub(self.start) as f64;
+        let seconds = at.duration_since(self.started).as_secs_f64();
+        if self.total > end && done > 0.0 {
+            let eta = (self.total - end) as f64 * seconds / done;
+            self.line.push_str(&format!(" {}", format_eta(eta as u64)));
+        }
+        self.line.push('\n');
+        self.write_line();
+    }
+
+    fn push_percent(&mut self, offset: u64) {
+        if let Some(percent) = (offset.min(self.total) * 100).checked_div(self.total) {
+            self.line.push_str(&format!(" {:>3}%", percent));
+        }
+    }
+
+    /// Ends the last line, padded to where full lines have their
+    /// statistics, with the average speed and the total time
+    fn finish(&mut self) {
+        let missing = self.dots_per_line - self.drawn;
+        let clusters = self.dots_per_line.div_ceil(self.dots_per_cluster)
+            - self.drawn.div_ceil(self.dots_per_cluster);
+        self.line
+            .push_str(&" ".repeat((missing + clusters) as usize));
+        self.push_percent(self.position);
+        let total = self.started.elapsed().as_secs_f64();
+        let bytes = self.position.saturating_sub(self.start);
+        self.line.push_str(&format!(
+            " {}={:.1}s\n",
+            format_rate(bytes as f64 / total.max(0.001)),
+            total
+        ));
+        self.write_line();
+    }
+
+    fn abandon(&mut self) {
+        if self.prefix.is_none() {
+            self.line.push('\n');
+            self.write_line();
+        }
+    }
+
+    /// Writes what is drawn so far; with a prefix only complete lines go out
+    fn flush(&mut self) {
+        if self.prefix.is_none() {
+            self.write_line();
+        }
+    }
+
+    fn write_line(&mut self) {
+        if self.prefix.is_some() && !self.line.ends_with('\n') {
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        let _ = stderr.write_all(self.line.as_bytes());
+        let _ = stderr.flush();
+        self.line.clear();
+    }
+}
+
+/// wget's compact speed, e.g. `512K` or `1.23M`
+fn format_rate(bytes_per_sec: f64) -> String {
+    let (value, unit) = if bytes_per_sec >= 1024.0 * 1024.0 * 1024.0 {
+        (bytes_per_sec / (1024.0 * 1024.0 * 1024.0), "G")
+    } else if bytes_per_sec >= 1024.0 * 1024.0 {
+        (bytes_per_sec / (1024.0 * 1024.0), "M")
+    } else if bytes_per_sec >= 1024.0 {
+        (bytes_per_sec / 1024.0, "K")
+    } else {
+        (bytes_per_sec, "")
+    };
+    match value {
+        v if v >= 100.0 || unit.is_empty() => format!("{:.0}{}", v, unit),
+        v if v >= 10.0 => format!("{:.1}{}", v, unit),
+        v => format!("{:.2}{}", v, unit),
+    }
+}
+
+fn format_eta(seconds: u64) -> String {
+    match seconds {
+        s if s < 100 => format!("{}s", s),
+        s if s < 100 * 60 => format!("{}m{}s", s / 60, s % 60),
+        s if s < 48 * 3600 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_modes_parse_and_dots_draw_wget_lines() {
+        assert_eq!("bar".parse(), Ok(ProgressMode::Bar));
+        assert_eq!("none".parse(), Ok(ProgressMode::None));
+        assert_eq!("dot".parse(), Ok(ProgressMode::Dot(DotStyle::Default)));
+        assert_eq!("dot:mega".parse(), Ok(ProgressMode::Dot(DotStyle::Mega)));
+        assert!("dot:huge".parse::<ProgressMode>().is_err());
+        assert!("bar:binary".parse::<ProgressMode>().is_err());
+
+        // Resumed at 12K: the existing part shows as commas. Named lines
+        // are only written once complete.
+        let mut dots = Dots::new(DotStyle::Default, 100 << 10, 12 << 10, Some("f".into()));
+        dots.begin();
+        dots.advance_to(14 << 10);
+        assert_eq!(dots.line, "f      0K ,,,,,,,,,, ,,..");
+
+        assert_eq!(format_rate(512.0 * 1024.0), "512K");
+        assert_eq!(format_rate(1.5 * 1024.0 * 1024.0), "1.50M");
+        assert_eq!(format_eta(125), "2m5s");
+    }
+}
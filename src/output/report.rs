@@ -0,0 +1,85 @@
+use crate::control::jobs::JobStatus;
+use std::io;
+use std::path::Path;
+
+const CSV_HEADER: &str = "id,url,path,state,bytes,duration_ms,speed,error_kind,error";
+
+/// Writes `--report FILE`: one entry per download of the run with its
+/// outcome. FILE gets CSV when its name ends in `.csv`, JSON otherwise.
+pub fn write(path: &Path, downloads: &[JobStatus]) -> io::Result<()> {
+    let csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let text = if csv {
+        render_csv(downloads)
+    } else {
+        let mut json = serde_json::to_string_pretty(downloads).map_err(io::Error::other)?;
+        json.push('\n');
+        json
+    };
+    std::fs::write(path, text)
+}
+
+fn render_csv(downloads: &[JobStatus]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for download in downloads {
+        let fields = [
+            download.id.to_string(),
+            download.url.clone(),
+            download
+                .path
+                .as_ref()
+                .map_or(String::new(), |path| path.display().to_string()),
+            download.state.to_string(),
+            download.bytes.to_string(),
+            download.duration_ms.to_string(),
+            download.speed.to_string(),
+            download
+                .error_kind
+                .map_or(String::new(), |kind| kind.to_string()),
+            download.error.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes a field holding a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::Jobs;
+    use crate::http::client::{DownloadError, ErrorKind};
+
+    #[test]
+    fn test_csv_report_lists_outcomes_and_quotes_fields() {
+        let jobs = Jobs::default();
+        let done = jobs.add("http://example.com/a.txt");
+        done.set_path(Path::new("a.txt"));
+        done.record(&Ok(3));
+        let failed = jobs.add("http://example.com/b,c.txt");
+        failed.record(&Err(DownloadError {
+            kind: ErrorKind::Http,
+            message: "HTTP error: 404 \"Not Found\"".into(),
+        }));
+
+        let csv = render_csv(&jobs.status());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1,http://example.com/a.txt,a.txt,done,3,0,0,,");
+        assert_eq!(
+            lines[2],
+            "2,\"http://example.com/b,c.txt\",,failed,0,0,0,http,\"HTTP error: 404 \"\"Not Found\"\"\""
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::control::Jobs;
+use crate::control::jobs::JobState;
+use crate::http::client::ErrorKind;
+use crate::utils::size::format_size;
+use serde::Serialize;
+use std::time::Duration;
+
+/// What a run did, printed (or emitted as a `summary` event) when it ends,
+/// whatever the mode
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub wall_time_ms: u64,
+    /// Downloads that completed
+    pub files: usize,
+    /// Bytes downloaded by this run
+    pub bytes: u64,
+    /// `bytes` over the wall-clock time, in bytes per second
+    pub speed: u64,
+    pub retries: u64,
+    /// Bytes already on disk that were not downloaded again
+    pub skipped_bytes: u64,
+    /// Downloads left out on purpose (`-Q`, `--max-filesize`)
+    pub skipped: usize,
+    /// Downloads stopped with `wget ctl cancel`
+    pub cancelled: usize,
+    pub failed: usize,
+    /// Failed downloads by error kind, in order of first appearance
+    pub failures: Vec<FailureGroup>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureGroup {
+    pub error_kind: ErrorKind,
+    pub downloads: Vec<Failure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub url: String,
+    pub error: String,
+}
+
+impl RunSummary {
+    /// Summary of the downloads registered in `jobs`, `wall_time` after
+    /// the run started
+    pub(crate) fn new(jobs: &Jobs, wall_time: Duration) -> Self {
+        let status = jobs.status();
+        let count = |state: JobState| status.iter().filter(|job| job.state == state).count();
+        let bytes: u64 = status.iter().map(|job| job.bytes).sum();
+        let seconds = wall_time.as_secs_f64();
+
+        let mut failures: Vec<FailureGroup> = Vec::new();
+        for job in status.iter().filter(|job| job.state == JobState::Failed) {
+            let error_kind = job.error_kind.unwrap_or(ErrorKind::Network);
+            let failure = Failure {
+                url: job.url.clone(),
+                error: job.error.clone().unwrap_or_default(),
+            };
+            match failures
+                .iter_mut()
+                .find(|group| group.error_kind == error_kind)
+            {
+                Some(group) => group.downloads.push(failure),
+                None => failures.push(FailureGroup {
+                    error_kind,
+                    downloads: vec![failure],
+                }),
+            }
+        }
+
+        Self {
+            wall_time_ms: wall_time.as_millis() as u64,
+            files: count(JobState::Done),
+            bytes,
+            speed: if seconds > 0.0 {
+                (bytes as f64 / seconds) as u64
+            } else {
+                0
+            },
+            retries: jobs.retries(),
+            skipped_bytes: jobs.skipped_bytes(),
+            skipped: count(JobState::Skipped),
+            cancelled: count(JobState::Cancelled),
+            failed: count(JobState::Failed),
+            failures,
+        }
+    }
+
+    /// The summary as text, one fact per line
+    pub fn render(&self) -> String {
+        let wall_time = Duration::from_millis(self.wall_time_ms);
+        let mut text = format!("Total wall clock time: {}\n", format_duration(wall_time));
+        text.push_str(&format!(
+            "Downloaded: {} files, {} in {} ({}/s)\n",
+            self.files,
+            format_size(self.bytes),
+            format_duration(wall_time),
+            format_size(self.speed)
+        ));
+        text.push_str(&format!("Retries: {}\n", self.retries));
+        text.push_str(&format!(
+            "Already on disk: {}\n",
+            format_size(self.skipped_bytes)
+        ));
+        if self.skipped > 0 {
+            text.push_str(&format!("Skipped: {}\n", self.skipped));
+        }
+        if self.cancelled > 0 {
+            text.push_str(&format!("Cancelled: {}\n", self.cancelled));
+        }
+        text.push_str(&format!("Failed: {}\n", self.failed));
+        for group in &self.failures {
+            text.push_str(&format!(
+                "  {} ({}):\n",
+                group.error_kind,
+                group.downloads.len()
+            ));
+            for failure in &group.downloads {
+                text.push_str(&format!("    {} - {}\n", failure.url, failure.error));
+            }
+        }
+        text
+    }
+}
+
+/// `2.9s`, `4m 12s` or `1h 5m`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        s if s < 60 => format!("{:.1}s", duration.as_secs_f64()),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::client::DownloadError;
+
+    #[tokio::test]
+    async fn test_summary_groups_failures_by_kind() {
+        let jobs = Jobs::default();
+        let outcomes = [
+            Ok(2048),
+            Err((ErrorKind::Http, "HTTP error: 404 Not Found")),
+            Err((ErrorKind::Skipped, "File is larger than --max-filesize")),
+            Err((ErrorKind::Http, "HTTP error: 500 Internal Server Error")),
+            Err((ErrorKind::Timeout, "No data for 900.0s")),
+            // `wget -O - URL | head`: the reader had enough
+            Err((ErrorKind::OutputClosed, "Output closed by the reading program")),
+        ];
+        for (n, outcome) in outcomes.into_iter().enumerate() {
+            let job = jobs.add(&format!("http://example.com/{}", n));
+            job.start().await;
+            job.record(&outcome.map_err(|(kind, message)| DownloadError {
+                kind,
+                message: message.to_string(),
+            }));
+        }
+        jobs.count_retry();
+        jobs.add_skipped_bytes(1024);
+
+        let summary = RunSummary::new(&jobs, Duration::from_secs(2));
+        assert_eq!(
+            (summary.files, summary.bytes, summary.speed),
+            (1, 2048, 1024)
+        );
+        assert_eq!((summary.skipped, summary.failed), (1, 3));
+        let text = summary.render();
+        assert!(text.contains("Downloaded: 1 files, 2.00 KB in 2.0s (1.00 KB/s)\n"));
+        assert!(text.contains("Retries: 1\nAlready on disk: 1.00 KB\nSkipped: 1\nFailed: 3\n"));
+        assert!(text.contains(
+            "  http (2):\n    http://example.com/1 - HTTP error: 404 Not Found\n    http://example.com/3 - HTTP error: 500 Internal Server Error\n  timeout (1):\n"
+        ));
+    }
+}
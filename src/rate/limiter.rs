@@ -1,56 +1,166 @@
+use crate::rate::schedule::RateSchedule;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use tokio::time::sleep;
 
-#[derive(Clone)]
+/// Smallest and largest share of the bucket handed out per turn
+const MIN_QUANTUM: u64 = 1024;
+const MAX_QUANTUM: u64 = 64 * 1024;
+/// How often a running limiter looks at its schedule again
+const RATE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Token bucket shared by every transfer holding a clone. Tokens are handed
+/// out in small quanta, one waiter at a time in arrival order, so active
+/// transfers share the rate evenly and none of them bursts. The rate follows
+/// a `RateSchedule` and changes at its boundaries while transfers run.
+#[derive(Debug, Clone)]
 pub struct RateLimiter {
-    bytes_per_second: u64,
-    last_check: Instant,
-    bytes_consumed: u64,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// Replaced by `wget ctl set-rate` while transfers run
+    schedule: RwLock<RateSchedule>,
+    schedule_changed: AtomicBool,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// Rate in force and when the schedule was last consulted
+    rate: u64,
+    rate_checked: Instant,
 }
 
 impl RateLimiter {
-    pub fn new(rate_str: &str) -> Result<Self, String> {
-        let bytes_per_second = Self::parse_rate(rate_str)?;
-        Ok(Self {
-            bytes_per_second,
-            last_check: Instant::now(),
-            bytes_consumed: 0,
-        })
+    /// Limiter for `bytes_per_second`; 0 means unlimited
+    #[allow(dead_code)]
+    pub fn from_bytes_per_second(bytes_per_second: u64) -> Self {
+        Self::from_schedule(RateSchedule::constant(bytes_per_second))
     }
 
-    pub async fn consume(&mut self, bytes: u64) {
-        if self.bytes_per_second == 0 {
-            return;
+    pub fn from_schedule(schedule: RateSchedule) -> Self {
+        let rate = schedule.current_rate();
+        let now = Instant::now();
+        Self {
+            shared: Arc::new(Shared {
+                schedule: RwLock::new(schedule),
+                schedule_changed: AtomicBool::new(false),
+                // Starts full so the first quantum goes out without a pause
+                bucket: Mutex::new(Bucket {
+                    tokens: quantum(rate) as f64,
+                    last_refill: now,
+                    rate,
+                    rate_checked: now,
+                }),
+            }),
         }
+    }
 
-        self.bytes_consumed += bytes;
-        let elapsed = self.last_check.elapsed();
-        let allowed_bytes = (elapsed.as_secs_f64() * self.bytes_per_second as f64) as u64;
+    /// Switches every holder of this limiter to `schedule`; running
+    /// transfers pick it up with their next chunk
+    pub fn set_schedule(&self, schedule: RateSchedule) {
+        *self.shared.schedule.write().unwrap() = schedule;
+        self.shared.schedule_changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Bytes per second allowed now; 0 means unlimited
+    pub fn current_rate(&self) -> u64 {
+        self.shared.schedule.read().unwrap().current_rate()
+    }
 
-        if self.bytes_consumed > allowed_bytes {
-            let excess = self.bytes_consumed - allowed_bytes;
-            let delay_secs = excess as f64 / self.bytes_per_second as f64;
-            sleep(Duration::from_secs_f64(delay_secs)).await;
+    /// Waits until `bytes` may be passed on
+    pub async fn consume(&self, bytes: u64) {
+        if self.shared.schedule.read().unwrap().is_unlimited() {
+            return;
         }
 
-        // Reset counters periodically
-        if elapsed >= Duration::from_secs(1) {
-            self.last_check = Instant::now();
-            self.bytes_consumed = 0;
+        let mut remaining = bytes;
+        while remaining > 0 {
+            // The lock queues waiters in order, so transfers take turns
+            let mut bucket = self.shared.bucket.lock().await;
+            let rate = bucket.current_rate(&self.shared);
+            if rate == 0 {
+                return;
+            }
+            let capacity = quantum(rate);
+            let take = remaining.min(capacity);
+            bucket.refill(rate as f64, capacity as f64);
+            let missing = take as f64 - bucket.tokens;
+            if missing > 0.0 {
+                sleep(Duration::from_secs_f64(missing / rate as f64)).await;
+                bucket.refill(rate as f64, capacity as f64);
+            }
+            bucket.tokens -= take as f64;
+            remaining -= take;
         }
     }
+}
 
-    fn parse_rate(rate_str: &str) -> Result<u64, String> {
-        if rate_str.ends_with('k') || rate_str.ends_with('K') {
-            let num_str = &rate_str[..rate_str.len() - 1];
-            let num: u64 = num_str.parse().map_err(|_| "Invalid rate format".to_string())?;
-            Ok(num * 1024)
-        } else if rate_str.ends_with('M') || rate_str.ends_with('m') {
-            let num_str = &rate_str[..rate_str.len() - 1];
-            let num: u64 = num_str.parse().map_err(|_| "Invalid rate format".to_string())?;
-            Ok(num * 1024 * 1024)
-        } else {
-            rate_str.parse().map_err(|_| "Invalid rate format".to_string())
+/// Tokens handed out per turn, about 1/20 s worth. This is also the bucket
+/// size, which keeps bursts short.
+fn quantum(bytes_per_second: u64) -> u64 {
+    (bytes_per_second / 20).clamp(MIN_QUANTUM, MAX_QUANTUM)
+}
+
+impl Default for RateLimiter {
+    /// A limiter that never waits, until given a schedule
+    fn default() -> Self {
+        Self::from_schedule(RateSchedule::constant(0))
+    }
+}
+
+impl Bucket {
+    /// Rate from the schedule, looked up at most once per `RATE_CHECK_INTERVAL`
+    /// or right after the schedule was replaced
+    fn current_rate(&mut self, shared: &Shared) -> u64 {
+        if self.rate_checked.elapsed() >= RATE_CHECK_INTERVAL
+            || shared.schedule_changed.swap(false, Ordering::Relaxed)
+        {
+            self.rate = shared.schedule.read().unwrap().current_rate();
+            self.rate_checked = Instant::now();
         }
+        self.rate
+    }
+
+    fn refill(&mut self, rate: f64, capacity: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.last_refill = now;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shared_limit_is_split_evenly() {
+        // 20 KiB/s shared by two transfers of 10 KiB each
+        let limiter = RateLimiter::from_bytes_per_second(20 * 1024);
+        let start = Instant::now();
+        let transfer = |limiter: RateLimiter| async move {
+            for _ in 0..10 {
+                limiter.consume(1024).await;
+            }
+            start.elapsed()
+        };
+        let (a, b) = tokio::join!(
+            tokio::spawn(transfer(limiter.clone())),
+            tokio::spawn(transfer(limiter.clone()))
+        );
+        let (a, b) = (a.unwrap().as_secs_f64(), b.unwrap().as_secs_f64());
+
+        // The first quantum is free, the other 19 KiB take about 0.95s
+        assert!(a.max(b) > 0.85, "finished too early: {} / {}", a, b);
+        assert!(a.max(b) < 1.5, "finished too late: {} / {}", a, b);
+        // Taking turns, neither transfer finishes long before the other
+        assert!((a - b).abs() < 0.15, "unfair split: {} / {}", a, b);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod limiter;
+pub mod schedule;
+pub mod throttle;
 
-pub use limiter::RateLimiter;
\ No newline at end of file
+pub use limiter::RateLimiter;
+pub use schedule::RateSchedule;
+pub use throttle::{RateLimits, Throttle};
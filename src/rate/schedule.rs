@@ -0,0 +1,141 @@
+use crate::utils::size::parse_size;
+use chrono::Timelike;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// A rate that may change with the local time of day, e.g.
+/// `08:00-18:00=500k,*=0`: 500k during office hours, unlimited otherwise.
+/// A plain rate such as `2M` applies all day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateSchedule {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// Start and end minute of the day; `None` for `*`. The end is
+    /// exclusive and may lie before the start for windows past midnight.
+    window: Option<(u32, u32)>,
+    /// 0 means unlimited
+    bytes_per_second: u64,
+}
+
+impl RateSchedule {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if !spec.contains('=') {
+            return Ok(Self::constant(parse_rate(spec)?));
+        }
+
+        let mut rules = Vec::new();
+        for part in spec.split(',') {
+            let (window, rate) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected WINDOW=RATE, got {:?}", part.trim()))?;
+            let window = match window.trim() {
+                "*" => None,
+                window => Some(parse_window(window)?),
+            };
+            rules.push(Rule {
+                window,
+                bytes_per_second: parse_rate(rate)?,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// The same rate all day
+    pub fn constant(bytes_per_second: u64) -> Self {
+        Self {
+            rules: vec![Rule {
+                window: None,
+                bytes_per_second,
+            }],
+        }
+    }
+
+    /// Rate in force at `minute` past midnight: the first matching window,
+    /// unlimited when none matches
+    pub fn rate_at(&self, minute: u32) -> u64 {
+        self.rules
+            .iter()
+            .find(|rule| match rule.window {
+                None => true,
+                Some((start, end)) if start <= end => (start..end).contains(&minute),
+                Some((start, end)) => minute >= start || minute < end,
+            })
+            .map_or(0, |rule| rule.bytes_per_second)
+    }
+
+    /// Rate in force now, by the local clock
+    pub fn current_rate(&self) -> u64 {
+        if let [Rule { window: None, bytes_per_second }] = self.rules.as_slice() {
+            return *bytes_per_second;
+        }
+        let now = chrono::Local::now();
+        self.rate_at(now.hour() * 60 + now.minute())
+    }
+
+    /// Whether no window ever limits the rate
+    pub fn is_unlimited(&self) -> bool {
+        self.rules.iter().all(|rule| rule.bytes_per_second == 0)
+    }
+}
+
+/// Parses a rate such as `500k`, `1.5M` or `2GB` per second, in the units
+/// of `parse_size`. A trailing `/s` is ignored.
+pub fn parse_rate(rate: &str) -> Result<u64, String> {
+    let text = rate.trim();
+    parse_size(text.strip_suffix("/s").unwrap_or(text))
+        .map_err(|_| format!("invalid rate {:?} (e.g., 400k, 1.5M, 2MB)", text))
+}
+
+/// `HH:MM-HH:MM` (or `H-H`) as minutes of the day
+fn parse_window(window: &str) -> Result<(u32, u32), String> {
+    let (start, end) = window
+        .split_once('-')
+        .ok_or_else(|| format!("expected a window like 08:00-18:00, got {:?}", window))?;
+    Ok((parse_time(start)?, parse_time(end)?))
+}
+
+fn parse_time(time: &str) -> Result<u32, String> {
+    let time = time.trim();
+    let invalid = || format!("invalid time {:?} (expected HH:MM)", time);
+    let (hours, minutes) = time.split_once(':').unwrap_or((time, "0"));
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    // 24:00 closes a window at midnight
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedules_and_units() {
+        let office = RateSchedule::parse("08:00-18:00=500k,*=0").unwrap();
+        assert_eq!(office.rate_at(9 * 60), 500 * 1024);
+        assert_eq!(office.rate_at(18 * 60), 0);
+        assert_eq!(office.rate_at(3 * 60), 0);
+        assert_eq!(RateSchedule::parse("0-24=1k").unwrap().rate_at(23 * 60 + 59), 1024);
+
+        let night = RateSchedule::parse("22:00-06:30=2M,*=100k").unwrap();
+        assert_eq!(night.rate_at(23 * 60), 2 << 20);
+        assert_eq!(night.rate_at(6 * 60 + 29), 2 << 20);
+        assert_eq!(night.rate_at(12 * 60), 100 << 10);
+
+        assert_eq!(RateSchedule::parse("1.5M").unwrap().current_rate(), 1_572_864);
+        assert_eq!(parse_rate("2G"), Ok(2 << 30));
+        assert_eq!(parse_rate("2GB"), Ok(2_000_000_000));
+        assert_eq!(parse_rate("500kB"), Ok(500_000));
+        assert_eq!(parse_rate("500KiB/s"), Ok(512_000));
+        assert_eq!(parse_rate("0.5k"), Ok(512));
+        assert!(parse_rate("fast").is_err());
+        assert!(RateSchedule::parse("08:00-25:00=1M").is_err());
+        assert!(RateSchedule::parse("08:00=1M").is_err());
+    }
+}
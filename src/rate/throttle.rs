@@ -0,0 +1,132 @@
+use crate::http::client::DownloadError;
+use crate::rate::{RateLimiter, RateSchedule};
+use crate::utils::url::host_key;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// Every bandwidth cap configured for a run: one limiter shared by all
+/// transfers (`--rate-limit`), one per host (`--limit-rate-per-host`) and a
+/// fresh one for each download (`--limit-rate-per-download`). Clones share
+/// the limiters, so one value serves every client of a run.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    /// Unlimited unless `--rate-limit` or `wget ctl set-rate` says otherwise
+    total: RateLimiter,
+    per_host: Option<RateSchedule>,
+    hosts: Arc<Mutex<HashMap<String, RateLimiter>>>,
+    per_download: Option<RateSchedule>,
+}
+
+impl RateLimits {
+    pub fn new(
+        total: Option<&str>,
+        per_host: Option<&str>,
+        per_download: Option<&str>,
+    ) -> Result<Self, String> {
+        Ok(Self::from_schedules(
+            total.map(RateSchedule::parse).transpose()?,
+            per_host.map(RateSchedule::parse).transpose()?,
+            per_download.map(RateSchedule::parse).transpose()?,
+        ))
+    }
+
+    /// Limits from schedules already parsed, as `api::DownloaderBuilder` has them
+    pub fn from_schedules(
+        total: Option<RateSchedule>,
+        per_host: Option<RateSchedule>,
+        per_download: Option<RateSchedule>,
+    ) -> Self {
+        Self {
+            total: total.map(RateLimiter::from_schedule).unwrap_or_default(),
+            per_host,
+            hosts: Arc::default(),
+            per_download,
+        }
+    }
+
+    /// Replaces the combined limit of all transfers, including running ones
+    pub fn set_total(&self, spec: &str) -> Result<(), String> {
+        self.total.set_schedule(RateSchedule::parse(spec)?);
+        Ok(())
+    }
+
+    /// Combined limit in force now, in bytes per second; 0 means unlimited
+    pub fn total_rate(&self) -> u64 {
+        self.total.current_rate()
+    }
+
+    /// Limiters for a new download of `url`
+    pub fn throttle(&self, url: &str) -> Throttle {
+        let mut limiters = vec![self.total.clone()];
+        if let Some(schedule) = &self.per_host {
+            let mut hosts = self.hosts.lock().unwrap();
+            let host = hosts
+                .entry(host_key(url))
+                .or_insert_with(|| RateLimiter::from_schedule(schedule.clone()));
+            limiters.push(host.clone());
+        }
+        limiters.extend(self.per_download.clone().map(RateLimiter::from_schedule));
+        Throttle {
+            limiters,
+            ..Throttle::default()
+        }
+    }
+}
+
+/// The limiters one download passes its bytes through. Clones share them,
+/// so the connections of a segmented download count together. A throttle
+/// may also hold the run's pause and stop switches and count the bytes it
+/// lets through.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    limiters: Vec<RateLimiter>,
+    paused: Option<watch::Receiver<bool>>,
+    stop: Option<CancellationToken>,
+    progress: Option<Arc<AtomicU64>>,
+}
+
+impl Throttle {
+    /// Stops passing bytes while `paused` is true, fails once `stop` is
+    /// cancelled and adds every byte to `progress` (used by `wget ctl` and
+    /// the signal handlers)
+    pub fn with_control(
+        mut self,
+        paused: watch::Receiver<bool>,
+        stop: CancellationToken,
+        progress: Option<Arc<AtomicU64>>,
+    ) -> Self {
+        self.paused = Some(paused);
+        self.stop = Some(stop);
+        self.progress = progress;
+        self
+    }
+
+    /// Waits until every applicable cap allows `bytes` more. Fails with
+    /// an `Interrupted` error, also while waiting, once the run is stopped;
+    /// the caller then keeps what it has written so far.
+    pub async fn consume(&self, bytes: u64) -> Result<(), DownloadError> {
+        let wait = async {
+            if let Some(paused) = &self.paused {
+                let _ = paused.clone().wait_for(|paused| !paused).await;
+            }
+            for limiter in &self.limiters {
+                limiter.consume(bytes).await;
+            }
+        };
+        match &self.stop {
+            Some(stop) => tokio::select! {
+                biased;
+                _ = stop.cancelled() => return Err(DownloadError::interrupted()),
+                _ = wait => {}
+            },
+            None => wait.await,
+        }
+        if let Some(progress) = &self.progress {
+            progress.fetch_add(bytes, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
 pub mod handler;
+pub mod validators;
 
-pub use handler::ResumeHandler;
\ No newline at end of file
+pub use handler::ResumeHandler;
+pub use validators::{ContentRange, ResumeValidators};
@@ -0,0 +1,297 @@
+use reqwest::header::{CONTENT_ENCODING, CONTENT_RANGE, ETAG, LAST_MODIFIED};
+use reqwest::{Response, StatusCode};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Parsed `Content-Range`: `bytes a-b/N`, `bytes a-b/*` or, on a 416,
+/// `bytes */N` (no range)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    /// First and last byte, inclusive
+    pub range: Option<(u64, u64)>,
+    pub total: Option<u64>,
+}
+
+impl ContentRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+        let total = match total.trim() {
+            "*" => None,
+            total => Some(total.parse().ok()?),
+        };
+        let range = match range.trim() {
+            "*" => None,
+            range => {
+                let (first, last) = range.split_once('-')?;
+                let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                if first > last || total.is_some_and(|total| last >= total) {
+                    return None;
+                }
+                Some((first, last))
+            }
+        };
+        Some(Self { range, total })
+    }
+
+    pub fn from_response(response: &Response) -> Option<Self> {
+        response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse)
+    }
+}
+
+/// What identified the remote file when a download started. Saved next to
+/// the partial file as `<file>.resume` so that `-c` can tell whether the
+/// bytes on disk still belong to the same version.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResumeValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Full length of the (unencoded) file
+    pub length: Option<u64>,
+}
+
+impl ResumeValidators {
+    pub fn from_response(response: &Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let length = if response.status() == StatusCode::PARTIAL_CONTENT {
+            ContentRange::from_response(response).and_then(|range| range.total)
+        } else if response.headers().contains_key(CONTENT_ENCODING) {
+            None
+        } else {
+            response.content_length()
+        };
+
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            length,
+        }
+    }
+
+    pub fn sidecar_path(file_path: &Path) -> PathBuf {
+        let mut name = file_path.as_os_str().to_owned();
+        name.push(".resume");
+        PathBuf::from(name)
+    }
+
+    /// Validators saved for `file_path`, or `None` if there are none
+    pub async fn load(file_path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(Self::sidecar_path(file_path))
+            .await
+            .ok()?;
+        let mut validators = Self::default();
+        for line in text.lines() {
+            match line.split_once(": ") {
+                Some(("etag", value)) => validators.etag = Some(value.to_string()),
+                Some(("last-modified", value)) => {
+                    validators.last_modified = Some(value.to_string())
+                }
+                Some(("length", value)) => validators.length = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(validators)
+    }
+
+    pub async fn save(&self, file_path: &Path) -> std::io::Result<()> {
+        let mut text = String::new();
+        if let Some(etag) = &self.etag {
+            text.push_str(&format!("etag: {}\n", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            text.push_str(&format!("last-modified: {}\n", last_modified));
+        }
+        if let Some(length) = self.length {
+            text.push_str(&format!("length: {}\n", length));
+        }
+        fs::write(Self::sidecar_path(file_path), text).await
+    }
+
+    /// Drops the sidecar once the file is complete
+    pub async fn remove(file_path: &Path) {
+        let _ = fs::remove_file(Self::sidecar_path(file_path)).await;
+    }
+
+    /// Value for `If-Range`. Weak ETags are not allowed there, so
+    /// Last-Modified is used instead.
+    pub fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    /// Whether a 206 answer continues this version of the file at `offset`
+    pub fn continues(&self, response: &Response, offset: u64) -> bool {
+        let Some(ContentRange {
+            range: Some((first, _)),
+            total,
+        }) = ContentRange::from_response(response)
+        else {
+            return false;
+        };
+        let current = Self::from_response(response);
+        let same = |saved: &Option<String>, now: &Option<String>| match (saved, now) {
+            (Some(saved), Some(now)) => saved == now,
+            _ => true,
+        };
+
+        first == offset
+            && (self.length.is_none() || total.is_none() || self.length == total)
+            && same(&self.etag, &current.etag)
+            && same(&self.last_modified, &current.last_modified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpClient;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_content_range_parsing() {
+        assert_eq!(
+            ContentRange::parse("bytes 100-199/1000"),
+            Some(ContentRange {
+                range: Some((100, 199)),
+                total: Some(1000)
+            })
+        );
+        assert_eq!(
+            ContentRange::parse("bytes */1000"),
+            Some(ContentRange {
+                range: None,
+                total: Some(1000)
+            })
+        );
+        assert_eq!(ContentRange::parse("bytes 0-9/*").unwrap().total, None);
+        assert_eq!(ContentRange::parse("bytes 10-5/100"), None);
+        assert_eq!(ContentRange::parse("bytes 0-100/100"), None);
+        assert_eq!(ContentRange::parse("items 0-1/2"), None);
+
+        let weak = ResumeValidators {
+            etag: Some("W/\"v1\"".into()),
+            last_modified: Some("Sat, 17 Oct 2026 10:00:00 GMT".into()),
+            length: None,
+        };
+        assert_eq!(weak.if_range(), Some("Sat, 17 Oct 2026 10:00:00 GMT"));
+    }
+
+    /// Serves `body` under ETag "v2", honouring Range and If-Range like a
+    /// real server would
+    async fn serve(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                let header = |name: &str| {
+                    request
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(|value| value.trim().to_string())
+                };
+                let start = header("range: bytes=")
+                    .filter(|_| header("if-range:").is_none_or(|tag| tag == "\"v2\""))
+                    .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap());
+
+                let (head, part) = match start {
+                    Some(start) if start >= body.len() => (
+                        format!(
+                            "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n",
+                            body.len()
+                        ),
+                        &body[..0],
+                    ),
+                    Some(start) => (
+                        format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n",
+                            start,
+                            body.len() - 1,
+                            body.len(),
+                            body.len() - start
+                        ),
+                        &body[start..],
+                    ),
+                    None => (
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", body.len()),
+                        body,
+                    ),
+                };
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket
+                    .write_all(b"ETag: \"v2\"\r\nConnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                socket.write_all(part).await.unwrap();
+            }
+        });
+        format!("http://{}/file.txt", addr)
+    }
+
+    #[tokio::test]
+    async fn test_resume_restarts_on_changed_file_and_skips_complete_one() {
+        let body: &'static [u8] = b"version two of the remote file";
+        let url = serve(body).await;
+        let dir = std::env::temp_dir().join(format!("wget-validators-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        let client = HttpClient::new();
+
+        // Partial file from an older version: If-Range fails, the 200 replaces it
+        std::fs::write(&path, b"VERSION ONE").unwrap();
+        let old = ResumeValidators {
+            etag: Some("\"v1\"".into()),
+            ..ResumeValidators::default()
+        };
+        old.save(&path).await.unwrap();
+        let fetched = client
+            .download_to_file_with_resume(&url, &path, true, true)
+            .await
+            .unwrap();
+        assert_eq!(fetched, body.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert!(!ResumeValidators::sidecar_path(&path).exists());
+
+        // Same version, partially downloaded: continues with a 206
+        std::fs::write(&path, &body[..7]).unwrap();
+        ResumeValidators {
+            etag: Some("\"v2\"".into()),
+            length: Some(body.len() as u64),
+            ..ResumeValidators::default()
+        }
+        .save(&path)
+        .await
+        .unwrap();
+        let fetched = client
+            .download_to_file_with_resume(&url, &path, true, true)
+            .await
+            .unwrap();
+        assert_eq!(fetched, body.len() as u64 - 7);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+
+        // Already complete: the 416 is not an error
+        let fetched = client
+            .download_to_file_with_resume(&url, &path, true, true)
+            .await
+            .unwrap();
+        assert_eq!(fetched, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
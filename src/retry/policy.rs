@@ -3,12 +3,17 @@ use tokio::time::sleep;
 
 #[derive(Clone)]
 pub struct RetryPolicy {
-    #[allow(dead_code)]
+    /// Attempts including the first; 0 retries forever like wget's `--tries=0`
     max_tries: u32,
-    #[allow(dead_code)]
     wait_retry: Duration,
 }
 
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3, 1)
+    }
+}
+
 impl RetryPolicy {
     pub fn new(max_tries: u32, wait_retry_secs: u64) -> Self {
         Self {
@@ -17,9 +22,19 @@ impl RetryPolicy {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn default() -> Self {
-        Self::new(3, 1)
+    /// Whether another attempt may follow attempt number `attempt`
+    pub fn allows_retry(&self, attempt: u32) -> bool {
+        self.max_tries == 0 || attempt < self.max_tries
+    }
+
+    /// Pause after attempt number `attempt`; grows linearly like `execute`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.wait_retry * attempt
+    }
+
+    /// Statuses worth retrying: the server may answer differently later
+    pub fn is_transient_status(status: reqwest::StatusCode) -> bool {
+        matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
     }
 
     #[allow(dead_code)]
@@ -0,0 +1,321 @@
+use crate::http::client::{DownloadError, ErrorKind, Fetched};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Journal of a batch run (`--session FILE`): what became of every URL and,
+/// for mirrors, the crawl frontier. Each change is appended as one line of
+/// JSON and flushed right away, so a crash loses at most the line being
+/// written. Running again with the same file replays it: finished URLs are
+/// skipped, interrupted and failed ones continued, and a mirror picks up
+/// its queue where it stopped. Clones share the file.
+#[derive(Debug, Clone)]
+pub struct Session {
+    inner: Arc<Mutex<Journal>>,
+}
+
+#[derive(Debug)]
+struct Journal {
+    path: PathBuf,
+    file: File,
+    urls: HashMap<String, UrlState>,
+    visited: HashSet<String>,
+    /// Every URL the crawler queued, in order
+    queued: Vec<String>,
+}
+
+/// What the journal knows about one URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlState {
+    Pending,
+    /// Started; `offset` bytes were on disk at the time
+    InProgress {
+        offset: u64,
+    },
+    /// Finished with `size` bytes on disk; `checksum` is the digest of
+    /// the file as it was written
+    Done {
+        size: u64,
+        checksum: String,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// One journal line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Record {
+    Pending {
+        url: String,
+    },
+    InProgress {
+        url: String,
+        offset: u64,
+    },
+    Done {
+        url: String,
+        size: u64,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        checksum: String,
+    },
+    Failed {
+        url: String,
+        error: String,
+    },
+    /// The crawler queued `url`
+    Queued {
+        url: String,
+    },
+    /// The crawler finished with `url` and queued its links
+    Visited {
+        url: String,
+    },
+}
+
+impl Session {
+    /// Opens `path`, replaying what an earlier run recorded there
+    pub fn open(path: &Path) -> Result<Self, DownloadError> {
+        let io_error = |e: std::io::Error| DownloadError {
+            kind: ErrorKind::Io,
+            message: format!("Session file {}: {}", path.display(), e),
+        };
+        let mut journal = Journal {
+            path: path.to_path_buf(),
+            file: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(io_error)?,
+            urls: HashMap::new(),
+            visited: HashSet::new(),
+            queued: Vec::new(),
+        };
+        let reader = BufReader::new(File::open(path).map_err(io_error)?);
+        for line in reader.lines() {
+            let line = line.map_err(io_error)?;
+            // A line cut short by a crash is the last one; skip it
+            if let Ok(record) = serde_json::from_str::<Record>(&line) {
+                journal.apply(record);
+            }
+        }
+        Ok(Self {
+            inner: Arc::new(Mutex::new(journal)),
+        })
+    }
+
+    pub fn state(&self, url: &str) -> Option<UrlState> {
+        self.inner.lock().unwrap().urls.get(url).cloned()
+    }
+
+    /// Whether an earlier run finished `url` and its file is still there,
+    /// with the size it had then
+    pub fn is_done(&self, url: &str, file_path: &Path) -> bool {
+        match self.state(url) {
+            Some(UrlState::Done { size, .. }) => {
+                std::fs::metadata(file_path).is_ok_and(|meta| meta.len() == size)
+            }
+            _ => false,
+        }
+    }
+
+    /// Records URLs about to be downloaded that the journal does not know yet
+    pub fn add_pending<'a>(&self, urls: impl IntoIterator<Item = &'a str>) {
+        let mut journal = self.inner.lock().unwrap();
+        for url in urls {
+            if !journal.urls.contains_key(url) {
+                journal.record(Record::Pending {
+                    url: url.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Runs `download` for `url` and records its start and outcome. Files an
+    /// earlier run started or failed on are continued, as with `-c`: the
+    /// closure is told whether to resume.
+    pub async fn track<F, Fut>(
+        &self,
+        url: &str,
+        file_path: &Path,
+        resume: bool,
+        download: F,
+    ) -> Result<Fetched, DownloadError>
+    where
+        F: FnOnce(bool) -> Fut,
+        Fut: Future<Output = Result<Fetched, DownloadError>>,
+    {
+        let interrupted = matches!(
+            self.state(url),
+            Some(UrlState::InProgress { .. } | UrlState::Failed { .. })
+        );
+        let offset = std::fs::metadata(file_path).map_or(0, |meta| meta.len());
+        self.write(Record::InProgress {
+            url: url.to_string(),
+            offset: if resume || interrupted { offset } else { 0 },
+        });
+
+        let outcome = download(resume || interrupted).await;
+        let record = match &outcome {
+            // The digest was computed as the file was written
+            Ok(fetched) => Record::Done {
+                url: url.to_string(),
+                size: std::fs::metadata(file_path).map_or(0, |meta| meta.len()),
+                checksum: fetched
+                    .digest
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+            },
+            Err(e) => Record::Failed {
+                url: url.to_string(),
+                error: e.message.clone(),
+            },
+        };
+        self.write(record);
+        outcome
+    }
+
+    /// The crawl frontier of an earlier run: the pages it finished and the
+    /// queue still to do, or `None` when no crawl was recorded
+    pub fn frontier(&self) -> Option<(HashSet<String>, VecDeque<String>)> {
+        let journal = self.inner.lock().unwrap();
+        if journal.queued.is_empty() {
+            return None;
+        }
+        let mut seen = journal.visited.clone();
+        let queue = journal
+            .queued
+            .iter()
+            .filter(|url| seen.insert(url.to_string()))
+            .cloned()
+            .collect();
+        Some((journal.visited.clone(), queue))
+    }
+
+    pub fn record_queued(&self, url: &str) {
+        self.write(Record::Queued {
+            url: url.to_string(),
+        });
+    }
+
+    pub fn record_visited(&self, url: &str) {
+        self.write(Record::Visited {
+            url: url.to_string(),
+        });
+    }
+
+    fn write(&self, record: Record) {
+        self.inner.lock().unwrap().record(record);
+    }
+}
+
+impl Journal {
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Pending { url } => {
+                self.urls.entry(url).or_insert(UrlState::Pending);
+            }
+            Record::InProgress { url, offset } => {
+                self.urls.insert(url, UrlState::InProgress { offset });
+            }
+            Record::Done {
+                url,
+                size,
+                checksum,
+            } => {
+                self.urls.insert(url, UrlState::Done { size, checksum });
+            }
+            Record::Failed { url, error } => {
+                self.urls.insert(url, UrlState::Failed { error });
+            }
+            Record::Queued { url } => self.queued.push(url),
+            Record::Visited { url } => {
+                self.visited.insert(url);
+            }
+        }
+    }
+
+    /// Appends `record` to the file and applies it. A journal that cannot
+    /// be written must not stop the downloads, so failures only warn.
+    fn record(&mut self, record: Record) {
+        let line = serde_json::to_string(&record).expect("journal records serialise");
+        if let Err(e) = writeln!(self.file, "{}", line) {
+            eprintln!("Cannot write session file {}: {}", self.path.display(), e);
+        }
+        self.apply(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Checksum;
+
+    #[tokio::test]
+    async fn test_rerun_skips_done_and_resumes_interrupted_urls() {
+        let dir = std::env::temp_dir().join(format!("wget-session-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let journal = dir.join("run.session");
+        let (done, cut) = (dir.join("done.txt"), dir.join("cut.txt"));
+        std::fs::write(&done, "abc").unwrap();
+        std::fs::write(&cut, "ab").unwrap();
+
+        let session = Session::open(&journal).unwrap();
+        session.add_pending(["http://a/done.txt", "http://a/cut.txt", "http://a/new.txt"]);
+        let sha256 = "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let digest = Checksum::parse(sha256).unwrap();
+        let fetched = session
+            .track("http://a/done.txt", &done, false, |resume| async move {
+                assert!(!resume);
+                Ok(Fetched {
+                    bytes: 3,
+                    digest: Some(digest),
+                })
+            })
+            .await;
+        assert_eq!(fetched.unwrap().bytes, 3);
+        // The process dies while this one is running
+        session.write(Record::InProgress {
+            url: "http://a/cut.txt".into(),
+            offset: 2,
+        });
+        session.record_queued("http://a/");
+        session.record_queued("http://a/x.html");
+        session.record_visited("http://a/");
+        drop(session);
+
+        let session = Session::open(&journal).unwrap();
+        assert!(session.is_done("http://a/done.txt", &done));
+        assert_eq!(
+            session.state("http://a/done.txt"),
+            Some(UrlState::Done {
+                size: 3,
+                checksum: sha256.into()
+            })
+        );
+        // A file changed since is not taken for done
+        std::fs::write(&done, "abcd").unwrap();
+        assert!(!session.is_done("http://a/done.txt", &done));
+        assert_eq!(session.state("http://a/new.txt"), Some(UrlState::Pending));
+        let resumed = session
+            .track("http://a/cut.txt", &cut, false, |resume| async move {
+                Ok(Fetched {
+                    bytes: u64::from(resume),
+                    digest: None,
+                })
+            })
+            .await;
+        assert_eq!(resumed.unwrap().bytes, 1);
+
+        let (visited, queue) = session.frontier().unwrap();
+        assert!(visited.contains("http://a/"));
+        assert_eq!(queue, ["http://a/x.html"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
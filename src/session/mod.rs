@@ -0,0 +1,3 @@
+pub mod journal;
+
+pub use journal::Session;
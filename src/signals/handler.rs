@@ -0,0 +1,129 @@
+use crate::background::daemon;
+use crate::control::Jobs;
+use crate::control::jobs::{JobState, JobStatus};
+use crate::utils::size::format_size;
+use std::io;
+use tokio::signal::unix::{SignalKind, signal};
+
+/// What SIGHUP does to a run
+#[derive(Debug, Clone, Copy)]
+pub enum Hangup {
+    /// Output already goes to a log (`-B`, `-o`, `-a`)
+    Ignore,
+    /// Move the messages to `wget-log` (or the next free name) so the run
+    /// survives its terminal, like GNU wget. `-O -` keeps its stdout.
+    Redirect { keep_stdout: bool },
+}
+
+/// Installs the handlers for the rest of the run:
+///
+/// * SIGINT and SIGTERM interrupt `jobs`: nothing new starts and running
+///   transfers stop at their next chunk, keeping their partial files. A
+///   second one aborts `jobs`, on which `cli::run` stops waiting, removes
+///   the `-B` PID file and control socket, and exits.
+/// * SIGHUP applies `hangup`.
+/// * SIGUSR1 writes the progress of every download to the log.
+pub fn listen(jobs: Jobs, mut hangup: Hangup) -> io::Result<()> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hang_up = signal(SignalKind::hangup())?;
+    let mut user1 = signal(SignalKind::user_defined1())?;
+
+    tokio::spawn(async move {
+        loop {
+            let signal = tokio::select! {
+                Some(()) = interrupt.recv() => libc::SIGINT,
+                Some(()) = terminate.recv() => libc::SIGTERM,
+                Some(()) = hang_up.recv() => {
+                    hangup = redirect(hangup);
+                    continue;
+                }
+                Some(()) = user1.recv() => {
+                    eprint!("{}", progress_report(&jobs.status()));
+                    continue;
+                }
+                else => break,
+            };
+            if !jobs.interrupt(signal) {
+                jobs.abort();
+                continue;
+            }
+            eprintln!(
+                "\n{} received, finishing up. Send it again to quit at once.",
+                signal_name(signal)
+            );
+        }
+    });
+    Ok(())
+}
+
+fn redirect(hangup: Hangup) -> Hangup {
+    let Hangup::Redirect { keep_stdout } = hangup else {
+        return hangup;
+    };
+    let log = daemon::default_log_path();
+    eprintln!(
+        "\nSIGHUP received, redirecting output to '{}'.",
+        log.display()
+    );
+    if let Err(e) = daemon::redirect_output(&log, false, keep_stdout) {
+        eprintln!("Cannot write to {}: {}", log.display(), e);
+        return hangup;
+    }
+    Hangup::Ignore
+}
+
+pub fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGUSR1 => "SIGUSR1",
+        _ => "Signal",
+    }
+}
+
+/// One line per download: id, state, bytes so far, average speed and URL
+pub fn progress_report(jobs: &[JobStatus]) -> String {
+    let mut report = format!(
+        "Progress at {}:\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    if jobs.is_empty() {
+        report.push_str("  no downloads yet\n");
+    }
+    for job in jobs {
+        let speed = match job.state {
+            JobState::Running => format!(" at {}/s", format_size(job.speed)),
+            _ => String::new(),
+        };
+        report.push_str(&format!(
+            "  [{}] {:<11} {}{}  {}\n",
+            job.id,
+            job.state,
+            format_size(job.bytes),
+            speed,
+            job.url
+        ));
+    }
+    report
+}
+
+/// What became of the downloads of an interrupted run
+pub fn partial_summary(signal: i32, jobs: &[JobStatus]) -> String {
+    let count = |state: JobState| jobs.iter().filter(|job| job.state == state).count();
+    let bytes: u64 = jobs.iter().map(|job| job.bytes).sum();
+    let mut summary = format!("\nInterrupted by {}:\n", signal_name(signal));
+    summary.push_str(&format!("  Done: {}\n", count(JobState::Done)));
+    summary.push_str(&format!(
+        "  Interrupted: {}\n",
+        count(JobState::Interrupted) + count(JobState::Running)
+    ));
+    summary.push_str(&format!("  Not started: {}\n", count(JobState::Queued)));
+    summary.push_str(&format!("  Failed: {}\n", count(JobState::Failed)));
+    summary.push_str(&format!("  Total bytes: {}\n", format_size(bytes)));
+    summary.push_str(
+        "Partial files were kept; run again with -c (or the same --session) to continue.\n",
+    );
+    summary
+}
@@ -0,0 +1,45 @@
+use crate::http::HttpClient;
+use crate::http::client::ErrorKind;
+use crate::spider::SpiderReport;
+use futures_util::StreamExt;
+
+/// Checks a list of URLs without saving anything (`--spider`), running up
+/// to `max_concurrent` probes at once. Results are reported in input order.
+pub async fn check_urls(
+    client: &HttpClient,
+    urls: &[String],
+    max_concurrent: usize,
+    quiet: bool,
+) -> SpiderReport {
+    let outcomes: Vec<_> = futures_util::stream::iter(urls)
+        .map(|url| async move { (url, client.probe(url).await) })
+        .buffered(max_concurrent.max(1))
+        .collect()
+        .await;
+
+    let mut report = SpiderReport::new();
+    for (url, outcome) in outcomes {
+        match outcome {
+            Ok(response) => {
+                if !quiet {
+                    println!(
+                        "{} - {} {}: remote file exists",
+                        url,
+                        response.status().as_u16(),
+                        response.status().canonical_reason().unwrap_or("")
+                    );
+                }
+                report.record_ok();
+            }
+            // Not checked before the run was stopped
+            Err(e) if e.kind == ErrorKind::Interrupted => {}
+            Err(e) => {
+                if !quiet {
+                    println!("{} - broken: {}", url, e.message);
+                }
+                report.record_broken(url, &e.message, Vec::new());
+            }
+        }
+    }
+    report
+}
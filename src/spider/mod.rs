@@ -0,0 +1,5 @@
+pub mod checker;
+pub mod report;
+
+pub use checker::check_urls;
+pub use report::SpiderReport;
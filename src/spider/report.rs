@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+/// A URL that could not be retrieved during a spider run
+#[derive(Debug, Clone)]
+pub struct BrokenLink {
+    pub url: String,
+    pub error: String,
+    /// Pages that linked to `url` (empty for command line and `-i` URLs)
+    pub referrers: Vec<String>,
+}
+
+/// Collects the outcome of every check in `--spider` mode
+#[derive(Debug, Clone, Default)]
+pub struct SpiderReport {
+    checked: usize,
+    broken: Vec<BrokenLink>,
+}
+
+impl SpiderReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_ok(&mut self) {
+        self.checked += 1;
+    }
+
+    pub fn record_broken(&mut self, url: &str, error: &str, referrers: Vec<String>) {
+        self.checked += 1;
+        self.broken.push(BrokenLink {
+            url: url.to_string(),
+            error: error.to_string(),
+            referrers,
+        });
+    }
+
+    /// Fills in the pages linking to each broken URL from a crawl's
+    /// `url -> referring pages` map
+    pub fn attach_referrers(&mut self, referrers: &HashMap<String, Vec<String>>) {
+        for link in &mut self.broken {
+            if let Some(pages) = referrers.get(&link.url) {
+                link.referrers = pages.clone();
+            }
+        }
+    }
+
+    pub fn broken(&self) -> &[BrokenLink] {
+        &self.broken
+    }
+
+    /// Formats the end-of-run broken links report, wget style
+    pub fn render(&self) -> String {
+        let mut out = format!("Checked {} URL(s).\n", self.checked);
+        if self.broken.is_empty() {
+            out.push_str("Found no broken links.\n");
+            return out;
+        }
+
+        out.push_str(&format!("Found {} broken link(s).\n", self.broken.len()));
+        for link in &self.broken {
+            out.push_str(&format!("\n{}\n    {}\n", link.url, link.error));
+            if !link.referrers.is_empty() {
+                out.push_str("    referenced by:\n");
+                for referrer in &link.referrers {
+                    out.push_str(&format!("        {}\n", referrer));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_lists_referrers() {
+        let mut report = SpiderReport::new();
+        report.record_ok();
+        report.record_broken(
+            "http://example.com/gone.html",
+            "HTTP error: 404 Not Found",
+            Vec::new(),
+        );
+
+        let mut referrers = HashMap::new();
+        referrers.insert(
+            "http://example.com/gone.html".to_string(),
+            vec!["http://example.com/".to_string()],
+        );
+        report.attach_referrers(&referrers);
+
+        let text = report.render();
+        assert!(text.starts_with("Checked 2 URL(s).\nFound 1 broken link(s).\n"));
+        assert!(text.contains("    referenced by:\n        http://example.com/\n"));
+    }
+}
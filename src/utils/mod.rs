@@ -1 +1,2 @@
-pub mod url;
\ No newline at end of file
+pub mod size;
+pub mod url;
@@ -0,0 +1,44 @@
+/// Parses an amount of bytes such as `500k`, `1.5M` or `2GB`.
+///
+/// A bare `k`, `M` or `G` counts in powers of 1024 like wget; `KiB`, `MiB`
+/// and `GiB` (IEC) do the same, while `kB`, `MB` and `GB` (SI) count in
+/// powers of 1000.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size {:?} (e.g., 400k, 1.5M, 2GB)", size.trim());
+    let text = size.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "k" | "K" | "KiB" | "kiB" => 1 << 10,
+        "m" | "M" | "MiB" => 1 << 20,
+        "g" | "G" | "GiB" => 1 << 30,
+        "kB" | "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        _ => return Err(invalid()),
+    };
+    if !number.is_finite() {
+        return Err(invalid());
+    }
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// `1.50 MB` style size for messages, in powers of 1024
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", value, UNITS[unit])
+}
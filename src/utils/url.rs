@@ -4,26 +4,39 @@ pub fn extract_filename(url: &str) -> String {
     // Parse URL and extract filename from path
     if let Ok(parsed) = url::Url::parse(url) {
         let path = parsed.path();
-        if let Some(filename) = Path::new(path).file_name() {
-            if let Some(filename_str) = filename.to_str() {
-                if !filename_str.is_empty() {
-                    return filename_str.to_string();
-                }
-            }
-        }
-    }
-
-    // Fallback: use last segment of URL or default name
-    if let Some(filename) = url.split('/').last() {
-        if !filename.is_empty() && filename.contains('.') {
-            return filename.to_string();
+        if let Some(filename) = Path::new(path).file_name()
+            && let Some(filename_str) = filename.to_str()
+            && !filename_str.is_empty()
+        {
+            return filename_str.to_string();
         }
+    } else if let Some(filename) = url.split('/').next_back()
+        && !filename.is_empty()
+        && filename.contains('.')
+    {
+        // Fallback for unparseable URLs: use last segment
+        return filename.to_string();
     }
 
     // Default filename
     "index.html".to_string()
 }
 
+/// `host:port` a URL connects to, used to cap connections per server.
+/// Unparseable URLs share one key.
+pub fn host_key(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|parsed| {
+            let host = parsed.host_str()?.to_ascii_lowercase();
+            Some(match parsed.port_or_known_default() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        })
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -37,5 +50,19 @@ mod tests {
         );
         assert_eq!(extract_filename("https://example.com/"), "index.html");
         assert_eq!(extract_filename("https://example.com"), "index.html");
+        assert_eq!(host_key("https://Example.com/a"), "example.com:443");
+        assert_eq!(host_key("http://example.com:8080/b"), "example.com:8080");
+    }
+
+    #[test]
+    fn test_extract_filename_fallback_only_for_unparseable_urls() {
+        // A bare host is not a file name, even with a dot in it
+        assert_eq!(extract_filename("https://example.com"), "index.html");
+        assert_eq!(extract_filename("http://files.example.org/"), "index.html");
+        assert_eq!(
+            extract_filename("downloads/archive.tar.gz"),
+            "archive.tar.gz"
+        );
+        assert_eq!(extract_filename("downloads/latest"), "index.html");
     }
 }